@@ -1,4 +1,4 @@
-use std::{env, fs, io, path::Path, process::Command};
+use std::{fs, io, process::Command};
 
 use gtk::gio;
 
@@ -7,7 +7,6 @@ const RESOURCE_DIR: &str = "./resources";
 
 fn main() {
     println!("cargo:rerun-if-changed=build.rs");
-    return;
 
     compile_blueprints().expect("Couldn't compile blueprints");
 
@@ -17,26 +16,12 @@ fn main() {
         "transcrible.gresource",
     );
 
-    let out_dir = env::var("OUT_DIR").unwrap();
-    let schemas_dir = format!("{out_dir}/schemas");
-    let schemas_dir = Path::new(&schemas_dir);
-    fs::create_dir_all(&schemas_dir).unwrap();
-
-    fs::copy(
-        "resources/ninja.seppli.Transcrible.gschema.xml",
-        &schemas_dir.join("ninja.seppli.Transcrible.gschema.xml"),
-    )
-    .unwrap();
-
-    Command::new("glib-compile-schemas")
-        .arg(format!("{out_dir}/schemas"))
+    // the schema is installed by `make install`, here it is only checked for errors
+    let status = Command::new("glib-compile-schemas")
+        .args(["--strict", "--dry-run", RESOURCE_DIR])
         .status()
-        .unwrap();
-
-    println!(
-        "cargo:rustc-env=GSETTINGS_SCHEMA_DIR={}",
-        schemas_dir.to_str().unwrap()
-    );
+        .expect("Couldn't run glib-compile-schemas");
+    assert!(status.success(), "The GSettings schema is invalid");
     println!("cargo:rerun-if-changed=resources/ninja.seppli.Transcrible.gschema.xml");
 }
 
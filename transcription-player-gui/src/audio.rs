@@ -1,19 +1,39 @@
 use std::{
     cmp,
     collections::VecDeque,
-    fs::File,
-    io::{self, BufReader},
+    io,
     path::Path,
-    time::Duration,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        mpsc, Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
 };
 
-use rodio::{
-    decoder::DecoderError, Decoder, OutputStream, OutputStreamHandle, PlayError, Sink, Source,
-    StreamError,
-};
+use rodio::{OutputStream, OutputStreamHandle, PlayError, Sink, Source, StreamError};
 use rubberband_rs::{AudioBuffer, RubberBand, RubberBandOption};
+use symphonia::core::errors::Error as SymphoniaError;
 use thiserror::Error;
 
+use self::{
+    channels::{ChannelRouter, ChannelRouting, RoutingControl},
+    denoise::{DenoiseControl, DenoiseSource, NoiseProfile},
+    dynamics::{DynamicsControl, DynamicsSettings, DynamicsSource},
+    equalizer::{EqControl, EqSettings, EqSource},
+    memory::{DecodedAudio, MemorySource},
+    symphonia_decoder::SymphoniaDecoder,
+};
+
+pub mod channels;
+pub mod denoise;
+pub mod dynamics;
+pub mod equalizer;
+pub mod memory;
+pub mod peak_cache;
+pub mod peaks;
+pub mod symphonia_decoder;
+
 #[derive(Error, Debug)]
 pub enum AudioError {
     #[error("Couldn't create output stream for audio player")]
@@ -21,6 +41,8 @@ pub enum AudioError {
         #[from]
         source: StreamError,
     },
+    #[error("Couldn't start the audio output thread")]
+    OutputThreadError { source: io::Error },
     #[error("Couldn't create sink for audio player")]
     CreateSinkError {
         #[from]
@@ -28,54 +50,347 @@ pub enum AudioError {
     },
     #[error("Couldn't load audio file \"{path}\"")]
     LoadError { path: String, source: io::Error },
+    #[error("The format of audio file \"{path}\" isn't supported")]
+    FormatError {
+        path: String,
+        source: SymphoniaError,
+    },
+    #[error("Audio file \"{path}\" has no audio track which can be played")]
+    NoAudioTrackError { path: String },
+    #[error("Audio file \"{path}\" uses the {codec} codec, for which there is no decoder")]
+    MissingCodecError { path: String, codec: String },
     #[error("Couldn't decode audio file \"{path}\"")]
-    DecodeError { path: String, source: DecoderError },
+    CodecError {
+        path: String,
+        source: SymphoniaError,
+    },
+    #[error("Couldn't seek in audio file \"{path}\"")]
+    SeekError {
+        path: String,
+        source: SymphoniaError,
+    },
+    #[error("Couldn't create the Opus decoder for audio file \"{path}\"")]
+    OpusError { path: String, source: opus::Error },
+    #[error("The region of the noise profile has to be at least {min_millis} ms long")]
+    NoiseRegionTooShortError { min_millis: u64 },
 }
 
-pub struct AudioPlayer {
-    // when _stream_handle and _stream drop, the audio stops playing
-    _stream_handle: OutputStreamHandle,
-    _stream: OutputStream,
+/// Keeps the [`OutputStream`] alive on its own thread. The stream can't be sent to other
+/// threads, but the [`AudioPlayer`] has to live on the thread of its worker.
+///
+/// When the guard drops, the thread ends and the stream stops playing.
+struct OutputStreamGuard {
+    _stop_sender: mpsc::Sender<()>,
+}
+
+impl OutputStreamGuard {
+    fn spawn() -> Result<(Self, OutputStreamHandle), AudioError> {
+        let (handle_sender, handle_receiver) = mpsc::channel();
+        let (stop_sender, stop_receiver) = mpsc::channel::<()>();
+        thread::Builder::new()
+            .name("audio-output".to_string())
+            .spawn(move || match OutputStream::try_default() {
+                Ok((stream, stream_handle)) => {
+                    let _ = handle_sender.send(Ok(stream_handle));
+                    // blocks until the guard and with it the sender is dropped
+                    let _ = stop_receiver.recv();
+                    drop(stream);
+                }
+                Err(err) => {
+                    let _ = handle_sender.send(Err(err));
+                }
+            })
+            .map_err(|source| AudioError::OutputThreadError { source })?;
+        let stream_handle = match handle_receiver.recv() {
+            Ok(stream_handle) => stream_handle?,
+            Err(_) => {
+                let source = io::Error::new(
+                    io::ErrorKind::Other,
+                    "the audio output thread ended unexpectedly",
+                );
+                return Err(AudioError::OutputThreadError { source });
+            }
+        };
+        Ok((
+            OutputStreamGuard {
+                _stop_sender: stop_sender,
+            },
+            stream_handle,
+        ))
+    }
+}
+
+/// How far the playback jumps back when it is resumed after a pause, so that the last words
+/// before the pause are heard again.
+#[derive(Clone, Copy, Debug)]
+pub struct ResumeRewind {
+    pub rewind: Duration,
+    /// if true, `pause_factor` times the duration of the pause is added to the rewind
+    pub scale_with_pause: bool,
+    pub pause_factor: f64,
+    /// the upper limit of the rewind
+    pub max_rewind: Duration,
+}
+
+impl ResumeRewind {
+    /// The duration to rewind after the playback was paused for `pause`.
+    pub fn rewind_after(&self, pause: Duration) -> Duration {
+        if self.scale_with_pause {
+            cmp::min(
+                self.rewind + pause.mul_f64(self.pause_factor),
+                self.max_rewind,
+            )
+        } else {
+            cmp::min(self.rewind, self.max_rewind)
+        }
+    }
+}
+
+impl Default for ResumeRewind {
+    fn default() -> Self {
+        ResumeRewind {
+            rewind: Duration::from_millis(1500),
+            scale_with_pause: false,
+            pause_factor: 0.1,
+            max_rewind: Duration::from_secs(5),
+        }
+    }
+}
+
+/// A region of the audio file which is played repeatedly.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LoopRegion {
+    pub start: Duration,
+    pub end: Duration,
+    /// the silence between two repetitions
+    pub gap: Duration,
+    /// how many times the region is repeated, `None` repeats it forever
+    pub max_repeats: Option<u32>,
+}
+
+/// The connection to the output device
+struct Output {
+    // when _stream_guard drops, the audio stops playing
+    _stream_guard: OutputStreamGuard,
+    stream_handle: OutputStreamHandle,
     sink: Sink,
 }
 
-impl AudioPlayer {
-    pub fn new() -> Result<Self, AudioError> {
-        let (stream, stream_handle) =
-            OutputStream::try_default().map_err(|err| AudioError::from(err))?;
-
-        let sink = Sink::try_new(&stream_handle).map_err(|err| AudioError::from(err))?;
-        Ok(AudioPlayer {
-            _stream_handle: stream_handle,
-            _stream: stream,
+impl Output {
+    fn connect() -> Result<Self, AudioError> {
+        let (stream_guard, stream_handle) = OutputStreamGuard::spawn()?;
+        let sink = Sink::try_new(&stream_handle)?;
+        Ok(Output {
+            _stream_guard: stream_guard,
+            stream_handle,
             sink,
         })
     }
+}
+
+/// Plays one audio file at a time. Without an output device, files can still be loaded, they
+/// start playing once [`AudioPlayer::connect_output`] succeeds.
+pub struct AudioPlayer {
+    output: Option<Output>,
+    /// the source of the loaded file, while there is no output to play it
+    pending_source: Option<DecodedSource>,
+    /// the control of the source which is currently playing
+    control: Option<PlaybackControl>,
+    /// shared with the denoiser of every loaded file, so that it stays on or off
+    denoise: Arc<DenoiseControl>,
+    /// shared with the compressor of every loaded file
+    dynamics: Arc<DynamicsControl>,
+    /// shared with the equalizer of every loaded file
+    eq: Arc<EqControl>,
+    /// shared with the channel router of every loaded file
+    routing: Arc<RoutingControl>,
+    resume_rewind: ResumeRewind,
+    /// when the playback was paused, `None` if it is playing
+    paused_at: Option<Instant>,
+    loop_start: Option<Duration>,
+    loop_end: Option<Duration>,
+    loop_gap: Duration,
+    loop_max_repeats: Option<u32>,
+}
 
-    pub fn load<P: AsRef<Path>>(&mut self, path: &P) -> Result<(), AudioError> {
-        let file = File::open(path).map_err(|err| AudioError::LoadError {
-            path: path.as_ref().display().to_string(),
-            source: err,
-        })?;
-        let buffered_reader = BufReader::new(file);
-        let source = Decoder::new(buffered_reader).map_err(|err| AudioError::DecodeError {
-            path: path.as_ref().display().to_string(),
-            source: err,
-        })?;
-        let rubber_band_options: RubberBandOption =
-            RubberBandOption::PROCESS_REAL_TIME | RubberBandOption::ENGINE_FINER;
-        let source = RubberBandSource::new(source.convert_samples(), rubber_band_options);
-
-        self.sink.append(source);
-        self.sink.sleep_until_end();
+impl AudioPlayer {
+    /// Creates a player without output, call [`AudioPlayer::connect_output`] to play something.
+    pub fn new() -> Self {
+        AudioPlayer {
+            output: None,
+            pending_source: None,
+            control: None,
+            denoise: Arc::default(),
+            dynamics: Arc::default(),
+            eq: Arc::default(),
+            routing: Arc::default(),
+            resume_rewind: ResumeRewind::default(),
+            paused_at: None,
+            loop_start: None,
+            loop_end: None,
+            loop_gap: Duration::ZERO,
+            loop_max_repeats: None,
+        }
+    }
+
+    pub fn has_output(&self) -> bool {
+        self.output.is_some()
+    }
+
+    /// Connects to the default output device, if the player isn't connected yet. A file which
+    /// was loaded without output is appended paused.
+    pub fn connect_output(&mut self) -> Result<(), AudioError> {
+        if self.output.is_some() {
+            return Ok(());
+        }
+        let output = Output::connect()?;
+        if let Some(source) = self.pending_source.take() {
+            output.sink.pause();
+            output.sink.append(source);
+            self.paused_at = Some(Instant::now());
+        }
+        self.output = Some(output);
         Ok(())
     }
 
+    /// Loads the audio file at `path` and starts playing it. The previously loaded file stops.
+    ///
+    /// This returns right away, the audio is decoded and played on the audio thread. Without
+    /// output, the file waits until the player is connected.
+    /// The returned [`PlaybackControl`] changes the speed and pitch of this file while it plays.
+    /// The speed, pitch and formant scale of the previously loaded file are kept.
+    ///
+    /// If the decoder can't tell the duration up front, like for some mp3 files, it is unknown
+    /// until it is set with [`PlaybackControl::set_duration`].
+    pub fn load<P: AsRef<Path>>(&mut self, path: &P) -> Result<PlaybackControl, AudioError> {
+        let source = FileSource::open(path)?;
+        let control = self.new_control();
+        if let Some(duration) = source.total_duration() {
+            control.state.set_duration(duration);
+        }
+        self.play_source(source, control)
+    }
+
+    /// Like [`AudioPlayer::load`], but plays audio which was decoded into memory with
+    /// [`DecodedAudio::decode`]. Seeking in it is instant and exact.
+    pub fn load_decoded(
+        &mut self,
+        audio: Arc<DecodedAudio>,
+    ) -> Result<PlaybackControl, AudioError> {
+        let control = self.new_control();
+        control.state.set_duration(audio.duration());
+        self.play_source(MemorySource::new(audio), control)
+    }
+
+    /// Stops the loaded file, for example while the next one is decoded
+    pub fn unload(&mut self) {
+        if let Some(output) = &self.output {
+            output.sink.stop();
+        }
+        self.pending_source = None;
+        self.paused_at = None;
+    }
+
+    /// Creates the control of a new file, with the parameters of the previous one
+    fn new_control(&self) -> PlaybackControl {
+        let state = match &self.control {
+            Some(control) => control.state.copy_parameters(),
+            None => SourceState::default(),
+        };
+        PlaybackControl {
+            state: Arc::new(state),
+        }
+    }
+
+    fn play_source<S: SeekableSource + Send + 'static>(
+        &mut self,
+        source: S,
+        control: PlaybackControl,
+    ) -> Result<PlaybackControl, AudioError> {
+        let rubber_band_options: RubberBandOption = RubberBandOption::PROCESS_REAL_TIME
+            | RubberBandOption::ENGINE_FINER
+            | RubberBandOption::FORMANT_PRESERVED;
+        // the noise profile of the previous file doesn't fit this one
+        self.denoise.set_profile(None);
+        let source = ChannelRouter::new(source, self.routing.clone());
+        let source = DenoiseSource::new(source, self.denoise.clone());
+        let source = EqSource::new(source, self.eq.clone());
+        let source = RubberBandSource::new(source, rubber_band_options, control.state.clone());
+        // the compressor comes after the time stretching, so that its attack and release
+        // don't depend on the speed
+        let source = DynamicsSource::new(source, self.dynamics.clone());
+
+        self.control = Some(control.clone());
+        self.paused_at = None;
+        self.loop_start = None;
+        self.loop_end = None;
+        match &mut self.output {
+            Some(output) => {
+                // dropping the old sink stops the previous file
+                output.sink = Sink::try_new(&output.stream_handle)?;
+                output.sink.append(source);
+            }
+            None => self.pending_source = Some(Box::new(source)),
+        }
+        Ok(control)
+    }
+
+    /// Switches the denoiser on or off, without interrupting the playback
+    pub fn set_denoise_enabled(&mut self, enabled: bool) {
+        self.denoise.set_enabled(enabled);
+    }
+
+    /// Sets the noise profile of the loaded file, which the denoiser removes
+    pub fn set_noise_profile(&mut self, profile: NoiseProfile) {
+        self.denoise.set_profile(Some(Arc::new(profile)));
+    }
+
+    /// Changes the compressor, the limiter and the automatic gain control while the audio plays
+    pub fn set_dynamics(&mut self, settings: DynamicsSettings) {
+        self.dynamics.set_settings(settings);
+    }
+
+    /// Changes the filters of the equalizer while the audio plays
+    pub fn set_eq(&mut self, settings: EqSettings) {
+        self.eq.set_settings(settings);
+    }
+
+    /// Changes which channels are heard on which side while the audio plays
+    pub fn set_channel_routing(&mut self, routing: ChannelRouting) {
+        self.routing.set_routing(routing);
+    }
+
+    pub fn channel_routing(&self) -> ChannelRouting {
+        self.routing.routing()
+    }
+
+    /// Resumes the playback. If it was paused, it jumps back according to the
+    /// [`ResumeRewind`] settings first.
     pub fn play(&mut self) {
-        self.sink.play();
+        if self.output.is_none() {
+            return;
+        }
+        if let Some(paused_at) = self.paused_at.take() {
+            let rewind = self.resume_rewind.rewind_after(paused_at.elapsed());
+            if let Some(position) = self.position() {
+                self.seek(position.saturating_sub(rewind));
+            }
+        }
+        if let Some(output) = &self.output {
+            output.sink.play();
+        }
     }
     pub fn pause(&mut self) {
-        self.sink.pause();
+        if !self.is_paused() {
+            self.paused_at = Some(Instant::now());
+        }
+        if let Some(output) = &self.output {
+            output.sink.pause();
+        }
+    }
+
+    pub fn set_resume_rewind(&mut self, resume_rewind: ResumeRewind) {
+        self.resume_rewind = resume_rewind;
     }
 
     pub fn toggle_play_status(&mut self) {
@@ -86,54 +401,551 @@ impl AudioPlayer {
         }
     }
 
+    /// Moves the playback to `time`, measured in the time of the audio file.
+    ///
+    /// The seek is executed by the audio thread before it plays the next sample. Everything
+    /// which was already stretched by rubberband is discarded.
     pub fn seek(&mut self, time: Duration) {
-        todo!()
+        if let Some(control) = &self.control {
+            control.state.request_seek(time);
+        }
     }
 
+    /// Moves the playback by `offset` milliseconds, negative values move it backwards. The
+    /// target is clamped to the start and the end of the file.
+    pub fn skip_by(&mut self, offset: i64) {
+        let position = match self.position() {
+            Some(position) => position,
+            None => return,
+        };
+        let offset_duration = Duration::from_millis(offset.unsigned_abs());
+        let mut target = if offset < 0 {
+            position.saturating_sub(offset_duration)
+        } else {
+            position.saturating_add(offset_duration)
+        };
+        if let Some(duration) = self.duration() {
+            target = cmp::min(target, duration);
+        }
+        self.seek(target);
+    }
+
+    /// Whether nothing is playing, which is also the case without output
     pub fn is_paused(&self) -> bool {
-        self.sink.is_paused()
+        self.output
+            .as_ref()
+            .map_or(true, |output| output.sink.is_paused())
+    }
+
+    /// The position of the playback in the time of the audio file, not in the stretched time
+    /// which is actually played. Returns `None` if no file is loaded.
+    pub fn position(&self) -> Option<Duration> {
+        self.control
+            .as_ref()
+            .map(|control| control.state.position())
+    }
+
+    /// The duration of the loaded file. Returns `None` if no file is loaded or the duration
+    /// is still being determined.
+    pub fn duration(&self) -> Option<Duration> {
+        self.control
+            .as_ref()
+            .and_then(|control| control.state.duration())
+    }
+
+    /// Sets the start of the loop (point A) to the current position.
+    pub fn set_loop_start(&mut self) {
+        self.loop_start = self.position();
+        self.update_loop_region();
+    }
+
+    /// Sets the end of the loop (point B) to the current position.
+    pub fn set_loop_end(&mut self) {
+        self.loop_end = self.position();
+        self.update_loop_region();
+    }
+
+    /// Sets both points of the loop, like when a saved loop is restored.
+    pub fn set_loop_points(&mut self, start: Duration, end: Duration) {
+        self.loop_start = Some(start);
+        self.loop_end = Some(end);
+        self.update_loop_region();
+    }
+
+    pub fn clear_loop(&mut self) {
+        self.loop_start = None;
+        self.loop_end = None;
+        self.update_loop_region();
+    }
+
+    pub fn set_loop_options(&mut self, gap: Duration, max_repeats: Option<u32>) {
+        self.loop_gap = gap;
+        self.loop_max_repeats = max_repeats;
+        self.update_loop_region();
+    }
+
+    /// The region which is looped. It is `None` until both points are set.
+    pub fn loop_region(&self) -> Option<LoopRegion> {
+        match (self.loop_start, self.loop_end) {
+            (Some(a), Some(b)) if a != b => Some(LoopRegion {
+                start: cmp::min(a, b),
+                end: cmp::max(a, b),
+                gap: self.loop_gap,
+                max_repeats: self.loop_max_repeats,
+            }),
+            _ => None,
+        }
+    }
+
+    fn update_loop_region(&self) {
+        if let Some(control) = &self.control {
+            control.state.set_loop_region(self.loop_region());
+        }
+    }
+}
+
+/// A container or codec which the decoder can play
+pub struct AudioFormat {
+    pub name: &'static str,
+    /// the file extensions, without the dot
+    pub extensions: &'static [&'static str],
+    pub mime_types: &'static [&'static str],
+}
+
+/// The formats the decoder supports. A format which is added to the decoder has to be added
+/// here too, so that its files are shown in the open dialog.
+pub static SUPPORTED_FORMATS: [AudioFormat; 6] = [
+    AudioFormat {
+        name: "WAV",
+        extensions: &["wav", "wave"],
+        mime_types: &["audio/wav", "audio/x-wav", "audio/vnd.wave"],
+    },
+    AudioFormat {
+        name: "MP3",
+        extensions: &["mp3"],
+        mime_types: &["audio/mpeg", "audio/mp3"],
+    },
+    AudioFormat {
+        name: "FLAC",
+        extensions: &["flac"],
+        mime_types: &["audio/flac", "audio/x-flac"],
+    },
+    AudioFormat {
+        name: "Ogg Vorbis",
+        extensions: &["ogg", "oga"],
+        mime_types: &["audio/ogg", "audio/vorbis", "audio/x-vorbis+ogg"],
+    },
+    AudioFormat {
+        name: "Opus",
+        extensions: &["opus"],
+        mime_types: &["audio/opus", "audio/x-opus+ogg"],
+    },
+    AudioFormat {
+        name: "AAC/M4A",
+        extensions: &["m4a", "m4b", "mp4", "aac"],
+        mime_types: &["audio/mp4", "audio/x-m4a", "audio/aac", "audio/x-aac"],
+    },
+];
+
+/// A source which waits for the output device
+type DecodedSource = Box<dyn Source<Item = f32> + Send>;
+
+/// A [`Source`] which can jump to an arbitrary frame.
+pub trait SeekableSource: Source + Iterator<Item = f32> {
+    /// Moves the source to `frame`. The next sample returned is the first sample of this frame.
+    /// If `frame` is after the end of the source, the source is empty afterwards.
+    fn seek(&mut self, frame: u64) -> Result<(), AudioError>;
+}
+
+/// A source which decodes an audio file from the disk.
+///
+/// Seeking jumps to the packet which contains the frame, so it doesn't decode the audio in
+/// between and can be done on the audio thread.
+pub struct FileSource {
+    decoder: SymphoniaDecoder,
+}
+
+impl FileSource {
+    pub fn open<P: AsRef<Path>>(path: &P) -> Result<Self, AudioError> {
+        let decoder = SymphoniaDecoder::open(path.as_ref())?;
+        Ok(FileSource { decoder })
+    }
+}
+
+impl SeekableSource for FileSource {
+    fn seek(&mut self, frame: u64) -> Result<(), AudioError> {
+        self.decoder.seek(frame)
+    }
+}
+
+impl Source for FileSource {
+    fn current_frame_len(&self) -> Option<usize> {
+        self.decoder.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.decoder.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.decoder.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.decoder.total_duration()
+    }
+}
+
+impl Iterator for FileSource {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.decoder.next()
+    }
+}
+
+pub const MIN_SPEED: f64 = 0.25;
+pub const MAX_SPEED: f64 = 3.0;
+
+/// A f64 which can be shared between threads. The value is stored as its bit pattern.
+struct AtomicF64(AtomicU64);
+
+impl AtomicF64 {
+    fn new(value: f64) -> Self {
+        AtomicF64(AtomicU64::new(value.to_bits()))
+    }
+
+    fn load(&self) -> f64 {
+        f64::from_bits(self.0.load(Ordering::Acquire))
+    }
+
+    fn store(&self, value: f64) {
+        self.0.store(value.to_bits(), Ordering::Release)
+    }
+}
+
+/// State which is shared between the [`AudioPlayer`] and the [`RubberBandSource`] running on the
+/// audio thread.
+pub struct SourceState {
+    seek_requested: AtomicBool,
+    seek_target: Mutex<Option<Duration>>,
+    parameters_changed: AtomicBool,
+    time_ratio: AtomicF64,
+    pitch_scale: AtomicF64,
+    formant_scale: AtomicF64,
+    /// the position in seconds in the time of the audio file
+    position: AtomicF64,
+    duration: Mutex<Option<Duration>>,
+    loop_changed: AtomicBool,
+    loop_region: Mutex<Option<LoopRegion>>,
+}
+
+impl Default for SourceState {
+    fn default() -> Self {
+        SourceState {
+            seek_requested: AtomicBool::new(false),
+            seek_target: Mutex::new(None),
+            parameters_changed: AtomicBool::new(false),
+            time_ratio: AtomicF64::new(1.0),
+            pitch_scale: AtomicF64::new(1.0),
+            // 0.0 lets rubberband choose the formant scale which matches the pitch scale
+            formant_scale: AtomicF64::new(0.0),
+            position: AtomicF64::new(0.0),
+            duration: Mutex::new(None),
+            loop_changed: AtomicBool::new(false),
+            loop_region: Mutex::new(None),
+        }
+    }
+}
+
+impl SourceState {
+    /// Creates a new state with the same time ratio, pitch scale and formant scale.
+    fn copy_parameters(&self) -> Self {
+        SourceState {
+            time_ratio: AtomicF64::new(self.time_ratio.load()),
+            pitch_scale: AtomicF64::new(self.pitch_scale.load()),
+            formant_scale: AtomicF64::new(self.formant_scale.load()),
+            ..Default::default()
+        }
+    }
+
+    fn mark_parameters_changed(&self) {
+        self.parameters_changed.store(true, Ordering::Release);
+    }
+
+    fn request_seek(&self, time: Duration) {
+        *self.seek_target.lock().unwrap() = Some(time);
+        // the position is updated right away, so that consecutive skips add up even if the
+        // audio thread hasn't executed the seek yet
+        self.position.store(time.as_secs_f64());
+        self.seek_requested.store(true, Ordering::Release);
+    }
+
+    fn take_seek_request(&self) -> Option<Duration> {
+        if self.seek_requested.swap(false, Ordering::AcqRel) {
+            self.seek_target.lock().unwrap().take()
+        } else {
+            None
+        }
+    }
+
+    fn set_loop_region(&self, loop_region: Option<LoopRegion>) {
+        *self.loop_region.lock().unwrap() = loop_region;
+        self.loop_changed.store(true, Ordering::Release);
+    }
+
+    fn take_loop_change(&self) -> Option<Option<LoopRegion>> {
+        if self.loop_changed.swap(false, Ordering::AcqRel) {
+            Some(*self.loop_region.lock().unwrap())
+        } else {
+            None
+        }
+    }
+
+    fn position(&self) -> Duration {
+        Duration::from_secs_f64(self.position.load().max(0.0))
+    }
+
+    fn duration(&self) -> Option<Duration> {
+        *self.duration.lock().unwrap()
+    }
+
+    fn set_duration(&self, duration: Duration) {
+        *self.duration.lock().unwrap() = Some(duration);
+    }
+}
+
+/// A handle to change the playback of a loaded file while it plays. It can be cloned and sent
+/// to other threads.
+///
+/// The changes are applied by the audio thread before it processes the next block.
+#[derive(Clone)]
+pub struct PlaybackControl {
+    state: Arc<SourceState>,
+}
+
+impl PlaybackControl {
+    pub fn speed(&self) -> f64 {
+        1.0 / self.state.time_ratio.load()
+    }
+
+    /// Sets the playback speed. The speed is clamped between [`MIN_SPEED`] and [`MAX_SPEED`].
+    pub fn set_speed(&self, speed: f64) {
+        let speed = speed.clamp(MIN_SPEED, MAX_SPEED);
+        self.state.time_ratio.store(1.0 / speed);
+        self.state.mark_parameters_changed();
+    }
+
+    pub fn pitch_scale(&self) -> f64 {
+        self.state.pitch_scale.load()
+    }
+
+    pub fn set_pitch_scale(&self, scale: f64) {
+        self.state.pitch_scale.store(scale);
+        self.state.mark_parameters_changed();
+    }
+
+    /// Sets the pitch in semitones relative to the original pitch.
+    pub fn set_pitch_semitones(&self, semitones: f64) {
+        self.set_pitch_scale(2.0f64.powf(semitones / 12.0));
+    }
+
+    pub fn formant_scale(&self) -> f64 {
+        self.state.formant_scale.load()
+    }
+
+    /// Sets the duration of the file, if the decoder couldn't tell it. It is known once the
+    /// file was decoded to its end, for example to compute its peaks.
+    pub fn set_duration(&self, duration: Duration) {
+        if self.state.duration().is_none() {
+            self.state.set_duration(duration);
+        }
+    }
+
+    /// Sets the formant scale. A scale of 0.0 lets rubberband choose the formant scale which
+    /// matches the pitch scale.
+    pub fn set_formant_scale(&self, scale: f64) {
+        self.state.formant_scale.store(scale);
+        self.state.mark_parameters_changed();
     }
 }
 
 const INPUT_BUFFER_SIZE: usize = 1024 * 2;
 
-pub struct RubberBandSource<S: Source + Iterator<Item = f32>> {
+/// The number of samples which are read from the source for one process call. The reading
+/// stops at the end of the current frame of the source, since the number of channels may change
+/// there. Like [`Source::current_frame_len`], `frame_len_left` counts samples, not frames.
+fn input_size(frame_len_left: Option<usize>, channels: usize) -> usize {
+    let samples = frame_len_left.map_or(INPUT_BUFFER_SIZE, |len| cmp::min(INPUT_BUFFER_SIZE, len));
+    // rubberband takes whole frames only
+    cmp::max(samples - samples % channels.max(1), channels)
+}
+
+pub struct RubberBandSource<S: SeekableSource> {
     rubberband_options: RubberBandOption,
     rubberband: RubberBand,
     source: S,
     buffer: VecDeque<f32>,
     frame_len_left: Option<usize>,
+    /// the number of frames rubberband delays its output by, which still have to be dropped
+    start_delay_left: usize,
+    /// the input frame which corresponds to the first output frame since the last seek or
+    /// change of the time ratio
+    anchor_frame: f64,
+    /// the time ratio the output frames since the anchor were stretched with
+    anchor_time_ratio: f64,
+    /// the number of output frames returned since the anchor
+    output_frames: u64,
+    /// true if the end of the source was reached. The source then plays silence instead of
+    /// ending, so that it stays in the sink and can still seek backwards.
+    source_finished: bool,
+    loop_region: Option<LoopRegion>,
+    /// how many times the loop region was already repeated
+    loop_repeats: u32,
+    /// the number of silent samples which are played before the loop region starts again
+    gap_samples_left: usize,
+    state: Arc<SourceState>,
 }
 
-impl<S: Source + Iterator<Item = f32>> RubberBandSource<S> {
-    pub fn new(source: S, rubberband_options: RubberBandOption) -> Self {
-        let rubberband = RubberBand::new(
-            source.sample_rate() as u32,
-            source.channels() as u32,
-            rubberband_options.clone(),
-            1.5,
-            1.0,
-        );
+impl<S: SeekableSource> RubberBandSource<S> {
+    pub fn new(source: S, rubberband_options: RubberBandOption, state: Arc<SourceState>) -> Self {
+        let rubberband = Self::create_rubberband(&source, rubberband_options, &state);
         let frame_len_left = source.current_frame_len();
-        RubberBandSource {
+        let mut rubberband_source = RubberBandSource {
             rubberband_options,
             rubberband,
             source,
             buffer: VecDeque::new(),
             frame_len_left,
+            start_delay_left: 0,
+            anchor_frame: 0.0,
+            anchor_time_ratio: state.time_ratio.load(),
+            output_frames: 0,
+            source_finished: false,
+            loop_region: None,
+            loop_repeats: 0,
+            gap_samples_left: 0,
+            state,
+        };
+        rubberband_source.pad_start();
+        rubberband_source
+    }
+
+    fn create_rubberband(
+        source: &S,
+        rubberband_options: RubberBandOption,
+        state: &SourceState,
+    ) -> RubberBand {
+        state.parameters_changed.store(false, Ordering::Release);
+        let mut rubberband = RubberBand::new(
+            source.sample_rate(),
+            source.channels() as u32,
+            rubberband_options,
+            state.time_ratio.load(),
+            state.pitch_scale.load(),
+        );
+        rubberband.set_formant_scale(state.formant_scale.load());
+        rubberband
+    }
+
+    /// Passes changed parameters on to rubberband. Rubberband smooths the change in real-time
+    /// mode, so this can be done between two process calls without clicks.
+    fn apply_parameters(&mut self) {
+        if self.state.parameters_changed.swap(false, Ordering::AcqRel) {
+            let time_ratio = self.state.time_ratio.load();
+            self.set_anchor(self.current_frame(), time_ratio);
+            self.rubberband.set_time_ratio(time_ratio);
+            self.rubberband
+                .set_pitch_scale(self.state.pitch_scale.load());
+            self.rubberband
+                .set_formant_scale(self.state.formant_scale.load());
+        }
+    }
+
+    /// Feeds rubberband with the silence it expects before the first real sample and remembers
+    /// how many output frames have to be dropped. Without this, the first sample after a reset
+    /// doesn't line up with the first sample of the input, since the delay depends on the
+    /// time ratio.
+    fn pad_start(&mut self) {
+        let channels = self.rubberband.channel_count();
+        let padding =
+            AudioBuffer::new_sized(channels, self.rubberband.preferred_start_pad() as usize);
+        if padding.num_samples() > 0 {
+            self.rubberband.process(&padding, false);
         }
+        self.start_delay_left = self.rubberband.start_delay() as usize;
+    }
+
+    fn set_anchor(&mut self, frame: f64, time_ratio: f64) {
+        self.anchor_frame = frame;
+        self.anchor_time_ratio = time_ratio;
+        self.output_frames = 0;
     }
 
-    fn recreate_rubberband_if_necessary(&mut self) {
+    /// The input frame which is currently played. Since the start delay of rubberband is
+    /// dropped, the output frames map to the input frames by the time ratio alone.
+    fn current_frame(&self) -> f64 {
+        self.anchor_frame + self.output_frames as f64 / self.anchor_time_ratio
+    }
+
+    fn update_position(&mut self) {
+        self.output_frames += 1;
+        let position = self.current_frame() / self.source.sample_rate() as f64;
+        self.state.position.store(position);
+    }
+
+    /// Jumps back to the start of the loop region if the played frame just passed its end.
+    /// The position is compared in the time of the audio file, so the region stays the same
+    /// when the speed changes.
+    fn loop_if_necessary(&mut self, previous_frame: f64) {
+        let loop_region = match self.loop_region {
+            Some(loop_region) => loop_region,
+            None => return,
+        };
+        if let Some(max_repeats) = loop_region.max_repeats {
+            if self.loop_repeats >= max_repeats {
+                return;
+            }
+        }
+        let sample_rate = self.source.sample_rate() as f64;
+        let end_frame = loop_region.end.as_secs_f64() * sample_rate;
+        if previous_frame < end_frame && self.current_frame() >= end_frame {
+            self.loop_repeats += 1;
+            self.seek(loop_region.start);
+            let gap_frames = (loop_region.gap.as_secs_f64() * sample_rate).round() as usize;
+            self.gap_samples_left = gap_frames * self.channels() as usize;
+        }
+    }
+
+    fn seek(&mut self, time: Duration) {
+        let frame = (time.as_secs_f64() * self.source.sample_rate() as f64).round() as u64;
+        if let Err(err) = self.source.seek(frame) {
+            log::error!("Couldn't seek to {:?}: {}", time, err);
+        }
+        self.buffer.clear();
+        self.source_finished = false;
+        self.gap_samples_left = 0;
+        self.frame_len_left = self.source.current_frame_len();
+        if !self.recreate_rubberband_if_necessary() {
+            self.rubberband.reset();
+            self.pad_start();
+        }
+        self.set_anchor(frame as f64, self.rubberband.time_ratio());
+        self.state.position.store(time.as_secs_f64());
+    }
+
+    /// Recreates rubberband if the number of channels changed. Returns true if it was recreated.
+    /// The [`ChannelRouter`] keeps the number of channels, so changing its routing never
+    /// recreates rubberband.
+    fn recreate_rubberband_if_necessary(&mut self) -> bool {
         if self.source.channels() as u32 != self.rubberband.channel_count() {
-            println!("recreate rubberband");
-            self.rubberband = RubberBand::new(
-                self.source.sample_rate(),
-                self.source.channels() as u32,
-                self.rubberband_options,
-                1.0,
-                1.0,
-            );
+            log::debug!("recreate rubberband");
+            self.rubberband =
+                Self::create_rubberband(&self.source, self.rubberband_options, &self.state);
+            self.pad_start();
+            true
+        } else {
+            false
         }
     }
 
@@ -142,12 +954,10 @@ impl<S: Source + Iterator<Item = f32>> RubberBandSource<S> {
             self.recreate_rubberband_if_necessary();
             self.frame_len_left = self.source.current_frame_len();
         }
+        self.apply_parameters();
         let mut input_buffer = vec![];
         let channels = self.rubberband.channel_count() as usize;
-        let input_size = cmp::min(
-            INPUT_BUFFER_SIZE,
-            self.frame_len_left.unwrap_or(usize::MAX) * channels,
-        );
+        let input_size = input_size(self.frame_len_left, channels);
         loop {
             let value = self.source.next();
             if let Some(value) = value {
@@ -162,6 +972,9 @@ impl<S: Source + Iterator<Item = f32>> RubberBandSource<S> {
         if input_buffer.is_empty() {
             return false; // no elements left in source
         }
+        if let Some(frame_len_left) = &mut self.frame_len_left {
+            *frame_len_left = frame_len_left.saturating_sub(input_buffer.len());
+        }
         let audio_buffer =
             AudioBuffer::from_interleafed(self.rubberband.channel_count(), &input_buffer);
         self.rubberband.process(&audio_buffer, false);
@@ -169,19 +982,20 @@ impl<S: Source + Iterator<Item = f32>> RubberBandSource<S> {
     }
 
     fn try_retrieve_rubberband(&mut self) -> bool {
-        println!("test");
         // tries to process more items. If no items are left in source then return false
-        if self.rubberband.available() == 0 {
-            while self.rubberband.available() < 1024 * 40 {
-                if !self.try_process_rubberband() {
-                    return false;
-                }
+        // Only one block is processed ahead, so that parameter changes are audible right away.
+        while self.rubberband.available() <= 0 {
+            if !self.try_process_rubberband() {
+                return false;
             }
         }
         let mut buffer =
             AudioBuffer::new_sized(self.channels() as u32, self.rubberband.available() as usize);
         let sample_count = self.rubberband.retrieve(&mut buffer) as usize;
-        let interleaved_buffer = &buffer.to_interleaved()[..sample_count * buffer.num_channels()];
+        let dropped_frames = cmp::min(sample_count, self.start_delay_left);
+        self.start_delay_left -= dropped_frames;
+        let interleaved_buffer = &buffer.to_interleaved()
+            [dropped_frames * buffer.num_channels()..sample_count * buffer.num_channels()];
         for sample in interleaved_buffer {
             self.buffer.push_back(*sample);
         }
@@ -189,7 +1003,7 @@ impl<S: Source + Iterator<Item = f32>> RubberBandSource<S> {
     }
 }
 
-impl<S: Source + Iterator<Item = f32>> Source for RubberBandSource<S> {
+impl<S: SeekableSource> Source for RubberBandSource<S> {
     fn current_frame_len(&self) -> Option<usize> {
         self.source.current_frame_len()
     }
@@ -203,44 +1017,352 @@ impl<S: Source + Iterator<Item = f32>> Source for RubberBandSource<S> {
     }
 
     fn total_duration(&self) -> Option<Duration> {
-        None
+        self.source
+            .total_duration()
+            .map(|duration| duration.mul_f64(self.rubberband.time_ratio()))
     }
 }
 
-impl<S: Source + Iterator<Item = f32>> Iterator for RubberBandSource<S> {
+impl<S: SeekableSource> Iterator for RubberBandSource<S> {
     type Item = f32;
 
     fn next(&mut self) -> Option<Self::Item> {
-        // retrieve more samples if buffer is empty.
-        // if no samples could be retrieved because the source is empty then return None
-        if self.buffer.is_empty() && !self.try_retrieve_rubberband() {
-            None
-        } else {
-            let sample = self.buffer.pop_front();
-            sample
+        let channels = self.channels() as usize;
+        // only seek between two frames, otherwise the channels get mixed up
+        let at_frame_start =
+            self.buffer.len() % channels == 0 && self.gap_samples_left % channels == 0;
+        if at_frame_start {
+            if let Some(time) = self.state.take_seek_request() {
+                self.seek(time);
+            }
+            if let Some(loop_region) = self.state.take_loop_change() {
+                self.loop_region = loop_region;
+                self.loop_repeats = 0;
+            }
+        }
+        if self.gap_samples_left > 0 {
+            self.gap_samples_left -= 1;
+            return Some(0.0);
+        }
+        // retrieve more samples if buffer is empty. The retrieved samples might all be
+        // dropped because of the start delay, hence the loop.
+        // if no samples could be retrieved because the source is empty then play a frame of
+        // silence
+        while self.buffer.is_empty() {
+            if self.source_finished || !self.try_retrieve_rubberband() {
+                self.source_finished = true;
+                self.buffer.extend(std::iter::repeat(0.0).take(channels));
+            }
+        }
+        let sample = self.buffer.pop_front();
+        if !self.source_finished && self.buffer.len() % channels == 0 {
+            let previous_frame = self.current_frame();
+            self.update_position();
+            self.loop_if_necessary(previous_frame);
         }
+        sample
     }
 }
 
 pub mod worker {
-    use std::path::PathBuf;
+    use std::{
+        path::PathBuf,
+        sync::{
+            atomic::{AtomicU64, Ordering},
+            Arc,
+        },
+        thread,
+        time::Duration,
+    };
 
-    use relm4::{ComponentUpdate, Components, Model};
+    use relm4::{ComponentUpdate, Model};
 
-    use super::{AudioError, AudioPlayer};
+    use super::{
+        channels::{ChannelRouter, ChannelRouting, RoutingControl},
+        denoise::NoiseProfile,
+        dynamics::DynamicsSettings,
+        equalizer::EqSettings,
+        memory::{DecodedAudio, MemorySource},
+        peak_cache::PeakCache,
+        peaks::Peaks,
+        AudioError, AudioPlayer, FileSource, LoopRegion, PlaybackControl, ResumeRewind,
+    };
+
+    /// The step sizes for skipping forwards and backwards
+    #[derive(Clone, Copy, Debug)]
+    pub struct SkipSteps {
+        pub small: Duration,
+        pub large: Duration,
+    }
+
+    impl Default for SkipSteps {
+        fn default() -> Self {
+            SkipSteps {
+                small: Duration::from_secs(2),
+                large: Duration::from_secs(10),
+            }
+        }
+    }
 
     pub trait AudioPlayerWorkerParent: Model {
-        fn loading_done_msg() -> Self::Msg;
+        fn loading_done_msg(control: PlaybackControl) -> Self::Msg;
         fn loading_error_msg(err: AudioError) -> Self::Msg;
+        fn position_msg(
+            position: Duration,
+            duration: Option<Duration>,
+            loop_region: Option<LoopRegion>,
+        ) -> Self::Msg;
+        fn peaks_msg(peaks: Arc<Peaks>) -> Self::Msg;
+        /// reports how much of a file is decoded into memory, `None` if it isn't known
+        fn load_progress_msg(progress: Option<f64>) -> Self::Msg;
+        /// reports whether the noise profile was captured
+        fn noise_profile_msg(result: Result<(), AudioError>) -> Self::Msg;
+        /// reports whether the player is connected to an output device, with the error why
+        /// it couldn't connect
+        fn output_msg(error: Option<AudioError>) -> Self::Msg;
     }
 
+    /// The default maximal size of the peak cache in bytes
+    pub const DEFAULT_PEAK_CACHE_SIZE: u64 = 200 * 1024 * 1024;
+
+    /// The default size in bytes which a file may take up when it is decoded into memory
+    pub const DEFAULT_MEMORY_DECODE_LIMIT: u64 = 512 * 1024 * 1024;
+
+    /// The default interval between two position updates which are sent to the parent
+    pub const DEFAULT_POSITION_UPDATE_INTERVAL: Duration = Duration::from_millis(100);
+
     pub struct AudioPlayerWorkerModel {
         player: AudioPlayer,
+        skip_steps: SkipSteps,
+        peak_cache: PeakCache,
+        /// files which take up to this many bytes when they are decoded are decoded into memory,
+        /// larger ones are streamed
+        memory_decode_limit: u64,
+        /// whether a file is being decoded into memory
+        decoding: bool,
+        /// the position which was requested while the file was decoded, it is applied once the
+        /// file plays
+        pending_seek: Option<Duration>,
+        /// the loop points which were set while the file was decoded
+        pending_loop: Option<(Duration, Duration)>,
+        /// the loaded file, to capture noise profiles from
+        path: Option<PathBuf>,
+        /// the loaded file if it was decoded into memory
+        decoded: Option<Arc<DecodedAudio>>,
+        /// whether the parent was told that there is no output, so that it is only told once
+        output_error_reported: bool,
+        /// the interval between two position updates in milliseconds, shared with the thread
+        /// which triggers them
+        position_update_interval: Arc<AtomicU64>,
+        /// whether the thread which triggers the position updates is running
+        reporting_position: bool,
+        /// counts the loaded files, so that the peaks of a previous file are dropped when they
+        /// are computed after the next file was loaded
+        load_generation: Arc<AtomicU64>,
+    }
+
+    impl AudioPlayerWorkerModel {
+        /// Loads the peaks of the file at `path` from the cache or computes them on a new thread
+        /// and sends them to the parent. The peaks know the number of frames of the file, so they
+        /// also set its duration if the decoder couldn't tell it.
+        fn compute_peaks<ParentModel>(
+            &self,
+            path: PathBuf,
+            generation: u64,
+            control: PlaybackControl,
+            parent_sender: glib::Sender<<ParentModel as Model>::Msg>,
+        ) where
+            ParentModel: AudioPlayerWorkerParent + 'static,
+            <ParentModel as Model>::Msg: Send,
+        {
+            let load_generation = self.load_generation.clone();
+            let peak_cache = self.peak_cache.clone();
+            thread::spawn(move || match peak_cache.load_or_compute(&path) {
+                Ok(peaks) => {
+                    control.set_duration(peaks.duration());
+                    if load_generation.load(Ordering::SeqCst) == generation {
+                        let _ = parent_sender.send(ParentModel::peaks_msg(Arc::new(peaks)));
+                    }
+                }
+                Err(err) => log::warn!("Couldn't compute the waveform: {}", err),
+            });
+        }
+
+        /// Decodes the file at `path` into memory on a new thread. The progress is sent to the
+        /// parent, the decoded audio to the worker and then its peaks to the parent.
+        fn decode_into_memory<ParentModel>(
+            &self,
+            path: PathBuf,
+            generation: u64,
+            sender: glib::Sender<AudioPlayerMsg>,
+            parent_sender: glib::Sender<<ParentModel as Model>::Msg>,
+        ) where
+            ParentModel: AudioPlayerWorkerParent + 'static,
+            <ParentModel as Model>::Msg: Send,
+        {
+            let load_generation = self.load_generation.clone();
+            let is_current = move || load_generation.load(Ordering::SeqCst) == generation;
+            let max_size = self.memory_decode_limit;
+            thread::spawn(move || {
+                let result = DecodedAudio::decode(&path, max_size, |progress| {
+                    if is_current() {
+                        let _ = parent_sender.send(ParentModel::load_progress_msg(progress));
+                    }
+                })
+                .map(|audio| audio.map(Arc::new));
+                let audio = result.as_ref().ok().cloned().flatten();
+                let _ = sender.send(AudioPlayerMsg::Decoded { generation, result });
+                if let Some(audio) = audio {
+                    let peaks = Peaks::from_decoded(&audio);
+                    if is_current() {
+                        let _ = parent_sender.send(ParentModel::peaks_msg(Arc::new(peaks)));
+                    }
+                }
+            });
+        }
+
+        /// Loads the file at `path` streamed from the disk and reports the result to the parent
+        fn load_streamed<ParentModel>(
+            &mut self,
+            path: PathBuf,
+            generation: u64,
+            parent_sender: glib::Sender<<ParentModel as Model>::Msg>,
+        ) where
+            ParentModel: AudioPlayerWorkerParent + 'static,
+            <ParentModel as Model>::Msg: Send,
+        {
+            let msg = match self.player.load(&path) {
+                Ok(control) => {
+                    self.compute_peaks::<ParentModel>(
+                        path,
+                        generation,
+                        control.clone(),
+                        parent_sender.clone(),
+                    );
+                    self.apply_pending();
+                    ParentModel::loading_done_msg(control)
+                }
+                Err(err) => ParentModel::loading_error_msg(err),
+            };
+            let _ = parent_sender.send(msg);
+        }
+
+        /// Applies the seek and the loop points which were requested while the file was decoded
+        fn apply_pending(&mut self) {
+            if let Some(time) = self.pending_seek.take() {
+                self.player.seek(time);
+            }
+            if let Some((start, end)) = self.pending_loop.take() {
+                self.player.set_loop_points(start, end);
+            }
+        }
+
+        /// Starts the thread which makes the worker send the position to the parent at the
+        /// configured interval. It ends once the worker is dropped.
+        fn start_position_updates(&mut self, sender: glib::Sender<AudioPlayerMsg>) {
+            if self.reporting_position {
+                return;
+            }
+            self.reporting_position = true;
+            let interval = self.position_update_interval.clone();
+            thread::spawn(move || loop {
+                thread::sleep(Duration::from_millis(interval.load(Ordering::SeqCst)));
+                if sender.send(AudioPlayerMsg::ReportPosition).is_err() {
+                    break;
+                }
+            });
+        }
+
+        /// Measures the noise between `start` and `end` of the loaded file on a new thread and
+        /// sends the profile to the worker.
+        ///
+        /// The denoiser gets the routed channels, so the noise is measured with the current
+        /// routing too. The router gets a control of its own, as it would otherwise take the
+        /// routing changes of the playing file.
+        fn capture_noise_profile(
+            &self,
+            start: Duration,
+            end: Duration,
+            sender: glib::Sender<AudioPlayerMsg>,
+        ) {
+            let generation = self.load_generation.load(Ordering::SeqCst);
+            let decoded = self.decoded.clone();
+            let path = self.path.clone();
+            let routing = Arc::new(RoutingControl::new(self.player.channel_routing()));
+            thread::spawn(move || {
+                let result = match (decoded, path) {
+                    (Some(audio), _) => NoiseProfile::capture(
+                        ChannelRouter::new(MemorySource::new(audio), routing),
+                        start,
+                        end,
+                    ),
+                    (None, Some(path)) => FileSource::open(&path).and_then(|source| {
+                        NoiseProfile::capture(ChannelRouter::new(source, routing), start, end)
+                    }),
+                    (None, None) => return,
+                };
+                let _ = sender.send(AudioPlayerMsg::NoiseProfileCaptured { generation, result });
+            });
+        }
     }
 
     pub enum AudioPlayerMsg {
+        /// connects the player to the output device if it isn't yet. A failure is only reported
+        /// once, unless `report_failure` is set.
+        ConnectOutput {
+            report_failure: bool,
+        },
         Load(PathBuf),
+        /// a file was decoded into memory, sent by the thread which decoded it. The audio is
+        /// `None` if it would take more memory than allowed.
+        Decoded {
+            generation: u64,
+            result: Result<Option<Arc<DecodedAudio>>, AudioError>,
+        },
         TogglePlayPause,
+        Seek(Duration),
+        /// sends the current position to the parent, sent by the thread which triggers the
+        /// position updates
+        ReportPosition,
+        /// sets how often the position is sent to the parent and starts sending it
+        SetPositionUpdateInterval(Duration),
+        SetResumeRewind(ResumeRewind),
+        /// skips by the given number of milliseconds, negative values skip backwards
+        SkipBy(i64),
+        SkipSmallBackward,
+        SkipSmallForward,
+        SkipLargeBackward,
+        SkipLargeForward,
+        SetSkipSteps(SkipSteps),
+        /// sets the maximal size of the peak cache in bytes
+        SetPeakCacheSize(u64),
+        /// sets how many bytes a file may take up when it is decoded into memory
+        SetMemoryDecodeLimit(u64),
+        SetDenoise(bool),
+        SetDynamics(DynamicsSettings),
+        SetEq(EqSettings),
+        SetChannelRouting(ChannelRouting),
+        /// uses the region between `start` and `end` of the loaded file as its noise profile
+        CaptureNoiseProfile {
+            start: Duration,
+            end: Duration,
+        },
+        /// a noise profile was captured, sent by the thread which captured it
+        NoiseProfileCaptured {
+            generation: u64,
+            result: Result<NoiseProfile, AudioError>,
+        },
+        SetLoopStart,
+        SetLoopEnd,
+        SetLoopPoints {
+            start: Duration,
+            end: Duration,
+        },
+        ClearLoop,
+        SetLoopOptions {
+            gap: Duration,
+            max_repeats: Option<u32>,
+        },
     }
 
     impl Model for AudioPlayerWorkerModel {
@@ -251,33 +1373,490 @@ pub mod worker {
 
     impl<ParentModel> ComponentUpdate<ParentModel> for AudioPlayerWorkerModel
     where
-        ParentModel: AudioPlayerWorkerParent,
+        ParentModel: AudioPlayerWorkerParent + 'static,
+        <ParentModel as Model>::Msg: Send,
     {
         fn init_model(_parent_model: &ParentModel) -> AudioPlayerWorkerModel {
-            let player = AudioPlayer::new().expect("Couldn't create audio player");
-            AudioPlayerWorkerModel { player }
+            AudioPlayerWorkerModel {
+                player: AudioPlayer::new(),
+                skip_steps: SkipSteps::default(),
+                peak_cache: PeakCache::new(DEFAULT_PEAK_CACHE_SIZE),
+                memory_decode_limit: DEFAULT_MEMORY_DECODE_LIMIT,
+                decoding: false,
+                pending_seek: None,
+                pending_loop: None,
+                path: None,
+                decoded: None,
+                output_error_reported: false,
+                position_update_interval: Arc::new(AtomicU64::new(
+                    DEFAULT_POSITION_UPDATE_INTERVAL.as_millis() as u64,
+                )),
+                reporting_position: false,
+                load_generation: Arc::new(AtomicU64::new(0)),
+            }
         }
 
         fn update(
             &mut self,
             msg: AudioPlayerMsg,
             _components: &(),
-            _sender: glib::Sender<AudioPlayerMsg>,
+            sender: glib::Sender<AudioPlayerMsg>,
             parent_sender: glib::Sender<<ParentModel as Model>::Msg>,
         ) {
             match msg {
+                AudioPlayerMsg::ConnectOutput { report_failure } => {
+                    if self.player.has_output() {
+                        return;
+                    }
+                    match self.player.connect_output() {
+                        Ok(()) => {
+                            self.output_error_reported = false;
+                            let _ = parent_sender.send(ParentModel::output_msg(None));
+                        }
+                        Err(err) => {
+                            if report_failure || !self.output_error_reported {
+                                self.output_error_reported = true;
+                                let _ = parent_sender.send(ParentModel::output_msg(Some(err)));
+                            }
+                        }
+                    }
+                }
                 AudioPlayerMsg::Load(path) => {
-                    parent_sender
-                        .send(match self.player.load(&path) {
-                            Ok(_) => ParentModel::loading_done_msg(),
-                            Err(err) => ParentModel::loading_error_msg(err),
-                        })
-                        .unwrap();
+                    let generation = self.load_generation.fetch_add(1, Ordering::SeqCst) + 1;
+                    self.path = Some(path.clone());
+                    self.decoded = None;
+                    self.pending_seek = None;
+                    self.pending_loop = None;
+                    // whether the file fits into memory is only known once it is opened
+                    self.decoding = self.memory_decode_limit > 0;
+                    if self.decoding {
+                        self.player.unload();
+                        self.decode_into_memory::<ParentModel>(
+                            path,
+                            generation,
+                            sender,
+                            parent_sender,
+                        );
+                    } else {
+                        self.load_streamed::<ParentModel>(path, generation, parent_sender);
+                    }
+                }
+                AudioPlayerMsg::Decoded { generation, result } => {
+                    // a decoded file is dropped if another file was loaded in the meantime
+                    if generation != self.load_generation.load(Ordering::SeqCst) {
+                        return;
+                    }
+                    self.decoding = false;
+                    let msg = match result {
+                        Ok(Some(audio)) => {
+                            self.decoded = Some(audio.clone());
+                            match self.player.load_decoded(audio) {
+                                Ok(control) => {
+                                    self.apply_pending();
+                                    ParentModel::loading_done_msg(control)
+                                }
+                                Err(err) => ParentModel::loading_error_msg(err),
+                            }
+                        }
+                        Ok(None) => {
+                            // the file would take too much memory, so it is streamed instead
+                            if let Some(path) = self.path.clone() {
+                                self.load_streamed::<ParentModel>(path, generation, parent_sender);
+                            }
+                            return;
+                        }
+                        Err(err) => ParentModel::loading_error_msg(err),
+                    };
+                    let _ = parent_sender.send(msg);
                 }
                 AudioPlayerMsg::TogglePlayPause => {
                     self.player.toggle_play_status();
                 }
+                AudioPlayerMsg::Seek(time) => {
+                    if self.decoding {
+                        self.pending_seek = Some(time);
+                    } else {
+                        self.player.seek(time);
+                    }
+                }
+                AudioPlayerMsg::SetPositionUpdateInterval(interval) => {
+                    // a zero interval would flood the parent with updates
+                    let millis = (interval.as_millis() as u64).max(1);
+                    self.position_update_interval
+                        .store(millis, Ordering::SeqCst);
+                    self.start_position_updates(sender);
+                }
+                AudioPlayerMsg::SetResumeRewind(resume_rewind) => {
+                    self.player.set_resume_rewind(resume_rewind);
+                }
+                AudioPlayerMsg::SkipBy(offset) => {
+                    self.player.skip_by(offset);
+                }
+                AudioPlayerMsg::SkipSmallBackward => {
+                    self.player
+                        .skip_by(-(self.skip_steps.small.as_millis() as i64));
+                }
+                AudioPlayerMsg::SkipSmallForward => {
+                    self.player
+                        .skip_by(self.skip_steps.small.as_millis() as i64);
+                }
+                AudioPlayerMsg::SkipLargeBackward => {
+                    self.player
+                        .skip_by(-(self.skip_steps.large.as_millis() as i64));
+                }
+                AudioPlayerMsg::SkipLargeForward => {
+                    self.player
+                        .skip_by(self.skip_steps.large.as_millis() as i64);
+                }
+                AudioPlayerMsg::SetSkipSteps(skip_steps) => {
+                    self.skip_steps = skip_steps;
+                }
+                AudioPlayerMsg::SetPeakCacheSize(size) => {
+                    self.peak_cache.set_max_size(size);
+                }
+                AudioPlayerMsg::SetMemoryDecodeLimit(limit) => {
+                    self.memory_decode_limit = limit;
+                }
+                AudioPlayerMsg::SetDenoise(enabled) => {
+                    self.player.set_denoise_enabled(enabled);
+                }
+                AudioPlayerMsg::SetDynamics(settings) => {
+                    self.player.set_dynamics(settings);
+                }
+                AudioPlayerMsg::SetEq(settings) => {
+                    self.player.set_eq(settings);
+                }
+                AudioPlayerMsg::SetChannelRouting(routing) => {
+                    self.player.set_channel_routing(routing);
+                }
+                AudioPlayerMsg::CaptureNoiseProfile { start, end } => {
+                    self.capture_noise_profile(start, end, sender);
+                }
+                AudioPlayerMsg::NoiseProfileCaptured { generation, result } => {
+                    // the profile belongs to a file which isn't loaded anymore
+                    if generation != self.load_generation.load(Ordering::SeqCst) {
+                        return;
+                    }
+                    let result = result.map(|profile| self.player.set_noise_profile(profile));
+                    let _ = parent_sender.send(ParentModel::noise_profile_msg(result));
+                }
+                // the position of the previous file is meaningless while the next one decodes
+                AudioPlayerMsg::SetLoopStart | AudioPlayerMsg::SetLoopEnd if self.decoding => {}
+                AudioPlayerMsg::SetLoopStart => {
+                    self.player.set_loop_start();
+                }
+                AudioPlayerMsg::SetLoopEnd => {
+                    self.player.set_loop_end();
+                }
+                AudioPlayerMsg::SetLoopPoints { start, end } => {
+                    if self.decoding {
+                        self.pending_loop = Some((start, end));
+                    } else {
+                        self.player.set_loop_points(start, end);
+                    }
+                }
+                AudioPlayerMsg::ClearLoop => {
+                    self.pending_loop = None;
+                    self.player.clear_loop();
+                }
+                AudioPlayerMsg::SetLoopOptions { gap, max_repeats } => {
+                    self.player.set_loop_options(gap, max_repeats);
+                }
+                AudioPlayerMsg::ReportPosition => {
+                    // the position of the previous file is meaningless while the next one decodes
+                    if self.decoding {
+                        return;
+                    }
+                    if let Some(position) = self.player.position() {
+                        let _ = parent_sender.send(ParentModel::position_msg(
+                            position,
+                            self.player.duration(),
+                            self.player.loop_region(),
+                        ));
+                    }
+                }
             };
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{f32::consts::PI, sync::Arc, time::Duration};
+
+    use rubberband_rs::RubberBandOption;
+
+    use super::{
+        input_size,
+        memory::{DecodedAudio, MemorySource},
+        LoopRegion, PlaybackControl, ResumeRewind, RubberBandSource, SourceState,
+        INPUT_BUFFER_SIZE,
+    };
+
+    const SAMPLE_RATE: u32 = 8000;
+
+    /// A stereo source with a 440 Hz tone on the left channel and silence on the right one
+    fn stereo_tone(seconds: f64) -> MemorySource {
+        let frames = (seconds * SAMPLE_RATE as f64) as usize;
+        let samples = (0..frames)
+            .flat_map(|frame| {
+                let time = frame as f32 / SAMPLE_RATE as f32;
+                [0.5 * (2.0 * PI * 440.0 * time).sin(), 0.0]
+            })
+            .collect();
+        MemorySource::new(Arc::new(DecodedAudio {
+            channels: 2,
+            sample_rate: SAMPLE_RATE,
+            samples,
+        }))
+    }
+
+    /// Wraps `source` like the player does
+    fn stretched(source: MemorySource) -> (RubberBandSource<MemorySource>, PlaybackControl) {
+        let options = RubberBandOption::PROCESS_REAL_TIME
+            | RubberBandOption::ENGINE_FINER
+            | RubberBandOption::FORMANT_PRESERVED;
+        let state = Arc::new(SourceState::default());
+        let control = PlaybackControl {
+            state: state.clone(),
+        };
+        (RubberBandSource::new(source, options, state), control)
+    }
+
+    fn position(control: &PlaybackControl) -> f64 {
+        control.state.position.load()
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1e-9,
+            "{} is not {}",
+            actual,
+            expected
+        );
+    }
+
+    fn scaled(rewind_ms: u64, pause_factor: f64, max_ms: u64) -> ResumeRewind {
+        ResumeRewind {
+            rewind: Duration::from_millis(rewind_ms),
+            scale_with_pause: true,
+            pause_factor,
+            max_rewind: Duration::from_millis(max_ms),
+        }
+    }
+
+    #[test]
+    fn fixed_rewind_ignores_the_pause() {
+        let resume_rewind = ResumeRewind::default();
+        assert_eq!(
+            resume_rewind.rewind_after(Duration::ZERO),
+            Duration::from_millis(1500)
+        );
+        assert_eq!(
+            resume_rewind.rewind_after(Duration::from_secs(600)),
+            Duration::from_millis(1500)
+        );
+    }
+
+    #[test]
+    fn fixed_rewind_is_limited() {
+        let resume_rewind = ResumeRewind {
+            rewind: Duration::from_secs(10),
+            ..ResumeRewind::default()
+        };
+        assert_eq!(
+            resume_rewind.rewind_after(Duration::ZERO),
+            Duration::from_secs(5)
+        );
+    }
+
+    #[test]
+    fn scaled_rewind_grows_with_the_pause() {
+        let resume_rewind = scaled(1000, 0.1, 5000);
+        assert_eq!(
+            resume_rewind.rewind_after(Duration::ZERO),
+            Duration::from_millis(1000)
+        );
+        assert_eq!(
+            resume_rewind.rewind_after(Duration::from_secs(20)),
+            Duration::from_millis(3000)
+        );
+    }
+
+    #[test]
+    fn scaled_rewind_is_limited() {
+        let resume_rewind = scaled(1000, 0.1, 5000);
+        assert_eq!(
+            resume_rewind.rewind_after(Duration::from_secs(3600)),
+            Duration::from_millis(5000)
+        );
+        assert_eq!(
+            scaled(1000, 0.0, 500).rewind_after(Duration::from_secs(1)),
+            Duration::from_millis(500)
+        );
+    }
+
+    #[test]
+    fn input_size_counts_samples() {
+        assert_eq!(input_size(None, 2), INPUT_BUFFER_SIZE);
+        assert_eq!(input_size(Some(100), 2), 100);
+        assert_eq!(input_size(Some(100), 1), 100);
+        assert_eq!(
+            input_size(Some(INPUT_BUFFER_SIZE * 4), 2),
+            INPUT_BUFFER_SIZE
+        );
+        assert_eq!(input_size(Some(usize::MAX), 2), INPUT_BUFFER_SIZE);
+    }
+
+    #[test]
+    fn input_size_takes_whole_frames() {
+        assert_eq!(
+            input_size(None, 6),
+            INPUT_BUFFER_SIZE - INPUT_BUFFER_SIZE % 6
+        );
+        assert_eq!(input_size(Some(0), 2), 2);
+        assert_eq!(input_size(Some(7), 3), 6);
+    }
+
+    #[test]
+    fn stereo_memory_source_keeps_its_channels() {
+        let (source, control) = stretched(stereo_tone(2.0));
+        let samples: Vec<f32> = source.take(SAMPLE_RATE as usize * 2).collect();
+        assert!(samples.iter().all(|sample| sample.is_finite()));
+        let energy = |channel: usize| -> f32 {
+            samples
+                .iter()
+                .skip(channel)
+                .step_by(2)
+                .map(|sample| sample * sample)
+                .sum()
+        };
+        assert!(energy(0) > 100.0);
+        assert!(energy(0) > 1000.0 * energy(1));
+        assert_close(position(&control), 1.0);
+    }
+
+    #[test]
+    fn seek_continues_from_the_target() {
+        let (mut source, control) = stretched(stereo_tone(2.0));
+        source.by_ref().take(1000).count();
+        control.state.request_seek(Duration::from_millis(500));
+        assert_close(position(&control), 0.5);
+        source.by_ref().take(2 * 800).count();
+        assert_close(position(&control), 0.6);
+
+        control.state.request_seek(Duration::from_millis(100));
+        source.by_ref().take(2 * 400).count();
+        assert_close(position(&control), 0.15);
+    }
+
+    #[test]
+    fn position_follows_the_time_ratio() {
+        let (mut source, control) = stretched(stereo_tone(4.0));
+        control.set_speed(0.5);
+        // the first block is processed with the new speed, so every output frame is half an
+        // input frame
+        source.by_ref().take(2 * 8000).count();
+        assert_close(position(&control), 0.5);
+        assert_eq!(source.total_duration(), Some(Duration::from_secs(8)));
+
+        control.state.request_seek(Duration::from_secs(1));
+        control.set_speed(2.0);
+        source.by_ref().take(2 * 4000).count();
+        assert_close(position(&control), 2.0);
+    }
+
+    #[test]
+    fn position_counts_the_played_frames() {
+        let (mut source, control) = stretched(stereo_tone(2.0));
+        assert_close(position(&control), 0.0);
+        for frame in 1..=800 {
+            source.by_ref().take(2).count();
+            assert_close(position(&control), frame as f64 / SAMPLE_RATE as f64);
+        }
+    }
+
+    #[test]
+    fn position_stops_at_the_end_of_the_source() {
+        let (mut source, control) = stretched(stereo_tone(0.5));
+        source.by_ref().take(2 * 8000).count();
+        let end = position(&control);
+        assert!(end > 0.25 && end <= 0.5, "stopped at {}", end);
+        // the source plays silence after its end, so that it stays in the sink
+        assert!(source.by_ref().take(2 * 800).all(|sample| sample == 0.0));
+        assert_close(position(&control), end);
+    }
+
+    #[test]
+    fn known_duration_is_kept() {
+        let control = PlaybackControl {
+            state: Arc::new(SourceState::default()),
+        };
+        assert_eq!(control.state.duration(), None);
+        control.set_duration(Duration::from_secs(3));
+        control.set_duration(Duration::from_secs(5));
+        assert_eq!(control.state.duration(), Some(Duration::from_secs(3)));
+    }
+
+    fn loop_region(gap_ms: u64, max_repeats: Option<u32>) -> LoopRegion {
+        LoopRegion {
+            start: Duration::from_millis(200),
+            end: Duration::from_millis(400),
+            gap: Duration::from_millis(gap_ms),
+            max_repeats,
+        }
+    }
+
+    #[test]
+    fn loop_repeats_with_a_silent_gap() {
+        let (mut source, control) = stretched(stereo_tone(2.0));
+        control
+            .state
+            .set_loop_region(Some(loop_region(100, Some(2))));
+        let mut repeats = 0;
+        let mut previous = 0.0;
+        for _ in 0..SAMPLE_RATE * 3 {
+            source.by_ref().take(2).count();
+            let current = position(&control);
+            if current < previous {
+                repeats += 1;
+                assert_close(previous, 0.4 - 1.0 / SAMPLE_RATE as f64);
+                assert_close(current, 0.2);
+                // the gap doesn't move the position
+                assert!(source.by_ref().take(2 * 800).all(|sample| sample == 0.0));
+                assert_close(position(&control), 0.2);
+            }
+            previous = current;
+            if current >= 0.6 {
+                break;
+            }
+        }
+        assert_eq!(repeats, 2);
+        assert!(previous >= 0.6);
+    }
+
+    #[test]
+    fn loop_region_stays_when_the_speed_changes() {
+        let (mut source, control) = stretched(stereo_tone(2.0));
+        control.set_speed(2.0);
+        control.state.set_loop_region(Some(loop_region(0, None)));
+        let mut repeats = 0;
+        let mut previous = 0.0;
+        for _ in 0..SAMPLE_RATE {
+            source.by_ref().take(2).count();
+            let current = position(&control);
+            assert!(
+                current < 0.4,
+                "played {} after the end of the loop",
+                current
+            );
+            if current < previous {
+                repeats += 1;
+                assert_close(current, 0.2);
+            }
+            previous = current;
+        }
+        // 8000 frames at twice the speed play the region more than 4 times after its start
+        assert!(repeats >= 4, "repeated {} times", repeats);
+    }
+}
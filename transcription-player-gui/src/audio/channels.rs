@@ -0,0 +1,358 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use rodio::Source;
+use serde::{Deserialize, Serialize};
+
+use super::{AudioError, SeekableSource};
+
+/// How long the old and the new routing are cross-faded when the routing changes
+const FADE_DURATION: Duration = Duration::from_millis(20);
+
+/// Which of the first two channels are heard on which side
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum ChannelMode {
+    #[default]
+    Stereo,
+    /// plays the left channel on both sides
+    LeftOnly,
+    /// plays the right channel on both sides
+    RightOnly,
+    /// plays the average of both channels on both sides
+    Mono,
+    Swap,
+}
+
+impl ChannelMode {
+    pub const ALL: [ChannelMode; 5] = [
+        ChannelMode::Stereo,
+        ChannelMode::LeftOnly,
+        ChannelMode::RightOnly,
+        ChannelMode::Mono,
+        ChannelMode::Swap,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ChannelMode::Stereo => "Stereo",
+            ChannelMode::LeftOnly => "Left only",
+            ChannelMode::RightOnly => "Right only",
+            ChannelMode::Mono => "Mono",
+            ChannelMode::Swap => "Swap",
+        }
+    }
+}
+
+/// How the [`ChannelRouter`] mixes the channels of a recording where each speaker has their own
+/// channel
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub struct ChannelRouting {
+    #[serde(default)]
+    pub mode: ChannelMode,
+    /// the gain of the left channel in dB, it is applied before the channels are routed
+    #[serde(default)]
+    pub left_gain: f32,
+    /// the gain of the right channel in dB
+    #[serde(default)]
+    pub right_gain: f32,
+}
+
+impl ChannelRouting {
+    /// Routes the `left` and `right` sample of a frame
+    fn route(&self, left: f32, right: f32) -> (f32, f32) {
+        let left = left * from_db(self.left_gain);
+        let right = right * from_db(self.right_gain);
+        match self.mode {
+            ChannelMode::Stereo => (left, right),
+            ChannelMode::LeftOnly => (left, left),
+            ChannelMode::RightOnly => (right, right),
+            ChannelMode::Mono => {
+                let mono = (left + right) / 2.0;
+                (mono, mono)
+            }
+            ChannelMode::Swap => (right, left),
+        }
+    }
+}
+
+fn from_db(db: f32) -> f32 {
+    10.0f32.powf(db / 20.0)
+}
+
+/// Changes the routing of the [`ChannelRouter`] while the audio plays. It is shared between the
+/// player and the router.
+#[derive(Default)]
+pub struct RoutingControl {
+    routing: Mutex<ChannelRouting>,
+    changed: AtomicBool,
+}
+
+impl RoutingControl {
+    pub fn new(routing: ChannelRouting) -> Self {
+        RoutingControl {
+            routing: Mutex::new(routing),
+            changed: AtomicBool::new(false),
+        }
+    }
+
+    pub fn routing(&self) -> ChannelRouting {
+        *self.routing.lock().unwrap()
+    }
+
+    pub fn set_routing(&self, routing: ChannelRouting) {
+        *self.routing.lock().unwrap() = routing;
+        self.changed.store(true, Ordering::SeqCst);
+    }
+
+    /// The new routing, if it changed since it was taken last
+    fn take_change(&self) -> Option<ChannelRouting> {
+        if !self.changed.swap(false, Ordering::SeqCst) {
+            return None;
+        }
+        match self.routing.try_lock() {
+            Ok(routing) => Some(*routing),
+            Err(_) => {
+                // the routing is being changed, it is taken with the next frame
+                self.changed.store(true, Ordering::SeqCst);
+                None
+            }
+        }
+    }
+}
+
+/// Plays only one channel, mixes both channels or swaps them, and changes the gain of each
+/// channel. The first two channels are routed, a mono file only gets the gain of the left
+/// channel.
+///
+/// The number of channels never changes, so that switching the routing doesn't recreate
+/// rubberband in the [`RubberBandSource`](super::RubberBandSource).
+pub struct ChannelRouter<S: SeekableSource> {
+    source: S,
+    control: Arc<RoutingControl>,
+    routing: ChannelRouting,
+    /// the routing before the last change, it is faded out over `fade_frames_left` frames
+    previous_routing: ChannelRouting,
+    fade_frames: usize,
+    fade_frames_left: usize,
+    /// the samples of the current frame
+    frame: Vec<f32>,
+    /// the index of the next sample of `frame`
+    position: usize,
+}
+
+impl<S: SeekableSource> ChannelRouter<S> {
+    pub fn new(source: S, control: Arc<RoutingControl>) -> Self {
+        let routing = *control.routing.lock().unwrap();
+        let fade_frames = (FADE_DURATION.as_secs_f64() * source.sample_rate() as f64) as usize;
+        let channels = source.channels() as usize;
+        ChannelRouter {
+            source,
+            control,
+            routing,
+            previous_routing: routing,
+            fade_frames: fade_frames.max(1),
+            fade_frames_left: 0,
+            frame: Vec::with_capacity(channels),
+            position: 0,
+        }
+    }
+
+    /// Reads the next frame of the source into `frame` and routes it. Returns false at the end
+    /// of the source.
+    fn process_frame(&mut self) -> bool {
+        if let Some(routing) = self.control.take_change() {
+            self.previous_routing = self.routing;
+            self.routing = routing;
+            self.fade_frames_left = self.fade_frames;
+        }
+        self.frame.clear();
+        self.position = 0;
+        for _ in 0..self.source.channels() {
+            match self.source.next() {
+                Some(sample) => self.frame.push(sample),
+                None => break,
+            }
+        }
+
+        match &mut self.frame[..] {
+            [] => return false,
+            [mono] => *mono *= from_db(self.routing.left_gain),
+            [left, right, ..] => {
+                let (mut routed_left, mut routed_right) = self.routing.route(*left, *right);
+                if self.fade_frames_left > 0 {
+                    let (previous_left, previous_right) =
+                        self.previous_routing.route(*left, *right);
+                    let fade = self.fade_frames_left as f32 / self.fade_frames as f32;
+                    routed_left = routed_left * (1.0 - fade) + previous_left * fade;
+                    routed_right = routed_right * (1.0 - fade) + previous_right * fade;
+                    self.fade_frames_left -= 1;
+                }
+                *left = routed_left;
+                *right = routed_right;
+            }
+        }
+        true
+    }
+}
+
+impl<S: SeekableSource> SeekableSource for ChannelRouter<S> {
+    fn seek(&mut self, frame: u64) -> Result<(), AudioError> {
+        self.source.seek(frame)?;
+        self.frame.clear();
+        self.position = 0;
+        Ok(())
+    }
+}
+
+impl<S: SeekableSource> Source for ChannelRouter<S> {
+    fn current_frame_len(&self) -> Option<usize> {
+        // the rest of the routed frame comes first
+        let buffered = self.frame.len() - self.position;
+        self.source
+            .current_frame_len()
+            .map(|len| len.saturating_add(buffered))
+    }
+
+    fn channels(&self) -> u16 {
+        self.source.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.source.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.source.total_duration()
+    }
+}
+
+impl<S: SeekableSource> Iterator for ChannelRouter<S> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.position >= self.frame.len() && !self.process_frame() {
+            return None;
+        }
+        let sample = self.frame[self.position];
+        self.position += 1;
+        Some(sample)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use rodio::Source;
+
+    use super::{from_db, ChannelMode, ChannelRouter, ChannelRouting, RoutingControl};
+    use crate::audio::{
+        memory::{DecodedAudio, MemorySource},
+        SeekableSource,
+    };
+
+    fn assert_frame((left, right): (f32, f32), expected: (f32, f32)) {
+        assert!(
+            (left - expected.0).abs() < 1e-5 && (right - expected.1).abs() < 1e-5,
+            "{:?} is not {:?}",
+            (left, right),
+            expected
+        );
+    }
+
+    fn routing(mode: ChannelMode) -> ChannelRouting {
+        ChannelRouting {
+            mode,
+            ..ChannelRouting::default()
+        }
+    }
+
+    /// A source with 1000 frames per second and the given frame repeated `frames` times
+    fn source(frame: &[f32], frames: usize) -> MemorySource {
+        MemorySource::new(Arc::new(DecodedAudio {
+            channels: frame.len() as u16,
+            sample_rate: 1000,
+            samples: frame.repeat(frames),
+        }))
+    }
+
+    fn next_frame<S: Iterator<Item = f32>>(source: &mut S) -> (f32, f32) {
+        (source.next().unwrap(), source.next().unwrap())
+    }
+
+    #[test]
+    fn modes_route_the_channels() {
+        let expected = [
+            (ChannelMode::Stereo, (1.0, 0.5)),
+            (ChannelMode::LeftOnly, (1.0, 1.0)),
+            (ChannelMode::RightOnly, (0.5, 0.5)),
+            (ChannelMode::Mono, (0.75, 0.75)),
+            (ChannelMode::Swap, (0.5, 1.0)),
+        ];
+        for (mode, frame) in expected {
+            assert_frame(routing(mode).route(1.0, 0.5), frame);
+        }
+    }
+
+    #[test]
+    fn gains_are_applied_before_routing() {
+        let routing = ChannelRouting {
+            mode: ChannelMode::Mono,
+            left_gain: 20.0,
+            right_gain: -20.0,
+        };
+        assert_frame(routing.route(0.1, 1.0), (0.55, 0.55));
+        assert!((from_db(0.0) - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn mono_sources_get_the_left_gain() {
+        let control = Arc::new(RoutingControl::new(ChannelRouting {
+            mode: ChannelMode::RightOnly,
+            left_gain: -20.0,
+            right_gain: 20.0,
+        }));
+        let mut router = ChannelRouter::new(source(&[0.5], 3), control);
+        assert_eq!(router.channels(), 1);
+        assert!((router.next().unwrap() - 0.05).abs() < 1e-6);
+    }
+
+    #[test]
+    fn routing_changes_fade_over() {
+        let control = Arc::new(RoutingControl::default());
+        let mut router = ChannelRouter::new(source(&[1.0, 0.0], 100), control.clone());
+        assert_frame(next_frame(&mut router), (1.0, 0.0));
+        control.set_routing(routing(ChannelMode::Swap));
+        // the fade takes 20ms, which are 20 frames
+        let fade: Vec<(f32, f32)> = (0..20).map(|_| next_frame(&mut router)).collect();
+        assert_frame(fade[0], (1.0, 0.0));
+        assert_frame(fade[10], (0.5, 0.5));
+        for pair in fade.windows(2) {
+            assert!(pair[1].0 < pair[0].0 && pair[1].1 > pair[0].1);
+        }
+        assert_frame(next_frame(&mut router), (0.0, 1.0));
+        assert_frame(next_frame(&mut router), (0.0, 1.0));
+    }
+
+    #[test]
+    fn seek_starts_with_a_whole_frame() {
+        let audio = DecodedAudio {
+            channels: 2,
+            sample_rate: 1000,
+            samples: (0..20).map(|sample| sample as f32).collect(),
+        };
+        let control = Arc::new(RoutingControl::new(routing(ChannelMode::Swap)));
+        let mut router = ChannelRouter::new(MemorySource::new(Arc::new(audio)), control);
+        assert_eq!(router.next(), Some(1.0));
+        assert_eq!(router.current_frame_len(), Some(19));
+        router.seek(5).unwrap();
+        assert_eq!(next_frame(&mut router), (11.0, 10.0));
+        assert_eq!(router.current_frame_len(), Some(8));
+    }
+}
@@ -0,0 +1,487 @@
+use std::{
+    collections::VecDeque,
+    f32::consts::PI,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use realfft::{num_complex::Complex32, ComplexToReal, RealFftPlanner, RealToComplex};
+use rodio::Source;
+
+use super::{AudioError, SeekableSource};
+
+/// The number of frames which are transformed at once
+const FFT_SIZE: usize = 1024;
+/// Consecutive blocks overlap by half
+const HOP_SIZE: usize = FFT_SIZE / 2;
+/// The number of frequency bins of a block
+const BINS: usize = FFT_SIZE / 2 + 1;
+/// How many times the noise profile is subtracted, so that noise which is a bit louder than the
+/// profile is removed too
+const OVER_SUBTRACTION: f32 = 2.0;
+/// The lowest gain of a frequency. A bit of noise is kept, otherwise the rest sounds watery.
+const SPECTRAL_FLOOR: f32 = 0.1;
+
+/// Transforms blocks of `FFT_SIZE` samples into their spectrum and back
+struct Transform {
+    forward: Arc<dyn RealToComplex<f32>>,
+    inverse: Arc<dyn ComplexToReal<f32>>,
+    /// the square root of a hann window. It is applied before and after the transformation,
+    /// so that the overlapping blocks add up to the original signal.
+    window: Vec<f32>,
+    block: Vec<f32>,
+    spectrum: Vec<Complex32>,
+}
+
+impl Transform {
+    fn new() -> Self {
+        let mut planner = RealFftPlanner::new();
+        let forward = planner.plan_fft_forward(FFT_SIZE);
+        let inverse = planner.plan_fft_inverse(FFT_SIZE);
+        Transform {
+            window: (0..FFT_SIZE)
+                .map(|i| (PI * i as f32 / FFT_SIZE as f32).sin())
+                .collect(),
+            block: forward.make_input_vec(),
+            spectrum: forward.make_output_vec(),
+            forward,
+            inverse,
+        }
+    }
+
+    /// Windows `samples` and transforms them into `spectrum`
+    fn forward(&mut self, samples: &[f32]) {
+        for ((block, sample), window) in self.block.iter_mut().zip(samples).zip(&self.window) {
+            *block = sample * window;
+        }
+        // the sizes always match, so the transformation can't fail
+        let _ = self.forward.process(&mut self.block, &mut self.spectrum);
+    }
+
+    /// Transforms `spectrum` back into `block` and windows it
+    fn inverse(&mut self) {
+        // the first and the last bin of the spectrum of a real signal have no imaginary part
+        self.spectrum[0].im = 0.0;
+        self.spectrum[BINS - 1].im = 0.0;
+        let _ = self.inverse.process(&mut self.spectrum, &mut self.block);
+        for (block, window) in self.block.iter_mut().zip(&self.window) {
+            *block *= window / FFT_SIZE as f32;
+        }
+    }
+}
+
+/// The average magnitude of each frequency of a region which contains only noise
+pub struct NoiseProfile {
+    magnitudes: Vec<f32>,
+}
+
+impl NoiseProfile {
+    /// Measures the noise in the region from `start` to `end` of `source`. The channels are
+    /// mixed, so that all of them are denoised with the same profile.
+    pub fn capture<S: SeekableSource>(
+        mut source: S,
+        start: Duration,
+        end: Duration,
+    ) -> Result<Self, AudioError> {
+        // a source without channels has no samples, which is reported as too short
+        let channels = (source.channels() as usize).max(1);
+        let sample_rate = source.sample_rate() as f64;
+        let start_frame = (start.as_secs_f64() * sample_rate) as u64;
+        let end_frame = (end.as_secs_f64() * sample_rate) as u64;
+        source.seek(start_frame)?;
+        let samples: Vec<f32> = source
+            .take(end_frame.saturating_sub(start_frame) as usize * channels)
+            .collect();
+        let mixed: Vec<f32> = samples
+            .chunks_exact(channels)
+            .map(|frame| frame.iter().sum::<f32>() / channels as f32)
+            .collect();
+        if mixed.len() < FFT_SIZE {
+            return Err(AudioError::NoiseRegionTooShortError {
+                min_millis: (FFT_SIZE as f64 / sample_rate * 1000.0).ceil() as u64,
+            });
+        }
+
+        let mut transform = Transform::new();
+        let mut magnitudes = vec![0.0; BINS];
+        let mut blocks = 0;
+        for block_start in (0..=mixed.len() - FFT_SIZE).step_by(HOP_SIZE) {
+            transform.forward(&mixed[block_start..block_start + FFT_SIZE]);
+            for (magnitude, bin) in magnitudes.iter_mut().zip(&transform.spectrum) {
+                *magnitude += bin.norm();
+            }
+            blocks += 1;
+        }
+        for magnitude in &mut magnitudes {
+            *magnitude /= blocks as f32;
+        }
+        Ok(NoiseProfile { magnitudes })
+    }
+}
+
+/// Switches the denoiser on and off and sets its noise profile while the audio plays. It is
+/// shared between the player and the [`DenoiseSource`].
+#[derive(Default)]
+pub struct DenoiseControl {
+    enabled: AtomicBool,
+    profile: Mutex<Option<Arc<NoiseProfile>>>,
+}
+
+impl DenoiseControl {
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::SeqCst);
+    }
+
+    pub fn set_profile(&self, profile: Option<Arc<NoiseProfile>>) {
+        *self.profile.lock().unwrap() = profile;
+    }
+}
+
+/// Removes stationary noise, like the hum of a fan, by spectral subtraction: the magnitude of
+/// the noise profile is subtracted from each frequency of the audio.
+///
+/// The audio is transformed even while the denoiser is off, so that switching it on and off
+/// doesn't shift the audio. Without a noise profile, the audio passes unchanged.
+pub struct DenoiseSource<S: SeekableSource> {
+    source: S,
+    control: Arc<DenoiseControl>,
+    profile: Option<Arc<NoiseProfile>>,
+    transform: Transform,
+    channels: usize,
+    /// the last `FFT_SIZE` frames of each channel which were read from the source
+    input: Vec<Vec<f32>>,
+    /// the sum of the overlapping output blocks of each channel, the first `HOP_SIZE` frames
+    /// are complete
+    overlap: Vec<Vec<f32>>,
+    /// the interleaved samples which are ready
+    output: VecDeque<f32>,
+    /// the output frames which are dropped, because they belong to the silence before the
+    /// first frame of the source
+    skip_frames: usize,
+    /// the frames which were read from the source but aren't output yet
+    pending_frames: usize,
+    source_ended: bool,
+}
+
+impl<S: SeekableSource> DenoiseSource<S> {
+    pub fn new(source: S, control: Arc<DenoiseControl>) -> Self {
+        let channels = source.channels() as usize;
+        let mut denoise_source = DenoiseSource {
+            source,
+            control,
+            profile: None,
+            transform: Transform::new(),
+            channels,
+            input: vec![vec![0.0; FFT_SIZE]; channels],
+            overlap: vec![vec![0.0; FFT_SIZE]; channels],
+            output: VecDeque::new(),
+            skip_frames: 0,
+            pending_frames: 0,
+            source_ended: false,
+        };
+        denoise_source.reset();
+        denoise_source
+    }
+
+    /// Starts over as if the source begins at its current position
+    fn reset(&mut self) {
+        for channel in self.input.iter_mut().chain(&mut self.overlap) {
+            channel.fill(0.0);
+        }
+        self.output.clear();
+        self.skip_frames = FFT_SIZE - HOP_SIZE;
+        self.pending_frames = 0;
+        self.source_ended = false;
+    }
+
+    /// Reads `HOP_SIZE` frames from the source and denoises the block which ends with them.
+    /// Returns false once all frames of the source are output.
+    fn process_block(&mut self) -> bool {
+        // without channels nothing is read from the source, so it would never end
+        if self.channels == 0 || self.source_ended && self.pending_frames == 0 {
+            return false;
+        }
+        if let Ok(profile) = self.control.profile.try_lock() {
+            self.profile = profile.clone();
+        }
+        let profile = self
+            .profile
+            .as_ref()
+            .filter(|_| self.control.enabled.load(Ordering::SeqCst));
+
+        for channel in &mut self.input {
+            channel.copy_within(HOP_SIZE.., 0);
+            channel[FFT_SIZE - HOP_SIZE..].fill(0.0);
+        }
+        if !self.source_ended {
+            'frames: for frame in FFT_SIZE - HOP_SIZE..FFT_SIZE {
+                for channel in &mut self.input {
+                    match self.source.next() {
+                        Some(sample) => channel[frame] = sample,
+                        None => {
+                            self.source_ended = true;
+                            break 'frames;
+                        }
+                    }
+                }
+                self.pending_frames += 1;
+            }
+        }
+
+        for (input, overlap) in self.input.iter().zip(&mut self.overlap) {
+            self.transform.forward(input);
+            if let Some(profile) = profile {
+                for (bin, noise) in self.transform.spectrum.iter_mut().zip(&profile.magnitudes) {
+                    let magnitude = bin.norm();
+                    if magnitude > 0.0 {
+                        let gain = (1.0 - OVER_SUBTRACTION * noise / magnitude).max(SPECTRAL_FLOOR);
+                        *bin *= gain;
+                    }
+                }
+            }
+            self.transform.inverse();
+            for (overlap, sample) in overlap.iter_mut().zip(&self.transform.block) {
+                *overlap += sample;
+            }
+        }
+
+        for frame in 0..HOP_SIZE {
+            if self.skip_frames > 0 {
+                self.skip_frames -= 1;
+            } else if self.pending_frames > 0 {
+                self.pending_frames -= 1;
+                self.output
+                    .extend(self.overlap.iter().map(|channel| channel[frame]));
+            }
+        }
+        for channel in &mut self.overlap {
+            channel.copy_within(HOP_SIZE.., 0);
+            channel[FFT_SIZE - HOP_SIZE..].fill(0.0);
+        }
+        true
+    }
+}
+
+impl<S: SeekableSource> SeekableSource for DenoiseSource<S> {
+    fn seek(&mut self, frame: u64) -> Result<(), AudioError> {
+        self.source.seek(frame)?;
+        self.reset();
+        Ok(())
+    }
+}
+
+impl<S: SeekableSource> Source for DenoiseSource<S> {
+    fn current_frame_len(&self) -> Option<usize> {
+        // the samples which were read from the source but aren't returned yet come first
+        let buffered = self.output.len() + self.pending_frames * self.channels;
+        self.source
+            .current_frame_len()
+            .map(|len| len.saturating_add(buffered))
+    }
+
+    fn channels(&self) -> u16 {
+        self.channels as u16
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.source.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.source.total_duration()
+    }
+}
+
+impl<S: SeekableSource> Iterator for DenoiseSource<S> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(sample) = self.output.pop_front() {
+                return Some(sample);
+            }
+            if !self.process_block() {
+                return None;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{f32::consts::PI, sync::Arc, time::Duration};
+
+    use rodio::Source;
+
+    use super::{DenoiseControl, DenoiseSource, NoiseProfile, BINS, FFT_SIZE};
+    use crate::audio::{
+        memory::{DecodedAudio, MemorySource},
+        AudioError, SeekableSource,
+    };
+
+    const SAMPLE_RATE: u32 = 8000;
+
+    /// Deterministic white noise with an amplitude of `amplitude`
+    fn noise(len: usize, amplitude: f32) -> Vec<f32> {
+        let mut state = 0x2545_f491u32;
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                (state as f32 / u32::MAX as f32 * 2.0 - 1.0) * amplitude
+            })
+            .collect()
+    }
+
+    fn tone(len: usize, amplitude: f32) -> Vec<f32> {
+        (0..len)
+            .map(|frame| amplitude * (2.0 * PI * 300.0 * frame as f32 / SAMPLE_RATE as f32).sin())
+            .collect()
+    }
+
+    fn source(channels: u16, samples: Vec<f32>) -> MemorySource {
+        MemorySource::new(Arc::new(DecodedAudio {
+            channels,
+            sample_rate: SAMPLE_RATE,
+            samples,
+        }))
+    }
+
+    fn energy(samples: &[f32]) -> f32 {
+        samples.iter().map(|sample| sample * sample).sum()
+    }
+
+    fn assert_same(actual: &[f32], expected: &[f32]) {
+        assert_eq!(actual.len(), expected.len());
+        for (index, (actual, expected)) in actual.iter().zip(expected).enumerate() {
+            assert!(
+                (actual - expected).abs() < 1e-4,
+                "sample {} is {} instead of {}",
+                index,
+                actual,
+                expected
+            );
+        }
+    }
+
+    fn profile_of(samples: Vec<f32>) -> Arc<NoiseProfile> {
+        let duration = Duration::from_secs_f64(samples.len() as f64 / SAMPLE_RATE as f64);
+        Arc::new(NoiseProfile::capture(source(1, samples), Duration::ZERO, duration).unwrap())
+    }
+
+    #[test]
+    fn audio_passes_without_a_profile() {
+        let samples: Vec<f32> = tone(3000, 0.5)
+            .into_iter()
+            .zip(noise(3000, 0.1))
+            .flat_map(|(left, right)| [left, right])
+            .collect();
+        let control = Arc::new(DenoiseControl::default());
+        control.set_enabled(true);
+        let output: Vec<f32> = DenoiseSource::new(source(2, samples.clone()), control).collect();
+        // the latency of the transformation is dropped, so the samples line up
+        assert_same(&output, &samples);
+    }
+
+    #[test]
+    fn audio_passes_while_switched_off() {
+        let samples = tone(2000, 0.5);
+        let control = Arc::new(DenoiseControl::default());
+        control.set_profile(Some(profile_of(noise(4000, 0.1))));
+        let output: Vec<f32> = DenoiseSource::new(source(1, samples.clone()), control).collect();
+        assert_same(&output, &samples);
+    }
+
+    #[test]
+    fn profile_averages_the_channels() {
+        let left = noise(2 * FFT_SIZE, 0.1);
+        let samples = left.iter().flat_map(|sample| [*sample, -sample]).collect();
+        let profile =
+            NoiseProfile::capture(source(2, samples), Duration::ZERO, Duration::from_secs(1))
+                .unwrap();
+        assert_eq!(profile.magnitudes.len(), BINS);
+        assert!(profile.magnitudes.iter().all(|magnitude| *magnitude < 1e-6));
+
+        let profile = profile_of(noise(2 * FFT_SIZE, 0.1));
+        assert!(profile.magnitudes[1..BINS - 1]
+            .iter()
+            .all(|magnitude| *magnitude > 0.0));
+    }
+
+    #[test]
+    fn profile_needs_a_whole_block() {
+        let result = NoiseProfile::capture(
+            source(1, noise(8000, 0.1)),
+            Duration::from_millis(500),
+            Duration::from_millis(600),
+        );
+        assert!(matches!(
+            result,
+            Err(AudioError::NoiseRegionTooShortError { min_millis: 128 })
+        ));
+        // the region is cut off at the end of the source
+        let result = NoiseProfile::capture(
+            source(1, noise(8000, 0.1)),
+            Duration::from_millis(950),
+            Duration::from_secs(2),
+        );
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn noise_is_removed_and_speech_kept() {
+        let control = Arc::new(DenoiseControl::default());
+        control.set_profile(Some(profile_of(noise(8000, 0.1))));
+        control.set_enabled(true);
+
+        let noisy = noise(8000, 0.1);
+        let output: Vec<f32> = DenoiseSource::new(source(1, noisy.clone()), control.clone())
+            .skip(FFT_SIZE)
+            .collect();
+        assert!(energy(&output) < 0.1 * energy(&noisy[FFT_SIZE..]));
+
+        let speech = tone(8000, 0.5);
+        let output: Vec<f32> = DenoiseSource::new(source(1, speech.clone()), control)
+            .skip(FFT_SIZE)
+            .collect();
+        assert!(energy(&output) > 0.9 * energy(&speech[FFT_SIZE..]));
+    }
+
+    #[test]
+    fn seek_starts_over_at_the_target() {
+        let samples = tone(4000, 0.5);
+        let mut denoise = DenoiseSource::new(
+            source(1, samples.clone()),
+            Arc::new(DenoiseControl::default()),
+        );
+        denoise.by_ref().take(100).count();
+        denoise.seek(1000).unwrap();
+        let output: Vec<f32> = denoise.collect();
+        assert_same(&output, &samples[1000..]);
+    }
+
+    #[test]
+    fn frame_len_counts_the_buffered_samples() {
+        let mut denoise = DenoiseSource::new(
+            source(1, tone(4000, 0.5)),
+            Arc::new(DenoiseControl::default()),
+        );
+        assert_eq!(denoise.current_frame_len(), Some(4000));
+        denoise.next();
+        assert_eq!(denoise.current_frame_len(), Some(3999));
+    }
+
+    #[test]
+    fn sources_without_channels_end() {
+        let mut denoise =
+            DenoiseSource::new(source(0, vec![]), Arc::new(DenoiseControl::default()));
+        assert_eq!(denoise.next(), None);
+        let result =
+            NoiseProfile::capture(source(0, vec![]), Duration::ZERO, Duration::from_secs(1));
+        assert!(result.is_err());
+    }
+}
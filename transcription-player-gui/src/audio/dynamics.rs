@@ -0,0 +1,484 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use rodio::Source;
+
+/// The loudness in dBFS which the automatic gain control levels speech to
+const AGC_TARGET: f32 = -20.0;
+/// The automatic gain control never amplifies by more than this many dB
+const AGC_MAX_GAIN: f32 = 24.0;
+/// The automatic gain control never attenuates by more than this many dB
+const AGC_MAX_ATTENUATION: f32 = 12.0;
+/// Below this loudness in dBFS the audio is considered a pause, which doesn't change the gain
+/// of the automatic gain control. Otherwise it would amplify the background noise.
+const AGC_GATE: f32 = -50.0;
+/// How long the automatic gain control averages the loudness
+const AGC_WINDOW: Duration = Duration::from_secs(3);
+/// How long the automatic gain control takes to adapt its gain
+const AGC_ADAPTION: Duration = Duration::from_secs(1);
+/// The limiter keeps the peaks below this level in dBFS
+const LIMITER_CEILING: f32 = -1.0;
+const LIMITER_RELEASE: Duration = Duration::from_millis(50);
+/// The level in dB which is used for silence, instead of negative infinity
+const SILENCE: f32 = -120.0;
+
+/// The parameters of the compressor, the limiter and the automatic gain control
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DynamicsSettings {
+    /// the level in dBFS above which the compressor reduces the gain
+    pub threshold: f32,
+    /// how much the level above the threshold is reduced, 1 turns the compressor off
+    pub ratio: f32,
+    pub attack: Duration,
+    pub release: Duration,
+    /// the gain in dB which is added after compressing
+    pub makeup_gain: f32,
+    pub limiter: bool,
+    /// slowly levels the loudness of the speakers
+    pub agc: bool,
+}
+
+impl Default for DynamicsSettings {
+    fn default() -> Self {
+        DynamicsPreset::Off.settings()
+    }
+}
+
+/// Settings of the [`DynamicsSource`] which work well for transcribing
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DynamicsPreset {
+    Off,
+    /// evens out a single speaker a bit
+    Light,
+    /// a loud interviewer close to the microphone and a quiet interviewee further away
+    Interview,
+    /// levels several quiet speakers at different distances, also across long pauses
+    QuietSpeakers,
+}
+
+impl DynamicsPreset {
+    pub const ALL: [DynamicsPreset; 4] = [
+        DynamicsPreset::Off,
+        DynamicsPreset::Light,
+        DynamicsPreset::Interview,
+        DynamicsPreset::QuietSpeakers,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            DynamicsPreset::Off => "Off",
+            DynamicsPreset::Light => "Light",
+            DynamicsPreset::Interview => "Interview",
+            DynamicsPreset::QuietSpeakers => "Quiet speakers",
+        }
+    }
+
+    pub fn settings(&self) -> DynamicsSettings {
+        match self {
+            DynamicsPreset::Off => DynamicsSettings {
+                threshold: -20.0,
+                ratio: 1.0,
+                attack: Duration::from_millis(10),
+                release: Duration::from_millis(200),
+                makeup_gain: 0.0,
+                limiter: false,
+                agc: false,
+            },
+            DynamicsPreset::Light => DynamicsSettings {
+                threshold: -18.0,
+                ratio: 2.0,
+                attack: Duration::from_millis(10),
+                release: Duration::from_millis(200),
+                makeup_gain: 4.0,
+                limiter: true,
+                agc: false,
+            },
+            DynamicsPreset::Interview => DynamicsSettings {
+                threshold: -26.0,
+                ratio: 4.0,
+                attack: Duration::from_millis(5),
+                release: Duration::from_millis(250),
+                makeup_gain: 10.0,
+                limiter: true,
+                agc: false,
+            },
+            DynamicsPreset::QuietSpeakers => DynamicsSettings {
+                threshold: -22.0,
+                ratio: 3.0,
+                attack: Duration::from_millis(5),
+                release: Duration::from_millis(300),
+                makeup_gain: 2.0,
+                limiter: true,
+                agc: true,
+            },
+        }
+    }
+
+    /// The preset with exactly these settings, if there is one
+    pub fn matching(settings: &DynamicsSettings) -> Option<DynamicsPreset> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|preset| preset.settings() == *settings)
+    }
+}
+
+/// Changes the settings of the [`DynamicsSource`] while the audio plays. It is shared between
+/// the player and the source.
+#[derive(Default)]
+pub struct DynamicsControl {
+    settings: Mutex<DynamicsSettings>,
+    changed: AtomicBool,
+}
+
+impl DynamicsControl {
+    pub fn set_settings(&self, settings: DynamicsSettings) {
+        *self.settings.lock().unwrap() = settings;
+        self.changed.store(true, Ordering::SeqCst);
+    }
+
+    /// The new settings, if they changed since they were taken last
+    fn take_change(&self) -> Option<DynamicsSettings> {
+        if !self.changed.swap(false, Ordering::SeqCst) {
+            return None;
+        }
+        match self.settings.try_lock() {
+            Ok(settings) => Some(*settings),
+            Err(_) => {
+                // the settings are being changed, they are taken with the next frame
+                self.changed.store(true, Ordering::SeqCst);
+                None
+            }
+        }
+    }
+}
+
+/// The factor of a one pole filter which reaches about 63% of a step after `time`
+fn smoothing_coefficient(time: Duration, sample_rate: u32) -> f32 {
+    let samples = time.as_secs_f32() * sample_rate as f32;
+    if samples <= 0.0 {
+        0.0
+    } else {
+        (-1.0 / samples).exp()
+    }
+}
+
+fn to_db(amplitude: f32) -> f32 {
+    if amplitude > 0.0 {
+        (20.0 * amplitude.log10()).max(SILENCE)
+    } else {
+        SILENCE
+    }
+}
+
+fn from_db(db: f32) -> f32 {
+    10.0f32.powf(db / 20.0)
+}
+
+/// Evens out the loudness of the audio with a compressor with make-up gain, a limiter and an
+/// optional slow automatic gain control. All channels of a frame get the same gain, so that the
+/// stereo image stays the same.
+pub struct DynamicsSource<S: Source<Item = f32>> {
+    source: S,
+    control: Arc<DynamicsControl>,
+    settings: DynamicsSettings,
+    attack: f32,
+    release: f32,
+    agc_window: f32,
+    agc_adaption: f32,
+    limiter_release: f32,
+    /// the smoothed gain reduction of the compressor in dB
+    compressor_reduction: f32,
+    /// the average power of the audio, for the automatic gain control
+    agc_power: f32,
+    /// the gain of the automatic gain control in dB
+    agc_gain: f32,
+    /// the gain reduction of the limiter in dB
+    limiter_reduction: f32,
+    /// the samples of the current frame
+    frame: Vec<f32>,
+    /// the index of the next sample of `frame`
+    position: usize,
+}
+
+impl<S: Source<Item = f32>> DynamicsSource<S> {
+    pub fn new(source: S, control: Arc<DynamicsControl>) -> Self {
+        let settings = *control.settings.lock().unwrap();
+        let sample_rate = source.sample_rate();
+        let channels = source.channels() as usize;
+        let mut dynamics_source = DynamicsSource {
+            source,
+            control,
+            settings,
+            attack: 0.0,
+            release: 0.0,
+            agc_window: smoothing_coefficient(AGC_WINDOW, sample_rate),
+            agc_adaption: smoothing_coefficient(AGC_ADAPTION, sample_rate),
+            limiter_release: smoothing_coefficient(LIMITER_RELEASE, sample_rate),
+            compressor_reduction: 0.0,
+            agc_power: from_db(AGC_TARGET).powi(2),
+            agc_gain: 0.0,
+            limiter_reduction: 0.0,
+            frame: Vec::with_capacity(channels),
+            position: 0,
+        };
+        dynamics_source.apply_settings(settings);
+        dynamics_source
+    }
+
+    fn apply_settings(&mut self, settings: DynamicsSettings) {
+        let sample_rate = self.source.sample_rate();
+        self.settings = settings;
+        self.attack = smoothing_coefficient(settings.attack, sample_rate);
+        self.release = smoothing_coefficient(settings.release, sample_rate);
+        if !settings.agc {
+            self.agc_gain = 0.0;
+        }
+    }
+
+    /// Reads the next frame of the source into `frame` and applies the gain to it. Returns false
+    /// at the end of the source.
+    fn process_frame(&mut self) -> bool {
+        if let Some(settings) = self.control.take_change() {
+            self.apply_settings(settings);
+        }
+        self.frame.clear();
+        self.position = 0;
+        for _ in 0..self.source.channels() {
+            match self.source.next() {
+                Some(sample) => self.frame.push(sample),
+                None => break,
+            }
+        }
+        if self.frame.is_empty() {
+            return false;
+        }
+        let peak = self
+            .frame
+            .iter()
+            .fold(0.0f32, |peak, sample| peak.max(sample.abs()));
+
+        if self.settings.agc {
+            self.agc_power =
+                self.agc_window * self.agc_power + (1.0 - self.agc_window) * peak * peak;
+            let level = to_db(self.agc_power.sqrt());
+            // the average decays slowly, so the current frame is checked as well, otherwise
+            // the gain keeps rising at the start of a pause
+            if level > AGC_GATE && to_db(peak) > AGC_GATE {
+                let target_gain = (AGC_TARGET - level).clamp(-AGC_MAX_ATTENUATION, AGC_MAX_GAIN);
+                self.agc_gain =
+                    self.agc_adaption * self.agc_gain + (1.0 - self.agc_adaption) * target_gain;
+            }
+        }
+
+        let level = to_db(peak) + self.agc_gain;
+        let overshoot = level - self.settings.threshold;
+        let reduction = if overshoot > 0.0 && self.settings.ratio > 1.0 {
+            overshoot * (1.0 - 1.0 / self.settings.ratio)
+        } else {
+            0.0
+        };
+        let coefficient = if reduction > self.compressor_reduction {
+            self.attack
+        } else {
+            self.release
+        };
+        self.compressor_reduction =
+            coefficient * self.compressor_reduction + (1.0 - coefficient) * reduction;
+        let mut gain = self.agc_gain - self.compressor_reduction + self.settings.makeup_gain;
+
+        if self.settings.limiter {
+            // the limiter reacts right away, so that no peak gets through
+            let overshoot = (to_db(peak) + gain - LIMITER_CEILING).max(0.0);
+            self.limiter_reduction = overshoot.max(self.limiter_release * self.limiter_reduction);
+            gain -= self.limiter_reduction;
+        }
+
+        if gain != 0.0 {
+            let factor = from_db(gain);
+            for sample in &mut self.frame {
+                *sample *= factor;
+            }
+        }
+        true
+    }
+}
+
+impl<S: Source<Item = f32>> Source for DynamicsSource<S> {
+    fn current_frame_len(&self) -> Option<usize> {
+        // the rest of the current frame comes first
+        let buffered = self.frame.len() - self.position;
+        self.source
+            .current_frame_len()
+            .map(|len| len.saturating_add(buffered))
+    }
+
+    fn channels(&self) -> u16 {
+        self.source.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.source.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.source.total_duration()
+    }
+}
+
+impl<S: Source<Item = f32>> Iterator for DynamicsSource<S> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.position >= self.frame.len() && !self.process_frame() {
+            return None;
+        }
+        let sample = self.frame[self.position];
+        self.position += 1;
+        Some(sample)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::{
+        from_db, to_db, DynamicsControl, DynamicsPreset, DynamicsSettings, DynamicsSource,
+        LIMITER_CEILING, SILENCE,
+    };
+    use crate::audio::memory::{DecodedAudio, MemorySource};
+
+    const SAMPLE_RATE: u32 = 1000;
+
+    /// A source with 1000 frames per second and the given frame repeated `frames` times
+    fn source(frame: &[f32], frames: usize) -> MemorySource {
+        MemorySource::new(Arc::new(DecodedAudio {
+            channels: frame.len() as u16,
+            sample_rate: SAMPLE_RATE,
+            samples: frame.repeat(frames),
+        }))
+    }
+
+    fn dynamics(
+        source: MemorySource,
+        preset: DynamicsPreset,
+    ) -> (DynamicsSource<MemorySource>, Arc<DynamicsControl>) {
+        let control = Arc::new(DynamicsControl::default());
+        control.set_settings(preset.settings());
+        (DynamicsSource::new(source, control.clone()), control)
+    }
+
+    fn assert_db(amplitude: f32, expected_db: f32) {
+        let db = to_db(amplitude);
+        assert!(
+            (db - expected_db).abs() < 0.1,
+            "{} dB is not {} dB",
+            db,
+            expected_db
+        );
+    }
+
+    #[test]
+    fn presets_are_found_by_their_settings() {
+        for preset in DynamicsPreset::ALL {
+            assert_eq!(DynamicsPreset::matching(&preset.settings()), Some(preset));
+        }
+        let custom = DynamicsSettings {
+            ratio: 5.0,
+            ..DynamicsPreset::Light.settings()
+        };
+        assert_eq!(DynamicsPreset::matching(&custom), None);
+        assert_eq!(DynamicsSettings::default(), DynamicsPreset::Off.settings());
+    }
+
+    #[test]
+    fn decibels_convert_to_amplitudes() {
+        assert!((from_db(-6.0206) - 0.5).abs() < 1e-4);
+        assert!((to_db(0.1) + 20.0).abs() < 1e-4);
+        assert_eq!(to_db(0.0), SILENCE);
+        assert_eq!(to_db(-1.0), SILENCE);
+    }
+
+    #[test]
+    fn off_keeps_the_samples() {
+        let samples = [0.9, -0.001, 0.3, -0.7];
+        let (dynamics, _) = dynamics(source(&samples, 100), DynamicsPreset::Off);
+        assert_eq!(dynamics.collect::<Vec<_>>(), samples.repeat(100));
+    }
+
+    #[test]
+    fn compressor_reduces_loud_audio() {
+        // -6 dBFS is 12 dB over the threshold of -18 dBFS, the ratio of 2 takes away 6 dB and
+        // the makeup gain adds 4 dB
+        let (dynamics, _) = dynamics(source(&[0.5, -0.25], 1000), DynamicsPreset::Light);
+        let output: Vec<f32> = dynamics.collect();
+        assert_db(output[1998], -8.0);
+        // all channels get the same gain
+        assert!((output[1999] / output[1998] + 0.5).abs() < 1e-5);
+    }
+
+    #[test]
+    fn limiter_catches_the_attack() {
+        let mut samples = vec![0.0; 200];
+        samples.extend(vec![1.0; 200]);
+        let control = Arc::new(DynamicsControl::default());
+        control.set_settings(DynamicsPreset::Interview.settings());
+        let audio = DecodedAudio {
+            channels: 1,
+            sample_rate: SAMPLE_RATE,
+            samples,
+        };
+        let dynamics = DynamicsSource::new(MemorySource::new(Arc::new(audio)), control);
+        let ceiling = from_db(LIMITER_CEILING) + 1e-5;
+        assert!(dynamics.into_iter().all(|sample| sample.abs() <= ceiling));
+    }
+
+    #[test]
+    fn agc_levels_quiet_audio_and_keeps_its_gain_in_pauses() {
+        let mut samples = vec![0.01; 30_000];
+        samples.extend(vec![0.0; 10_000]);
+        let control = Arc::new(DynamicsControl::default());
+        control.set_settings(DynamicsPreset::QuietSpeakers.settings());
+        let audio = DecodedAudio {
+            channels: 1,
+            sample_rate: SAMPLE_RATE,
+            samples,
+        };
+        let mut dynamics = DynamicsSource::new(MemorySource::new(Arc::new(audio)), control);
+        let last = dynamics.nth(29_999).unwrap();
+        // -40 dBFS is lifted to about the target of -20 dBFS, then slightly compressed
+        assert!(
+            to_db(last) > -22.0 && to_db(last) < -17.0,
+            "{}",
+            to_db(last)
+        );
+        let gain = dynamics.agc_gain;
+        assert!(dynamics.by_ref().all(|sample| sample == 0.0));
+        assert_eq!(dynamics.agc_gain, gain);
+    }
+
+    #[test]
+    fn settings_change_while_playing() {
+        let (mut dynamics, control) = dynamics(source(&[0.5], 2000), DynamicsPreset::Off);
+        assert_eq!(dynamics.nth(999), Some(0.5));
+        control.set_settings(DynamicsPreset::Light.settings());
+        assert_db(dynamics.last().unwrap(), -8.0);
+        assert_eq!(control.settings.lock().unwrap().ratio, 2.0);
+    }
+
+    #[test]
+    fn frame_len_counts_the_rest_of_the_frame() {
+        use rodio::Source;
+
+        let (mut dynamics, _) = dynamics(source(&[0.1, 0.2], 10), DynamicsPreset::Off);
+        assert_eq!(dynamics.current_frame_len(), Some(20));
+        dynamics.next();
+        assert_eq!(dynamics.current_frame_len(), Some(19));
+    }
+}
@@ -0,0 +1,677 @@
+use std::{
+    f32::consts::PI,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use rodio::Source;
+use serde::{Deserialize, Serialize};
+
+use super::{AudioError, SeekableSource};
+
+/// The quality of the high-pass and the low-pass, which gives them a flat pass band
+const PASS_Q: f32 = std::f32::consts::FRAC_1_SQRT_2;
+/// The default quality of the presence boost, about one and a half octaves wide
+const PRESENCE_Q: f32 = 1.0;
+/// The quality of the hum notches, narrow enough to leave the voice untouched
+const NOTCH_Q: f32 = 30.0;
+/// How many multiples of the mains frequency are removed, hum is rich in harmonics
+const HUM_HARMONICS: u32 = 4;
+
+/// The filters a band starts with when it is switched on
+const DEFAULT_HIGH_PASS: f32 = 100.0;
+const DEFAULT_PRESENCE: PeakFilter = PeakFilter {
+    frequency: 3000.0,
+    gain: 6.0,
+    q: PRESENCE_Q,
+};
+const DEFAULT_LOW_PASS: f32 = 3400.0;
+
+/// A boost or cut of the frequencies around `frequency`
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct PeakFilter {
+    /// in Hz
+    pub frequency: f32,
+    /// in dB
+    pub gain: f32,
+    /// how narrow the boost or cut is, higher is narrower
+    #[serde(default = "presence_q")]
+    pub q: f32,
+}
+
+fn presence_q() -> f32 {
+    PRESENCE_Q
+}
+
+/// A filter of the equalizer which can be switched on and off
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EqBand {
+    HighPass,
+    Presence,
+    LowPass,
+}
+
+/// A value of the equalizer which can be adjusted while its band is on
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EqParameter {
+    /// the frequency of the high-pass in Hz
+    HighPass,
+    /// in Hz
+    PresenceFrequency,
+    /// in dB
+    PresenceGain,
+    PresenceQ,
+    /// the frequency of the low-pass in Hz
+    LowPass,
+}
+
+/// The filters of the [`EqSource`]. The filters which are `None` are off.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub struct EqSettings {
+    /// removes the rumble below this frequency in Hz
+    #[serde(default)]
+    pub high_pass: Option<f32>,
+    /// makes the voice clearer
+    #[serde(default)]
+    pub presence: Option<PeakFilter>,
+    /// removes the hiss above this frequency in Hz
+    #[serde(default)]
+    pub low_pass: Option<f32>,
+    /// removes the hum of the mains at this frequency in Hz and its harmonics
+    #[serde(default)]
+    pub hum: Option<f32>,
+}
+
+impl EqSettings {
+    pub fn is_enabled(&self, band: EqBand) -> bool {
+        match band {
+            EqBand::HighPass => self.high_pass.is_some(),
+            EqBand::Presence => self.presence.is_some(),
+            EqBand::LowPass => self.low_pass.is_some(),
+        }
+    }
+
+    /// Switches `band` on or off. A band which is switched on starts with its default filter, a
+    /// band which is already on keeps its filter.
+    pub fn set_enabled(&mut self, band: EqBand, enabled: bool) {
+        match band {
+            EqBand::HighPass => {
+                self.high_pass = enabled.then(|| self.high_pass.unwrap_or(DEFAULT_HIGH_PASS))
+            }
+            EqBand::Presence => {
+                self.presence = enabled.then(|| self.presence.unwrap_or(DEFAULT_PRESENCE))
+            }
+            EqBand::LowPass => {
+                self.low_pass = enabled.then(|| self.low_pass.unwrap_or(DEFAULT_LOW_PASS))
+            }
+        }
+    }
+
+    /// The value of `parameter`, or its default while its band is off
+    pub fn parameter(&self, parameter: EqParameter) -> f32 {
+        let presence = self.presence.unwrap_or(DEFAULT_PRESENCE);
+        match parameter {
+            EqParameter::HighPass => self.high_pass.unwrap_or(DEFAULT_HIGH_PASS),
+            EqParameter::PresenceFrequency => presence.frequency,
+            EqParameter::PresenceGain => presence.gain,
+            EqParameter::PresenceQ => presence.q,
+            EqParameter::LowPass => self.low_pass.unwrap_or(DEFAULT_LOW_PASS),
+        }
+    }
+
+    /// Changes `parameter`. The change is ignored while its band is off, so that showing the
+    /// default doesn't switch the band on.
+    pub fn set_parameter(&mut self, parameter: EqParameter, value: f32) {
+        let target = match (parameter, &mut self.presence) {
+            (EqParameter::HighPass, _) => self.high_pass.as_mut(),
+            (EqParameter::LowPass, _) => self.low_pass.as_mut(),
+            (EqParameter::PresenceFrequency, Some(presence)) => Some(&mut presence.frequency),
+            (EqParameter::PresenceGain, Some(presence)) => Some(&mut presence.gain),
+            (EqParameter::PresenceQ, Some(presence)) => Some(&mut presence.q),
+            (_, None) => None,
+        };
+        if let Some(target) = target {
+            *target = value;
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EqPreset {
+    Off,
+    /// keeps only the band of a telephone line
+    PhoneCall,
+    /// brings back the clarity of a recording through a wall or from a pocket
+    MuffledRoom,
+    RemoveHum50,
+    RemoveHum60,
+}
+
+impl EqPreset {
+    pub const ALL: [EqPreset; 5] = [
+        EqPreset::Off,
+        EqPreset::PhoneCall,
+        EqPreset::MuffledRoom,
+        EqPreset::RemoveHum50,
+        EqPreset::RemoveHum60,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            EqPreset::Off => "Off",
+            EqPreset::PhoneCall => "Phone call",
+            EqPreset::MuffledRoom => "Muffled room",
+            EqPreset::RemoveHum50 => "Remove hum 50 Hz",
+            EqPreset::RemoveHum60 => "Remove hum 60 Hz",
+        }
+    }
+
+    pub fn settings(&self) -> EqSettings {
+        match self {
+            EqPreset::Off => EqSettings::default(),
+            EqPreset::PhoneCall => EqSettings {
+                high_pass: Some(300.0),
+                presence: Some(PeakFilter {
+                    frequency: 2000.0,
+                    gain: 3.0,
+                    q: PRESENCE_Q,
+                }),
+                low_pass: Some(3400.0),
+                hum: None,
+            },
+            EqPreset::MuffledRoom => EqSettings {
+                high_pass: Some(100.0),
+                presence: Some(PeakFilter {
+                    frequency: 3000.0,
+                    gain: 8.0,
+                    q: PRESENCE_Q,
+                }),
+                low_pass: None,
+                hum: None,
+            },
+            EqPreset::RemoveHum50 => EqSettings {
+                high_pass: Some(40.0),
+                hum: Some(50.0),
+                ..EqSettings::default()
+            },
+            EqPreset::RemoveHum60 => EqSettings {
+                high_pass: Some(40.0),
+                hum: Some(60.0),
+                ..EqSettings::default()
+            },
+        }
+    }
+
+    /// The preset with exactly these settings, if there is one
+    pub fn matching(settings: &EqSettings) -> Option<EqPreset> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|preset| preset.settings() == *settings)
+    }
+}
+
+/// The normalized coefficients of a biquad filter, from the Audio EQ Cookbook by Robert
+/// Bristow-Johnson
+#[derive(Clone, Copy, Debug)]
+struct Biquad {
+    b0: f32,
+    b1: f32,
+    b2: f32,
+    a1: f32,
+    a2: f32,
+}
+
+impl Biquad {
+    fn new(b: [f32; 3], a: [f32; 3]) -> Self {
+        Biquad {
+            b0: b[0] / a[0],
+            b1: b[1] / a[0],
+            b2: b[2] / a[0],
+            a1: a[1] / a[0],
+            a2: a[2] / a[0],
+        }
+    }
+
+    /// The cosine of the frequency and alpha of the cookbook
+    fn parameters(frequency: f32, q: f32, sample_rate: u32) -> (f32, f32) {
+        let omega = 2.0 * PI * frequency / sample_rate as f32;
+        (omega.cos(), omega.sin() / (2.0 * q))
+    }
+
+    fn high_pass(frequency: f32, sample_rate: u32) -> Self {
+        let (cos, alpha) = Self::parameters(frequency, PASS_Q, sample_rate);
+        Self::new(
+            [(1.0 + cos) / 2.0, -(1.0 + cos), (1.0 + cos) / 2.0],
+            [1.0 + alpha, -2.0 * cos, 1.0 - alpha],
+        )
+    }
+
+    fn low_pass(frequency: f32, sample_rate: u32) -> Self {
+        let (cos, alpha) = Self::parameters(frequency, PASS_Q, sample_rate);
+        Self::new(
+            [(1.0 - cos) / 2.0, 1.0 - cos, (1.0 - cos) / 2.0],
+            [1.0 + alpha, -2.0 * cos, 1.0 - alpha],
+        )
+    }
+
+    fn peak(filter: PeakFilter, sample_rate: u32) -> Self {
+        let (cos, alpha) = Self::parameters(filter.frequency, filter.q, sample_rate);
+        let amplitude = 10.0f32.powf(filter.gain / 40.0);
+        Self::new(
+            [1.0 + alpha * amplitude, -2.0 * cos, 1.0 - alpha * amplitude],
+            [1.0 + alpha / amplitude, -2.0 * cos, 1.0 - alpha / amplitude],
+        )
+    }
+
+    fn notch(frequency: f32, sample_rate: u32) -> Self {
+        let (cos, alpha) = Self::parameters(frequency, NOTCH_Q, sample_rate);
+        Self::new(
+            [1.0, -2.0 * cos, 1.0],
+            [1.0 + alpha, -2.0 * cos, 1.0 - alpha],
+        )
+    }
+
+    /// Filters `input` in the transposed direct form II, `state` is the memory of the filter
+    fn process(&self, state: &mut [f32; 2], input: f32) -> f32 {
+        let output = self.b0 * input + state[0];
+        state[0] = self.b1 * input - self.a1 * output + state[1];
+        state[1] = self.b2 * input - self.a2 * output;
+        output
+    }
+}
+
+/// Which part of the equalizer a filter is, so that it keeps its memory when the settings change
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum FilterKind {
+    HighPass,
+    /// the notch of the given harmonic of the hum
+    Hum(u32),
+    Presence,
+    LowPass,
+}
+
+/// The filters which make up `settings`. Filters at or above the nyquist frequency are left out.
+fn filters(settings: &EqSettings, sample_rate: u32) -> Vec<(FilterKind, Biquad)> {
+    let nyquist = sample_rate as f32 / 2.0;
+    let mut filters = vec![];
+    if let Some(frequency) = settings.high_pass.filter(|frequency| *frequency < nyquist) {
+        filters.push((
+            FilterKind::HighPass,
+            Biquad::high_pass(frequency, sample_rate),
+        ));
+    }
+    if let Some(hum) = settings.hum {
+        filters.extend(
+            (1..=HUM_HARMONICS)
+                .map(|harmonic| (harmonic, hum * harmonic as f32))
+                .filter(|(_, frequency)| *frequency < nyquist)
+                .map(|(harmonic, frequency)| {
+                    (
+                        FilterKind::Hum(harmonic),
+                        Biquad::notch(frequency, sample_rate),
+                    )
+                }),
+        );
+    }
+    if let Some(presence) = settings
+        .presence
+        .filter(|presence| presence.frequency < nyquist && presence.q > 0.0)
+    {
+        filters.push((FilterKind::Presence, Biquad::peak(presence, sample_rate)));
+    }
+    if let Some(frequency) = settings.low_pass.filter(|frequency| *frequency < nyquist) {
+        filters.push((
+            FilterKind::LowPass,
+            Biquad::low_pass(frequency, sample_rate),
+        ));
+    }
+    filters
+}
+
+/// Changes the settings of the [`EqSource`] while the audio plays. It is shared between the
+/// player and the source.
+#[derive(Default)]
+pub struct EqControl {
+    settings: Mutex<EqSettings>,
+    changed: AtomicBool,
+}
+
+impl EqControl {
+    pub fn set_settings(&self, settings: EqSettings) {
+        *self.settings.lock().unwrap() = settings;
+        self.changed.store(true, Ordering::SeqCst);
+    }
+
+    /// The new settings, if they changed since they were taken last
+    fn take_change(&self) -> Option<EqSettings> {
+        if !self.changed.swap(false, Ordering::SeqCst) {
+            return None;
+        }
+        match self.settings.try_lock() {
+            Ok(settings) => Some(*settings),
+            Err(_) => {
+                // the settings are being changed, they are taken with the next frame
+                self.changed.store(true, Ordering::SeqCst);
+                None
+            }
+        }
+    }
+}
+
+/// An equalizer made of a chain of biquad filters: a high-pass against rumble, notches against
+/// the hum of the mains, a presence boost for the voice and a low-pass against hiss.
+pub struct EqSource<S: SeekableSource> {
+    source: S,
+    control: Arc<EqControl>,
+    filters: Vec<(FilterKind, Biquad)>,
+    /// the memory of each filter for each channel
+    states: Vec<Vec<[f32; 2]>>,
+    /// the channel of the next sample
+    channel: usize,
+}
+
+impl<S: SeekableSource> EqSource<S> {
+    pub fn new(source: S, control: Arc<EqControl>) -> Self {
+        let settings = *control.settings.lock().unwrap();
+        let channels = source.channels() as usize;
+        let mut eq_source = EqSource {
+            source,
+            control,
+            filters: vec![],
+            states: vec![vec![]; channels],
+            channel: 0,
+        };
+        eq_source.apply_settings(&settings);
+        eq_source
+    }
+
+    fn apply_settings(&mut self, settings: &EqSettings) {
+        let filters = filters(settings, self.source.sample_rate());
+        // the filters which stay keep their memory, so that the change doesn't click
+        for states in &mut self.states {
+            let kept = filters
+                .iter()
+                .map(|(kind, _)| {
+                    self.filters
+                        .iter()
+                        .position(|(previous, _)| previous == kind)
+                        .map_or([0.0; 2], |index| states[index])
+                })
+                .collect();
+            *states = kept;
+        }
+        self.filters = filters;
+    }
+
+    fn reset(&mut self) {
+        for state in self.states.iter_mut().flatten() {
+            *state = [0.0; 2];
+        }
+        self.channel = 0;
+    }
+}
+
+impl<S: SeekableSource> SeekableSource for EqSource<S> {
+    fn seek(&mut self, frame: u64) -> Result<(), AudioError> {
+        self.source.seek(frame)?;
+        self.reset();
+        Ok(())
+    }
+}
+
+impl<S: SeekableSource> Source for EqSource<S> {
+    fn current_frame_len(&self) -> Option<usize> {
+        self.source.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.source.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.source.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.source.total_duration()
+    }
+}
+
+impl<S: SeekableSource> Iterator for EqSource<S> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.channel == 0 {
+            if let Some(settings) = self.control.take_change() {
+                self.apply_settings(&settings);
+            }
+        }
+        let mut sample = self.source.next()?;
+        if let Some(states) = self.states.get_mut(self.channel) {
+            for ((_, filter), state) in self.filters.iter().zip(states) {
+                sample = filter.process(state, sample);
+            }
+        }
+        // a source without channels has no state, it only passes its samples through
+        self.channel = (self.channel + 1) % self.states.len().max(1);
+        Some(sample)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{f64::consts::PI, sync::Arc};
+
+    use super::{
+        filters, Biquad, EqBand, EqControl, EqParameter, EqPreset, EqSettings, EqSource,
+        FilterKind, PeakFilter, DEFAULT_HIGH_PASS, PRESENCE_Q,
+    };
+    use crate::audio::memory::{DecodedAudio, MemorySource};
+
+    const SAMPLE_RATE: u32 = 48000;
+
+    /// How much `biquad` amplifies a sine wave with the given frequency
+    fn response(biquad: &Biquad, frequency: f32) -> f32 {
+        let omega = 2.0 * PI * frequency as f64 / SAMPLE_RATE as f64;
+        // the magnitude of the numerator and the denominator of the transfer function at
+        // e^(i omega), in f64 since the terms almost cancel out at low frequencies
+        let polynomial = |c0: f32, c1: f32, c2: f32| {
+            let (c0, c1, c2) = (c0 as f64, c1 as f64, c2 as f64);
+            let real = c0 + c1 * omega.cos() + c2 * (2.0 * omega).cos();
+            let imaginary = -c1 * omega.sin() - c2 * (2.0 * omega).sin();
+            real.hypot(imaginary)
+        };
+        (polynomial(biquad.b0, biquad.b1, biquad.b2) / polynomial(1.0, biquad.a1, biquad.a2)) as f32
+    }
+
+    fn assert_close(actual: f32, expected: f32) {
+        assert!(
+            (actual - expected).abs() < 1e-3,
+            "{} is not {}",
+            actual,
+            expected
+        );
+    }
+
+    /// A stereo source with the given number of frames of noise-like samples
+    fn source(frames: usize) -> MemorySource {
+        MemorySource::new(Arc::new(DecodedAudio {
+            channels: 2,
+            sample_rate: SAMPLE_RATE,
+            samples: (0..frames * 2)
+                .map(|sample| ((sample * 7919) % 200) as f32 / 100.0 - 1.0)
+                .collect(),
+        }))
+    }
+
+    fn kinds(eq: &EqSource<MemorySource>) -> Vec<FilterKind> {
+        eq.filters.iter().map(|(kind, _)| *kind).collect()
+    }
+
+    #[test]
+    fn pass_filters_are_half_power_at_their_frequency() {
+        let high_pass = Biquad::high_pass(100.0, SAMPLE_RATE);
+        assert_close(response(&high_pass, 0.0), 0.0);
+        assert_close(response(&high_pass, 100.0), std::f32::consts::FRAC_1_SQRT_2);
+        assert!((response(&high_pass, 10000.0) - 1.0).abs() < 0.01);
+
+        let low_pass = Biquad::low_pass(3400.0, SAMPLE_RATE);
+        assert_close(response(&low_pass, 0.0), 1.0);
+        assert_close(response(&low_pass, 3400.0), std::f32::consts::FRAC_1_SQRT_2);
+        assert!(response(&low_pass, 20000.0) < 0.05);
+    }
+
+    #[test]
+    fn peak_filter_boosts_its_frequency_by_its_gain() {
+        let filter = PeakFilter {
+            frequency: 3000.0,
+            gain: 6.0,
+            q: PRESENCE_Q,
+        };
+        let peak = Biquad::peak(filter, SAMPLE_RATE);
+        assert_close(response(&peak, 3000.0), 10.0f32.powf(6.0 / 20.0));
+        assert_close(response(&peak, 0.0), 1.0);
+
+        let cut = Biquad::peak(
+            PeakFilter {
+                gain: -6.0,
+                ..filter
+            },
+            SAMPLE_RATE,
+        );
+        assert_close(response(&cut, 3000.0), 10.0f32.powf(-6.0 / 20.0));
+
+        // a higher quality narrows the boost
+        let narrow = Biquad::peak(PeakFilter { q: 4.0, ..filter }, SAMPLE_RATE);
+        assert!(response(&narrow, 2000.0) < response(&peak, 2000.0));
+    }
+
+    #[test]
+    fn notch_removes_only_its_frequency() {
+        let notch = Biquad::notch(50.0, SAMPLE_RATE);
+        // the coefficients are rounded to f32, which leaves about -30 dB of the hum
+        assert!(response(&notch, 50.0) < 0.05);
+        assert!((response(&notch, 1000.0) - 1.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn filters_at_or_above_nyquist_are_left_out() {
+        let kinds = |settings: &EqSettings, sample_rate| -> Vec<FilterKind> {
+            filters(settings, sample_rate)
+                .into_iter()
+                .map(|(kind, _)| kind)
+                .collect()
+        };
+        assert_eq!(
+            kinds(&EqPreset::PhoneCall.settings(), SAMPLE_RATE),
+            vec![
+                FilterKind::HighPass,
+                FilterKind::Presence,
+                FilterKind::LowPass
+            ]
+        );
+        assert_eq!(
+            kinds(&EqPreset::PhoneCall.settings(), 4000),
+            vec![FilterKind::HighPass]
+        );
+        assert_eq!(
+            kinds(&EqPreset::RemoveHum60.settings(), 400),
+            vec![
+                FilterKind::HighPass,
+                FilterKind::Hum(1),
+                FilterKind::Hum(2),
+                FilterKind::Hum(3)
+            ]
+        );
+    }
+
+    #[test]
+    fn kept_filters_keep_their_memory() {
+        let control = Arc::new(EqControl::default());
+        control.set_settings(EqPreset::RemoveHum50.settings());
+        let mut eq = EqSource::new(source(1000), control);
+        for _ in 0..200 {
+            eq.next();
+        }
+        let high_pass_states: Vec<[f32; 2]> = eq.states.iter().map(|states| states[0]).collect();
+        assert!(high_pass_states.iter().all(|state| *state != [0.0; 2]));
+
+        eq.apply_settings(&EqPreset::PhoneCall.settings());
+        assert_eq!(
+            kinds(&eq),
+            vec![
+                FilterKind::HighPass,
+                FilterKind::Presence,
+                FilterKind::LowPass
+            ]
+        );
+        for (states, high_pass_state) in eq.states.iter().zip(high_pass_states) {
+            assert_eq!(states, &vec![high_pass_state, [0.0; 2], [0.0; 2]]);
+        }
+    }
+
+    #[test]
+    fn presets_switch_at_the_next_frame() {
+        let control = Arc::new(EqControl::default());
+        let mut eq = EqSource::new(source(100), control.clone());
+        assert!(kinds(&eq).is_empty());
+        eq.next();
+        control.set_settings(EqPreset::MuffledRoom.settings());
+        // the right sample of the frame is filtered like the left one
+        eq.next();
+        assert!(kinds(&eq).is_empty());
+        eq.next();
+        assert_eq!(kinds(&eq), vec![FilterKind::HighPass, FilterKind::Presence]);
+
+        control.set_settings(EqPreset::Off.settings());
+        eq.next();
+        eq.next();
+        assert!(kinds(&eq).is_empty());
+        assert_eq!(eq.count(), 195);
+    }
+
+    #[test]
+    fn presets_are_recognized_until_they_are_edited() {
+        for preset in EqPreset::ALL {
+            assert_eq!(EqPreset::matching(&preset.settings()), Some(preset));
+        }
+        let mut settings = EqPreset::MuffledRoom.settings();
+        settings.set_parameter(EqParameter::PresenceQ, 2.0);
+        assert_eq!(EqPreset::matching(&settings), None);
+        settings.set_parameter(EqParameter::PresenceQ, PRESENCE_Q);
+        assert_eq!(EqPreset::matching(&settings), Some(EqPreset::MuffledRoom));
+    }
+
+    #[test]
+    fn bands_start_with_their_default_and_keep_their_filter() {
+        let mut settings = EqSettings::default();
+        // the parameters of a band which is off can't be changed
+        settings.set_parameter(EqParameter::HighPass, 200.0);
+        assert!(!settings.is_enabled(EqBand::HighPass));
+        assert_eq!(settings.parameter(EqParameter::HighPass), DEFAULT_HIGH_PASS);
+
+        settings.set_enabled(EqBand::HighPass, true);
+        assert_eq!(settings.high_pass, Some(DEFAULT_HIGH_PASS));
+        settings.set_parameter(EqParameter::HighPass, 200.0);
+        settings.set_enabled(EqBand::HighPass, true);
+        assert_eq!(settings.high_pass, Some(200.0));
+
+        settings.set_enabled(EqBand::Presence, true);
+        settings.set_parameter(EqParameter::PresenceGain, -3.0);
+        assert_eq!(settings.parameter(EqParameter::PresenceGain), -3.0);
+        settings.set_enabled(EqBand::Presence, false);
+        assert_eq!(settings.presence, None);
+        assert_eq!(settings.high_pass, Some(200.0));
+    }
+
+    #[test]
+    fn presence_quality_defaults_for_older_projects() {
+        let filter: PeakFilter =
+            serde_json::from_str(r#"{"frequency": 2000.0, "gain": 3.0}"#).unwrap();
+        assert_eq!(filter.q, PRESENCE_Q);
+    }
+}
@@ -0,0 +1,280 @@
+use std::{mem, path::Path, sync::Arc, time::Duration};
+
+use rodio::Source;
+
+use super::{AudioError, FileSource, SeekableSource};
+
+/// How often the progress of the decoding is reported, in frames
+const PROGRESS_INTERVAL: u64 = 1 << 16;
+/// The bytes a decoded sample takes in memory
+const SAMPLE_SIZE: u64 = mem::size_of::<f32>() as u64;
+
+/// The samples of a whole audio file, decoded into memory
+pub struct DecodedAudio {
+    pub channels: u16,
+    pub sample_rate: u32,
+    /// the interleaved samples of all channels
+    pub samples: Vec<f32>,
+}
+
+impl DecodedAudio {
+    /// Decodes all of the file at `path`. This takes a while for long files and should be run on
+    /// a background thread.
+    ///
+    /// Returns `None` if the decoded samples would take more than `max_size` bytes. If the
+    /// decoder knows the duration up front, nothing is decoded in this case, otherwise the
+    /// decoding stops once the samples exceed `max_size`.
+    ///
+    /// `progress` is called now and then with the fraction of the file which is decoded, or
+    /// `None` if the decoder can't tell the duration of the file up front.
+    pub fn decode<P: AsRef<Path>, F: FnMut(Option<f64>)>(
+        path: &P,
+        max_size: u64,
+        progress: F,
+    ) -> Result<Option<Self>, AudioError> {
+        Ok(Self::decode_source(
+            FileSource::open(path)?,
+            max_size,
+            progress,
+        ))
+    }
+
+    /// Collects the samples of `source` like [`DecodedAudio::decode`] does for a file.
+    fn decode_source<S: Source<Item = f32>, F: FnMut(Option<f64>)>(
+        source: S,
+        max_size: u64,
+        mut progress: F,
+    ) -> Option<Self> {
+        let channels = source.channels();
+        let sample_rate = source.sample_rate();
+        let expected_frames = source
+            .total_duration()
+            .map(|duration| (duration.as_secs_f64() * sample_rate as f64) as u64);
+        let max_samples = max_size / SAMPLE_SIZE;
+        if expected_frames.map_or(false, |frames| frames * channels as u64 > max_samples) {
+            return None;
+        }
+        let mut samples = Vec::with_capacity(
+            expected_frames.map_or(0, |frames| frames as usize * channels as usize),
+        );
+        let progress_interval = PROGRESS_INTERVAL as usize * channels.max(1) as usize;
+        progress(Some(0.0).filter(|_| expected_frames.is_some()));
+        for sample in source {
+            // without a duration up front, this stops the decoding as soon as the limit is
+            // reached instead of decoding the whole file
+            if samples.len() as u64 >= max_samples {
+                return None;
+            }
+            samples.push(sample);
+            if samples.len() % progress_interval == 0 {
+                let frame = (samples.len() / channels as usize) as u64;
+                progress(expected_frames.map(|frames| (frame as f64 / frames as f64).min(1.0)));
+            }
+        }
+        Some(DecodedAudio {
+            channels,
+            sample_rate,
+            samples,
+        })
+    }
+
+    pub fn frames(&self) -> u64 {
+        (self.samples.len() / self.channels as usize) as u64
+    }
+
+    pub fn duration(&self) -> Duration {
+        Duration::from_secs_f64(self.frames() as f64 / self.sample_rate as f64)
+    }
+}
+
+/// A source which plays [`DecodedAudio`]. Unlike a [`FileSource`], it jumps to any frame right
+/// away.
+pub struct MemorySource {
+    audio: Arc<DecodedAudio>,
+    /// the index of the next sample
+    position: usize,
+}
+
+impl MemorySource {
+    pub fn new(audio: Arc<DecodedAudio>) -> Self {
+        MemorySource { audio, position: 0 }
+    }
+}
+
+impl SeekableSource for MemorySource {
+    fn seek(&mut self, frame: u64) -> Result<(), AudioError> {
+        self.position = (frame as usize)
+            .saturating_mul(self.audio.channels as usize)
+            .min(self.audio.samples.len());
+        Ok(())
+    }
+}
+
+impl Source for MemorySource {
+    fn current_frame_len(&self) -> Option<usize> {
+        Some(self.audio.samples.len() - self.position)
+    }
+
+    fn channels(&self) -> u16 {
+        self.audio.channels
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.audio.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        Some(self.audio.duration())
+    }
+}
+
+impl Iterator for MemorySource {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let sample = *self.audio.samples.get(self.position)?;
+        self.position += 1;
+        Some(sample)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.audio.samples.len() - self.position;
+        (remaining, Some(remaining))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, time::Duration};
+
+    use rodio::Source;
+
+    use super::{DecodedAudio, MemorySource, SAMPLE_SIZE};
+    use crate::audio::SeekableSource;
+
+    /// A stereo source which counts up from 0.0. It has `len` samples or never ends.
+    struct CountingSource {
+        next: usize,
+        len: Option<usize>,
+        duration: Option<Duration>,
+    }
+
+    impl Source for CountingSource {
+        fn current_frame_len(&self) -> Option<usize> {
+            None
+        }
+
+        fn channels(&self) -> u16 {
+            2
+        }
+
+        fn sample_rate(&self) -> u32 {
+            100
+        }
+
+        fn total_duration(&self) -> Option<Duration> {
+            self.duration
+        }
+    }
+
+    impl Iterator for CountingSource {
+        type Item = f32;
+
+        fn next(&mut self) -> Option<Self::Item> {
+            if self.len.map_or(false, |len| self.next >= len) {
+                return None;
+            }
+            self.next += 1;
+            Some((self.next - 1) as f32)
+        }
+    }
+
+    fn counting_source(len: Option<usize>, duration: Option<Duration>) -> CountingSource {
+        CountingSource {
+            next: 0,
+            len,
+            duration,
+        }
+    }
+
+    fn stereo_audio(frames: usize) -> Arc<DecodedAudio> {
+        Arc::new(DecodedAudio {
+            channels: 2,
+            sample_rate: 100,
+            samples: (0..frames * 2).map(|sample| sample as f32).collect(),
+        })
+    }
+
+    #[test]
+    fn decode_collects_the_samples() {
+        let source = counting_source(Some(200), Some(Duration::from_secs(1)));
+        let audio = DecodedAudio::decode_source(source, 200 * SAMPLE_SIZE, |_| {}).unwrap();
+        assert_eq!(audio.channels, 2);
+        assert_eq!(audio.sample_rate, 100);
+        assert_eq!(audio.frames(), 100);
+        assert_eq!(audio.duration(), Duration::from_secs(1));
+        assert_eq!(audio.samples[199], 199.0);
+    }
+
+    #[test]
+    fn decode_skips_sources_which_are_too_long_without_decoding() {
+        // the source would return samples forever, so the test hangs if any are decoded
+        let source = counting_source(None, Some(Duration::from_secs(10)));
+        let mut progress = vec![];
+        let audio = DecodedAudio::decode_source(source, 1000 * SAMPLE_SIZE, |fraction| {
+            progress.push(fraction)
+        });
+        assert!(audio.is_none());
+        assert!(progress.is_empty());
+    }
+
+    #[test]
+    fn decode_stops_at_the_limit_without_a_duration() {
+        let source = counting_source(None, None);
+        let mut progress = vec![];
+        let audio = DecodedAudio::decode_source(source, 1000 * SAMPLE_SIZE, |fraction| {
+            progress.push(fraction)
+        });
+        assert!(audio.is_none());
+        assert_eq!(progress, vec![None]);
+
+        let source = counting_source(Some(1000), None);
+        let audio = DecodedAudio::decode_source(source, 1000 * SAMPLE_SIZE, |_| {}).unwrap();
+        assert_eq!(audio.samples.len(), 1000);
+    }
+
+    #[test]
+    fn memory_source_seeks_to_frames() {
+        let mut source = MemorySource::new(stereo_audio(10));
+        source.seek(3).unwrap();
+        assert_eq!(source.next(), Some(6.0));
+        assert_eq!(source.next(), Some(7.0));
+        source.seek(0).unwrap();
+        assert_eq!(source.next(), Some(0.0));
+        source.seek(9).unwrap();
+        assert_eq!(source.collect::<Vec<_>>(), vec![18.0, 19.0]);
+    }
+
+    #[test]
+    fn memory_source_is_empty_after_seeking_past_its_end() {
+        let mut source = MemorySource::new(stereo_audio(10));
+        source.seek(10).unwrap();
+        assert_eq!(source.next(), None);
+        source.seek(u64::MAX).unwrap();
+        assert_eq!(source.next(), None);
+        assert_eq!(source.current_frame_len(), Some(0));
+        source.seek(5).unwrap();
+        assert_eq!(source.next(), Some(10.0));
+    }
+
+    #[test]
+    fn memory_source_frame_ends_with_the_audio() {
+        let mut source = MemorySource::new(stereo_audio(10));
+        assert_eq!(source.current_frame_len(), Some(20));
+        source.next();
+        assert_eq!(source.current_frame_len(), Some(19));
+        source.seek(8).unwrap();
+        assert_eq!(source.current_frame_len(), Some(4));
+        assert_eq!(source.total_duration(), Some(Duration::from_millis(100)));
+    }
+}
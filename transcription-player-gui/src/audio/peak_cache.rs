@@ -0,0 +1,419 @@
+use std::{
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, Read, Seek, Write},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use super::{
+    peaks::{PeakLevel, Peaks},
+    AudioError,
+};
+
+/// The first bytes of every peak file
+const MAGIC: &[u8; 4] = b"TRPK";
+/// The version of the peak file format. Files of other versions are recomputed.
+const VERSION: u32 = 1;
+/// The extension of the peak files in the cache directory
+const EXTENSION: &str = "peaks";
+/// The number of bytes of a peak in a peak file, its minimum and maximum
+const PEAK_SIZE: u64 = 4;
+
+/// The size and modification time of an audio file. The peaks are recomputed when either
+/// changes.
+#[derive(PartialEq, Eq, Debug)]
+struct SourceKey {
+    path: String,
+    size: u64,
+    /// nanoseconds since the unix epoch
+    modified: u64,
+}
+
+impl SourceKey {
+    fn of(path: &Path) -> io::Result<Self> {
+        let metadata = fs::metadata(path)?;
+        let modified = metadata
+            .modified()?
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos() as u64;
+        Ok(SourceKey {
+            path: path.to_string_lossy().into_owned(),
+            size: metadata.len(),
+            modified,
+        })
+    }
+}
+
+/// A cache of the peaks of audio files in the XDG cache directory, so that the waveform of a
+/// long recording doesn't have to be decoded again every time it is opened.
+///
+/// There is one peak file per audio path. It stores the size and the modification time of the
+/// audio file and is recomputed when they don't match anymore. The peaks are quantized to 16
+/// bits, like in the `.dat` files of audiowaveform, and all levels are stored so that every zoom
+/// level is available right away.
+///
+/// When the cache grows larger than its maximal size, the least recently used files are removed.
+#[derive(Clone, Debug)]
+pub struct PeakCache {
+    dir: PathBuf,
+    max_size: u64,
+}
+
+impl PeakCache {
+    pub fn new(max_size: u64) -> Self {
+        Self::with_dir(
+            glib::user_cache_dir().join("transcrible").join("peaks"),
+            max_size,
+        )
+    }
+
+    /// A cache which stores its peak files in `dir`
+    fn with_dir(dir: PathBuf, max_size: u64) -> Self {
+        PeakCache { dir, max_size }
+    }
+
+    pub fn set_max_size(&mut self, max_size: u64) {
+        self.max_size = max_size;
+        self.evict();
+    }
+
+    /// Loads the peaks of `path` from the cache, or computes and caches them if they aren't
+    /// cached or the file changed since.
+    pub fn load_or_compute(&self, path: &Path) -> Result<Peaks, AudioError> {
+        let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        let key = match SourceKey::of(&path) {
+            Ok(key) => key,
+            // the cache can't tell whether the file changed, so the peaks aren't cached
+            Err(_) => return Peaks::compute(&path),
+        };
+        let entry = self.entry_path(&key.path);
+
+        match self.read(&entry, &key) {
+            Ok(Some(peaks)) => {
+                // the modification time of the peak file is the last use for the eviction
+                if let Err(err) = File::options()
+                    .write(true)
+                    .open(&entry)
+                    .and_then(|file| file.set_modified(SystemTime::now()))
+                {
+                    log::debug!("Couldn't update the last use of {:?}: {}", entry, err);
+                }
+                return Ok(peaks);
+            }
+            Ok(None) => {}
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+            Err(err) => log::warn!("Couldn't read the cached peaks {:?}: {}", entry, err),
+        }
+
+        let peaks = match Peaks::compute(&path) {
+            Ok(peaks) => peaks,
+            Err(err) => {
+                self.invalidate(&path);
+                return Err(err);
+            }
+        };
+        if let Err(err) = self.write(&entry, &key, &peaks) {
+            log::warn!("Couldn't cache the peaks of {:?}: {}", path, err);
+        }
+        self.evict();
+        Ok(peaks)
+    }
+
+    /// Removes the cached peaks of `path`
+    pub fn invalidate(&self, path: &Path) {
+        let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        let entry = self.entry_path(&path.to_string_lossy());
+        match fs::remove_file(&entry) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => {
+                log::warn!("Couldn't remove the cached peaks {:?}: {}", entry, err)
+            }
+            _ => {}
+        }
+    }
+
+    /// The path of the peak file of the audio file at `path`
+    fn entry_path(&self, path: &str) -> PathBuf {
+        let hash = glib::compute_checksum_for_string(glib::ChecksumType::Sha256, path)
+            .map(|hash| hash.to_string())
+            .unwrap_or_default();
+        self.dir.join(format!("{}.{}", hash, EXTENSION))
+    }
+
+    /// Reads the peak file `entry`. Returns `None` if it belongs to another version of the audio
+    /// file or of the format.
+    fn read(&self, entry: &Path, key: &SourceKey) -> io::Result<Option<Peaks>> {
+        let file = File::open(entry)?;
+        let file_len = file.metadata()?.len();
+        let mut reader = BufReader::new(file);
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC || read_u32(&mut reader)? != VERSION {
+            return Ok(None);
+        }
+        let path_len = read_u32(&mut reader)? as usize;
+        check_remaining(&mut reader, file_len, path_len as u64)?;
+        let mut path = vec![0; path_len];
+        reader.read_exact(&mut path)?;
+        let cached_key = SourceKey {
+            path: String::from_utf8_lossy(&path).into_owned(),
+            size: read_u64(&mut reader)?,
+            modified: read_u64(&mut reader)?,
+        };
+        if &cached_key != key {
+            return Ok(None);
+        }
+
+        let sample_rate = read_u32(&mut reader)?;
+        let frames = read_u64(&mut reader)?;
+        let level_count = read_u32(&mut reader)?;
+        let mut levels = vec![];
+        for _ in 0..level_count {
+            let frames_per_peak = read_u64(&mut reader)?;
+            let peak_count = read_u64(&mut reader)?;
+            // a damaged file could otherwise make the allocation below abort the app
+            check_remaining(&mut reader, file_len, peak_count.saturating_mul(PEAK_SIZE))?;
+            let mut peaks = Vec::with_capacity(peak_count as usize);
+            for _ in 0..peak_count {
+                let min = dequantize(read_i16(&mut reader)?);
+                let max = dequantize(read_i16(&mut reader)?);
+                peaks.push((min, max));
+            }
+            levels.push(PeakLevel {
+                frames_per_peak,
+                peaks,
+            });
+        }
+        Ok(Some(Peaks {
+            sample_rate,
+            frames,
+            levels,
+        }))
+    }
+
+    /// Writes the peak file `entry`. It is written to a temporary file with a unique name first,
+    /// so that a reader never sees a partially written file and two threads which compute the
+    /// peaks of the same file don't write into the same temporary file.
+    fn write(&self, entry: &Path, key: &SourceKey, peaks: &Peaks) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        let temp_entry = tempfile::NamedTempFile::new_in(&self.dir)?;
+        let mut writer = BufWriter::new(temp_entry.as_file());
+        writer.write_all(MAGIC)?;
+        writer.write_all(&VERSION.to_le_bytes())?;
+        writer.write_all(&(key.path.len() as u32).to_le_bytes())?;
+        writer.write_all(key.path.as_bytes())?;
+        writer.write_all(&key.size.to_le_bytes())?;
+        writer.write_all(&key.modified.to_le_bytes())?;
+
+        writer.write_all(&peaks.sample_rate.to_le_bytes())?;
+        writer.write_all(&peaks.frames.to_le_bytes())?;
+        writer.write_all(&(peaks.levels.len() as u32).to_le_bytes())?;
+        for level in &peaks.levels {
+            writer.write_all(&level.frames_per_peak.to_le_bytes())?;
+            writer.write_all(&(level.peaks.len() as u64).to_le_bytes())?;
+            for (min, max) in &level.peaks {
+                writer.write_all(&quantize(*min).to_le_bytes())?;
+                writer.write_all(&quantize(*max).to_le_bytes())?;
+            }
+        }
+        writer.flush()?;
+        drop(writer);
+        temp_entry.persist(entry)?;
+        Ok(())
+    }
+
+    /// Removes the least recently used peak files until the cache is smaller than its maximal
+    /// size
+    fn evict(&self) {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(_) => return,
+        };
+        let mut entries: Vec<(PathBuf, u64, SystemTime)> = entries
+            .filter_map(|entry| {
+                let entry = entry.ok()?;
+                let path = entry.path();
+                if path.extension()? != EXTENSION {
+                    return None;
+                }
+                let metadata = entry.metadata().ok()?;
+                Some((path, metadata.len(), metadata.modified().ok()?))
+            })
+            .collect();
+        entries.sort_by_key(|(_, _, modified)| *modified);
+
+        let mut size: u64 = entries.iter().map(|(_, size, _)| size).sum();
+        for (path, entry_size, _) in entries {
+            if size <= self.max_size {
+                break;
+            }
+            match fs::remove_file(&path) {
+                Ok(()) => size -= entry_size,
+                Err(err) => log::warn!("Couldn't evict the cached peaks {:?}: {}", path, err),
+            }
+        }
+    }
+}
+
+/// Fails if fewer than `needed` bytes are left after the current position of `reader`
+fn check_remaining(reader: &mut impl Seek, file_len: u64, needed: u64) -> io::Result<()> {
+    let position = reader.stream_position()?;
+    if file_len.saturating_sub(position) < needed {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "the peak file is shorter than its header says",
+        ));
+    }
+    Ok(())
+}
+
+fn quantize(sample: f32) -> i16 {
+    (sample.clamp(-1.0, 1.0) * i16::MAX as f32).round() as i16
+}
+
+fn dequantize(value: i16) -> f32 {
+    value as f32 / i16::MAX as f32
+}
+
+fn read_u32(reader: &mut impl Read) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_u64(reader: &mut impl Read) -> io::Result<u64> {
+    let mut bytes = [0; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn read_i16(reader: &mut impl Read) -> io::Result<i16> {
+    let mut bytes = [0; 2];
+    reader.read_exact(&mut bytes)?;
+    Ok(i16::from_le_bytes(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn key(path: &str) -> SourceKey {
+        SourceKey {
+            path: path.to_string(),
+            size: 1234,
+            modified: 5678,
+        }
+    }
+
+    fn peaks() -> Peaks {
+        Peaks {
+            sample_rate: 44100,
+            frames: 1024,
+            levels: vec![
+                PeakLevel {
+                    frames_per_peak: 256,
+                    peaks: vec![(-0.5, 0.5), (-1.0, 1.0), (0.0, 0.25), (-0.75, 0.0)],
+                },
+                PeakLevel {
+                    frames_per_peak: 1024,
+                    peaks: vec![(-1.0, 1.0)],
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn write_and_read_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = PeakCache::with_dir(dir.path().to_path_buf(), u64::MAX);
+        let entry = cache.entry_path("/audio/interview.mp3");
+        let original = peaks();
+        cache
+            .write(&entry, &key("/audio/interview.mp3"), &original)
+            .unwrap();
+
+        let read = cache
+            .read(&entry, &key("/audio/interview.mp3"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(read.sample_rate, original.sample_rate);
+        assert_eq!(read.frames, original.frames);
+        assert_eq!(read.levels.len(), original.levels.len());
+        for (read, original) in read.levels.iter().zip(&original.levels) {
+            assert_eq!(read.frames_per_peak, original.frames_per_peak);
+            assert_eq!(read.peaks.len(), original.peaks.len());
+            for ((min, max), (original_min, original_max)) in read.peaks.iter().zip(&original.peaks)
+            {
+                assert!((min - original_min).abs() < 1e-4);
+                assert!((max - original_max).abs() < 1e-4);
+            }
+        }
+    }
+
+    #[test]
+    fn changed_audio_file_is_not_read() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = PeakCache::with_dir(dir.path().to_path_buf(), u64::MAX);
+        let entry = cache.entry_path("/audio/interview.mp3");
+        cache
+            .write(&entry, &key("/audio/interview.mp3"), &peaks())
+            .unwrap();
+
+        let changed = SourceKey {
+            size: 4321,
+            ..key("/audio/interview.mp3")
+        };
+        assert!(cache.read(&entry, &changed).unwrap().is_none());
+    }
+
+    #[test]
+    fn truncated_file_is_an_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = PeakCache::with_dir(dir.path().to_path_buf(), u64::MAX);
+        let entry = cache.entry_path("/audio/interview.mp3");
+        cache
+            .write(&entry, &key("/audio/interview.mp3"), &peaks())
+            .unwrap();
+        let mut bytes = fs::read(&entry).unwrap();
+        // the peak count of the first level claims far more peaks than the file contains
+        let header_len = 4 + 4 + 4 + "/audio/interview.mp3".len() + 8 + 8 + 4 + 8 + 4 + 8;
+        bytes[header_len..header_len + 8].copy_from_slice(&u64::MAX.to_le_bytes());
+        bytes.truncate(header_len + 8 + 4);
+        fs::write(&entry, bytes).unwrap();
+
+        let err = cache
+            .read(&entry, &key("/audio/interview.mp3"))
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn evicts_least_recently_used_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut cache = PeakCache::with_dir(dir.path().to_path_buf(), u64::MAX);
+        let paths = ["/audio/a.mp3", "/audio/b.mp3", "/audio/c.mp3"];
+        let now = SystemTime::now();
+        for (index, path) in paths.iter().enumerate() {
+            let entry = cache.entry_path(path);
+            cache.write(&entry, &key(path), &peaks()).unwrap();
+            // a.mp3 was used longest ago, c.mp3 most recently
+            let used = now - Duration::from_secs(60 * (paths.len() - index) as u64);
+            File::options()
+                .write(true)
+                .open(&entry)
+                .unwrap()
+                .set_modified(used)
+                .unwrap();
+        }
+        let entry_size = fs::metadata(cache.entry_path(paths[0])).unwrap().len();
+
+        cache.set_max_size(entry_size * 2);
+
+        assert!(!cache.entry_path(paths[0]).exists());
+        assert!(cache.entry_path(paths[1]).exists());
+        assert!(cache.entry_path(paths[2]).exists());
+    }
+}
@@ -0,0 +1,219 @@
+use std::{path::Path, time::Duration};
+
+use rodio::Source;
+
+use super::{memory::DecodedAudio, AudioError, FileSource};
+
+/// The number of frames which are summarized by one peak of the finest level
+pub const FRAMES_PER_PEAK: u64 = 256;
+/// How many peaks of a level are summarized by one peak of the next coarser level
+const LEVEL_FACTOR: usize = 4;
+/// No coarser levels are created once a level has fewer peaks than this
+const MIN_LEVEL_PEAKS: usize = 1024;
+
+/// The peaks of an audio file at one resolution
+pub struct PeakLevel {
+    pub frames_per_peak: u64,
+    pub peaks: Vec<(f32, f32)>,
+}
+
+impl PeakLevel {
+    /// Summarizes `LEVEL_FACTOR` peaks of this level into one peak of the next coarser level
+    fn coarser(&self) -> PeakLevel {
+        PeakLevel {
+            frames_per_peak: self.frames_per_peak * LEVEL_FACTOR as u64,
+            peaks: self
+                .peaks
+                .chunks(LEVEL_FACTOR)
+                .filter_map(|chunk| chunk.iter().copied().reduce(merge))
+                .collect(),
+        }
+    }
+}
+
+/// The minimum and maximum sample of blocks of frames of an audio file, over all channels. It
+/// is used to draw the waveform.
+///
+/// The peaks are stored at multiple resolutions, from the finest to the coarsest, so that each
+/// zoom level can be drawn without summarizing a lot of peaks.
+pub struct Peaks {
+    pub sample_rate: u32,
+    /// the number of frames of the whole file
+    pub frames: u64,
+    pub levels: Vec<PeakLevel>,
+}
+
+fn merge((min_a, max_a): (f32, f32), (min_b, max_b): (f32, f32)) -> (f32, f32) {
+    (min_a.min(min_b), max_a.max(max_b))
+}
+
+impl Peaks {
+    /// Decodes the file at `path` and computes its peaks. This takes a while for long files and
+    /// should be run on a background thread.
+    pub fn compute<P: AsRef<Path>>(path: &P) -> Result<Self, AudioError> {
+        let source = FileSource::open(path)?;
+        let channels = source.channels();
+        let sample_rate = source.sample_rate();
+        Ok(Self::from_samples(source, channels, sample_rate))
+    }
+
+    /// Computes the peaks of audio which is already decoded
+    pub fn from_decoded(audio: &DecodedAudio) -> Self {
+        Self::from_samples(
+            audio.samples.iter().copied(),
+            audio.channels,
+            audio.sample_rate,
+        )
+    }
+
+    /// Computes the peaks of the interleaved `samples`
+    fn from_samples<I: Iterator<Item = f32>>(samples: I, channels: u16, sample_rate: u32) -> Self {
+        // a source without channels has no samples, this only keeps the divisions defined
+        let channels = channels.max(1) as usize;
+        let samples_per_peak = FRAMES_PER_PEAK as usize * channels;
+        let mut peaks = vec![];
+        let mut sample_count = 0usize;
+        let (mut min, mut max) = (0.0f32, 0.0f32);
+        for sample in samples {
+            min = min.min(sample);
+            max = max.max(sample);
+            sample_count += 1;
+            if sample_count % samples_per_peak == 0 {
+                peaks.push((min, max));
+                min = 0.0;
+                max = 0.0;
+            }
+        }
+        if sample_count % samples_per_peak != 0 {
+            peaks.push((min, max));
+        }
+
+        let mut levels = vec![PeakLevel {
+            frames_per_peak: FRAMES_PER_PEAK,
+            peaks,
+        }];
+        while let Some(level) = levels
+            .last()
+            .filter(|level| level.peaks.len() >= MIN_LEVEL_PEAKS)
+        {
+            let coarser = level.coarser();
+            levels.push(coarser);
+        }
+        Peaks {
+            sample_rate,
+            frames: (sample_count / channels) as u64,
+            levels,
+        }
+    }
+
+    pub fn duration(&self) -> Duration {
+        if self.sample_rate == 0 {
+            return Duration::ZERO;
+        }
+        Duration::from_secs_f64(self.frames as f64 / self.sample_rate as f64)
+    }
+
+    /// The minimum and maximum of the peaks between the fractions `start` and `end` of the
+    /// file. The coarsest level which still has a peak in the range is used. Returns `None` if
+    /// the range contains no peak.
+    pub fn range(&self, start: f64, end: f64) -> Option<(f32, f32)> {
+        let peaks = &self
+            .levels
+            .iter()
+            .rev()
+            .find(|level| (end - start) * level.peaks.len() as f64 >= 1.0)
+            .or_else(|| self.levels.first())?
+            .peaks;
+        let len = peaks.len() as f64;
+        let start = (start * len).floor().max(0.0) as usize;
+        let end = ((end * len).ceil() as usize).min(peaks.len());
+        peaks
+            .get(start..end.max(start + 1).min(peaks.len()))?
+            .iter()
+            .copied()
+            .reduce(merge)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{Peaks, FRAMES_PER_PEAK, LEVEL_FACTOR, MIN_LEVEL_PEAKS};
+
+    /// Mono peaks where the peak with the index `i` goes from `-i` to `i`
+    fn numbered_peaks(count: usize) -> Peaks {
+        let samples = (0..count).flat_map(|peak| {
+            let value = peak as f32;
+            (0..FRAMES_PER_PEAK).map(move |frame| if frame % 2 == 0 { -value } else { value })
+        });
+        Peaks::from_samples(samples, 1, 1000)
+    }
+
+    #[test]
+    fn peaks_cover_all_channels() {
+        let samples = [0.5, -0.25, 0.1, -0.75].repeat(FRAMES_PER_PEAK as usize / 2 + 1);
+        let peaks = Peaks::from_samples(samples.into_iter(), 2, 1000);
+        assert_eq!(peaks.frames, FRAMES_PER_PEAK + 2);
+        assert_eq!(peaks.levels.len(), 1);
+        assert_eq!(peaks.levels[0].peaks, vec![(-0.75, 0.5), (-0.75, 0.5)]);
+        assert_eq!(peaks.duration(), Duration::from_millis(258));
+    }
+
+    #[test]
+    fn coarser_levels_summarize_the_finer_ones() {
+        let peaks = numbered_peaks(MIN_LEVEL_PEAKS * LEVEL_FACTOR);
+        assert_eq!(peaks.levels.len(), 3);
+        assert_eq!(peaks.levels[1].peaks.len(), MIN_LEVEL_PEAKS);
+        assert_eq!(
+            peaks.levels[1].frames_per_peak,
+            FRAMES_PER_PEAK * LEVEL_FACTOR as u64
+        );
+        assert_eq!(peaks.levels[1].peaks[1], (-7.0, 7.0));
+        assert_eq!(peaks.levels[2].peaks.len(), MIN_LEVEL_PEAKS / LEVEL_FACTOR);
+    }
+
+    #[test]
+    fn sources_without_channels_or_samples_have_no_peaks() {
+        let peaks = Peaks::from_samples(std::iter::empty(), 0, 0);
+        assert_eq!(peaks.frames, 0);
+        assert_eq!(peaks.duration(), Duration::ZERO);
+        assert!(peaks.levels[0].peaks.is_empty());
+        assert_eq!(peaks.range(0.0, 1.0), None);
+    }
+
+    #[test]
+    fn range_covers_the_whole_file() {
+        let peaks = numbered_peaks(10);
+        assert_eq!(peaks.range(0.0, 1.0), Some((-9.0, 9.0)));
+        assert_eq!(peaks.range(-1.0, 2.0), Some((-9.0, 9.0)));
+    }
+
+    #[test]
+    fn range_at_the_boundaries() {
+        let peaks = numbered_peaks(10);
+        assert_eq!(peaks.range(0.0, 0.1), Some((0.0, 0.0)));
+        assert_eq!(peaks.range(0.0, 0.11), Some((-1.0, 1.0)));
+        assert_eq!(peaks.range(0.9, 1.0), Some((-9.0, 9.0)));
+        assert_eq!(peaks.range(0.95, 1.0), Some((-9.0, 9.0)));
+        // ranges smaller than a peak still show the peak they are in
+        assert_eq!(peaks.range(0.42, 0.43), Some((-4.0, 4.0)));
+        assert_eq!(peaks.range(1.0, 1.0), None);
+        assert_eq!(peaks.range(1.5, 2.0), None);
+    }
+
+    #[test]
+    fn range_uses_the_coarsest_level_with_a_peak() {
+        let peaks = numbered_peaks(MIN_LEVEL_PEAKS * LEVEL_FACTOR);
+        // the whole file is summarized by the coarsest level, a tiny range by the finest
+        assert_eq!(
+            peaks.range(0.0, 1.0),
+            Some((
+                -((MIN_LEVEL_PEAKS * LEVEL_FACTOR - 1) as f32),
+                (MIN_LEVEL_PEAKS * LEVEL_FACTOR - 1) as f32
+            ))
+        );
+        let peak = 1.0 / (MIN_LEVEL_PEAKS * LEVEL_FACTOR) as f64;
+        assert_eq!(peaks.range(5.0 * peak, 5.5 * peak), Some((-5.0, 5.0)));
+    }
+}
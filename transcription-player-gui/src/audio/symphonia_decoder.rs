@@ -0,0 +1,523 @@
+use std::{cmp, fs::File, io, path::Path, time::Duration};
+
+use rodio::Source;
+use symphonia::core::{
+    audio::SampleBuffer,
+    codecs::{self, CodecParameters, CodecType, DecoderOptions},
+    errors::{Error as SymphoniaError, SeekErrorKind},
+    formats::{FormatOptions, FormatReader, SeekMode, SeekTo},
+    io::MediaSourceStream,
+    meta::MetadataOptions,
+    probe::Hint,
+    units::TimeBase,
+};
+
+use super::AudioError;
+
+/// Opus is always decoded at this sample rate
+const OPUS_SAMPLE_RATE: u32 = 48000;
+/// The number of frames of the longest opus packet, which is 120ms long
+const MAX_OPUS_PACKET_FRAMES: usize = OPUS_SAMPLE_RATE as usize * 120 / 1000;
+/// How far before the target an opus stream is entered when seeking. The decoder needs 80ms to
+/// converge after a reset.
+const OPUS_PRE_ROLL: u64 = OPUS_SAMPLE_RATE as u64 * 80 / 1000;
+
+enum PacketDecoder {
+    Symphonia(Box<dyn codecs::Decoder>),
+    /// symphonia can read the ogg container of opus files, but it has no decoder for opus
+    Opus {
+        decoder: opus::Decoder,
+        channels: usize,
+        /// the number of frames at the start which are still to be dropped
+        pre_skip: usize,
+    },
+}
+
+/// Decodes all supported formats. The container is read by symphonia, which can jump to the
+/// packet of any timestamp, opus packets are decoded by libopus.
+pub struct SymphoniaDecoder {
+    path: String,
+    format: Box<dyn FormatReader>,
+    track_id: u32,
+    decoder: PacketDecoder,
+    channels: u16,
+    sample_rate: u32,
+    total_duration: Option<Duration>,
+    /// the time base of the timestamps of the packets, `None` if they count frames
+    time_base: Option<TimeBase>,
+    /// the timestamp of the first frame which is played, opus streams start after the pre-skip
+    start_ts: u64,
+    /// after a seek, the samples before this timestamp are dropped
+    skip_to: Option<u64>,
+    /// true if the last seek went past the end of the track
+    ended: bool,
+    /// the interleaved samples of the last decoded packet
+    buffer: Vec<f32>,
+    /// the index of the next sample in `buffer`
+    position: usize,
+}
+
+impl SymphoniaDecoder {
+    pub fn open(path: &Path) -> Result<Self, AudioError> {
+        let path_name = path.display().to_string();
+        let file = File::open(path).map_err(|source| AudioError::LoadError {
+            path: path_name.clone(),
+            source,
+        })?;
+        let stream = MediaSourceStream::new(Box::new(file), Default::default());
+        let mut hint = Hint::new();
+        if let Some(extension) = path.extension().and_then(|extension| extension.to_str()) {
+            hint.with_extension(extension);
+        }
+        let format = symphonia::default::get_probe()
+            .format(
+                &hint,
+                stream,
+                &FormatOptions::default(),
+                &MetadataOptions::default(),
+            )
+            .map_err(|source| AudioError::FormatError {
+                path: path_name.clone(),
+                source,
+            })?
+            .format;
+
+        let track = format
+            .tracks()
+            .iter()
+            .find(|track| track.codec_params.codec != codecs::CODEC_TYPE_NULL)
+            .ok_or_else(|| AudioError::NoAudioTrackError {
+                path: path_name.clone(),
+            })?;
+        let track_id = track.id;
+        let params = track.codec_params.clone();
+        let decoder = if params.codec == codecs::CODEC_TYPE_OPUS {
+            open_opus(&path_name, &params)?
+        } else {
+            let decoder = symphonia::default::get_codecs()
+                .make(&params, &DecoderOptions::default())
+                .map_err(|err| match err {
+                    SymphoniaError::Unsupported(_) => AudioError::MissingCodecError {
+                        path: path_name.clone(),
+                        codec: codec_name(params.codec),
+                    },
+                    source => AudioError::CodecError {
+                        path: path_name.clone(),
+                        source,
+                    },
+                })?;
+            PacketDecoder::Symphonia(decoder)
+        };
+
+        let mut source = SymphoniaDecoder {
+            path: path_name,
+            format,
+            track_id,
+            decoder,
+            channels: 0,
+            sample_rate: 0,
+            total_duration: None,
+            time_base: params.time_base,
+            start_ts: match params.codec {
+                codecs::CODEC_TYPE_OPUS => opus_pre_skip(&params) as u64,
+                _ => 0,
+            },
+            skip_to: None,
+            ended: false,
+            buffer: vec![],
+            position: 0,
+        };
+        // the channels and the sample rate are only known for sure once a packet is decoded
+        if !source.decode_packet() || source.channels == 0 || source.sample_rate == 0 {
+            return Err(AudioError::NoAudioTrackError { path: source.path });
+        }
+        source.total_duration = params.n_frames.map(|frames| {
+            let frames = match &source.decoder {
+                // the frames of an opus file include the ones which are skipped at the start
+                PacketDecoder::Opus { .. } => frames.saturating_sub(opus_pre_skip(&params) as u64),
+                PacketDecoder::Symphonia(_) => frames,
+            };
+            Duration::from_secs_f64(frames as f64 / source.sample_rate as f64)
+        });
+        Ok(source)
+    }
+
+    /// Moves to `frame`. Only the packets around `frame` are decoded, the container jumps to them
+    /// directly, so this is fast enough to be done while the audio plays.
+    pub fn seek(&mut self, frame: u64) -> Result<(), AudioError> {
+        let target = self.start_ts + self.frames_to_ts(frame);
+        let pre_roll = match self.decoder {
+            PacketDecoder::Opus { .. } => OPUS_PRE_ROLL,
+            PacketDecoder::Symphonia(_) => 0,
+        };
+        self.buffer.clear();
+        self.position = 0;
+        let seeked = self.format.seek(
+            SeekMode::Accurate,
+            SeekTo::TimeStamp {
+                ts: target.saturating_sub(pre_roll),
+                track_id: self.track_id,
+            },
+        );
+        match seeked {
+            Ok(_) => self.ended = false,
+            Err(SymphoniaError::SeekError(SeekErrorKind::OutOfRange)) => {
+                self.ended = true;
+                return Ok(());
+            }
+            Err(source) => {
+                return Err(AudioError::SeekError {
+                    path: self.path.clone(),
+                    source,
+                })
+            }
+        }
+        match &mut self.decoder {
+            PacketDecoder::Symphonia(decoder) => decoder.reset(),
+            PacketDecoder::Opus {
+                decoder, pre_skip, ..
+            } => {
+                if let Err(err) = decoder.reset_state() {
+                    log::warn!("Couldn't reset the decoder of \"{}\": {}", self.path, err);
+                }
+                // the frames before the target are dropped by their timestamp instead
+                *pre_skip = 0;
+            }
+        }
+        self.skip_to = Some(target);
+        Ok(())
+    }
+
+    fn frames_to_ts(&self, frames: u64) -> u64 {
+        match self.time_base {
+            Some(time_base) if time_base.numer > 0 => {
+                (frames as u128 * time_base.denom as u128
+                    / (time_base.numer as u128 * self.sample_rate as u128)) as u64
+            }
+            _ => frames,
+        }
+    }
+
+    fn ts_to_frames(&self, ts: u64) -> u64 {
+        match self.time_base {
+            Some(time_base) if time_base.denom > 0 => {
+                (ts as u128 * time_base.numer as u128 * self.sample_rate as u128
+                    / time_base.denom as u128) as u64
+            }
+            _ => ts,
+        }
+    }
+
+    /// Drops the samples of the decoded packet which starts at `ts` and lie before the target
+    /// of the last seek
+    fn skip_to_seek_target(&mut self, ts: u64) {
+        if let Some(target) = self.skip_to {
+            let channels = cmp::max(self.channels as usize, 1);
+            let frames = self.buffer.len() / channels;
+            let skipped = cmp::min(
+                self.ts_to_frames(target.saturating_sub(ts)) as usize,
+                frames,
+            );
+            self.buffer.drain(..skipped * channels);
+            if !self.buffer.is_empty() {
+                self.skip_to = None;
+            }
+        }
+    }
+
+    /// Decodes the next packet of the track into `buffer`. Returns false at the end of the file.
+    ///
+    /// A packet which can't be decoded is skipped, so that a damaged file plays as far as
+    /// possible.
+    fn decode_packet(&mut self) -> bool {
+        if self.ended {
+            return false;
+        }
+        loop {
+            let packet = match self.format.next_packet() {
+                Ok(packet) => packet,
+                Err(SymphoniaError::IoError(err)) if err.kind() == io::ErrorKind::UnexpectedEof => {
+                    return false
+                }
+                Err(err) => {
+                    log::error!("Couldn't read audio file \"{}\": {}", self.path, err);
+                    return false;
+                }
+            };
+            if packet.track_id() != self.track_id {
+                continue;
+            }
+
+            match &mut self.decoder {
+                PacketDecoder::Symphonia(decoder) => match decoder.decode(&packet) {
+                    Ok(decoded) => {
+                        let spec = *decoded.spec();
+                        let mut samples = SampleBuffer::<f32>::new(decoded.capacity() as u64, spec);
+                        samples.copy_interleaved_ref(decoded);
+                        self.channels = spec.channels.count() as u16;
+                        self.sample_rate = spec.rate;
+                        self.buffer.clear();
+                        self.buffer.extend_from_slice(samples.samples());
+                    }
+                    Err(SymphoniaError::DecodeError(err)) => {
+                        log::warn!("Skipped a packet of \"{}\": {}", self.path, err);
+                        continue;
+                    }
+                    Err(err) => {
+                        log::error!("Couldn't decode audio file \"{}\": {}", self.path, err);
+                        return false;
+                    }
+                },
+                PacketDecoder::Opus {
+                    decoder,
+                    channels,
+                    pre_skip,
+                } => {
+                    self.buffer.resize(MAX_OPUS_PACKET_FRAMES * *channels, 0.0);
+                    match decoder.decode_float(&packet.data, &mut self.buffer, false) {
+                        Ok(frames) => {
+                            self.buffer.truncate(frames * *channels);
+                            trim_pre_skip(&mut self.buffer, *channels, pre_skip);
+                            self.channels = *channels as u16;
+                            self.sample_rate = OPUS_SAMPLE_RATE;
+                        }
+                        Err(err) => {
+                            log::warn!("Skipped a packet of \"{}\": {}", self.path, err);
+                            continue;
+                        }
+                    }
+                }
+            }
+            self.skip_to_seek_target(packet.ts());
+            self.position = 0;
+            if !self.buffer.is_empty() {
+                return true;
+            }
+        }
+    }
+}
+
+fn open_opus(path: &str, params: &CodecParameters) -> Result<PacketDecoder, AudioError> {
+    // the identification header of the ogg container knows the channels for sure
+    let channels = opus_header(params)
+        .map(|header| header[9] as usize)
+        .or_else(|| params.channels.map(|channels| channels.count()))
+        .unwrap_or(0);
+    let opus_channels = match channels {
+        1 => opus::Channels::Mono,
+        2 => opus::Channels::Stereo,
+        _ => {
+            return Err(AudioError::MissingCodecError {
+                path: path.to_string(),
+                codec: format!("Opus with {} channels", channels),
+            })
+        }
+    };
+    let decoder = opus::Decoder::new(OPUS_SAMPLE_RATE, opus_channels).map_err(|source| {
+        AudioError::OpusError {
+            path: path.to_string(),
+            source,
+        }
+    })?;
+    Ok(PacketDecoder::Opus {
+        decoder,
+        channels,
+        pre_skip: opus_pre_skip(params),
+    })
+}
+
+/// The `OpusHead` identification header, which symphonia passes on as extra data
+fn opus_header(params: &CodecParameters) -> Option<&[u8]> {
+    params
+        .extra_data
+        .as_deref()
+        .filter(|header| header.len() >= 19 && header.starts_with(b"OpusHead"))
+}
+
+/// The number of frames at the start of an opus stream which only prime the decoder
+fn opus_pre_skip(params: &CodecParameters) -> usize {
+    opus_header(params).map_or(0, |header| {
+        u16::from_le_bytes([header[10], header[11]]) as usize
+    })
+}
+
+/// Drops the frames at the start of the decoded opus packet in `buffer` which only prime the
+/// decoder. `pre_skip` is the number of these frames which are still left.
+fn trim_pre_skip(buffer: &mut Vec<f32>, channels: usize, pre_skip: &mut usize) {
+    let skipped = cmp::min(*pre_skip, buffer.len() / cmp::max(channels, 1));
+    buffer.drain(..skipped * channels);
+    *pre_skip -= skipped;
+}
+
+/// A readable name of `codec`, for the error which says that it isn't supported
+fn codec_name(codec: CodecType) -> String {
+    let name = match codec {
+        codecs::CODEC_TYPE_AAC => "AAC",
+        codecs::CODEC_TYPE_ALAC => "ALAC",
+        codecs::CODEC_TYPE_OPUS => "Opus",
+        codecs::CODEC_TYPE_VORBIS => "Vorbis",
+        codecs::CODEC_TYPE_FLAC => "FLAC",
+        codecs::CODEC_TYPE_MP3 => "MP3",
+        codecs::CODEC_TYPE_AC4 => "AC-4",
+        codecs::CODEC_TYPE_EAC3 => "E-AC-3",
+        codecs::CODEC_TYPE_WAVPACK => "WavPack",
+        _ => return format!("unknown ({:?})", codec),
+    };
+    name.to_string()
+}
+
+impl Source for SymphoniaDecoder {
+    fn current_frame_len(&self) -> Option<usize> {
+        Some(self.buffer.len() - self.position).filter(|len| *len > 0)
+    }
+
+    fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.total_duration
+    }
+}
+
+impl Iterator for SymphoniaDecoder {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.position >= self.buffer.len() && !self.decode_packet() {
+            return None;
+        }
+        let sample = self.buffer[self.position];
+        self.position += 1;
+        Some(sample)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path, time::Duration};
+
+    use rodio::Source;
+    use symphonia::core::codecs::{self, CodecParameters};
+
+    use super::{codec_name, opus_pre_skip, trim_pre_skip, SymphoniaDecoder};
+
+    const FRAMES: u32 = 5000;
+
+    /// Writes a 16 bit stereo wav file at 8000 Hz whose samples count up from 0
+    fn write_wav(path: &Path) {
+        let data: Vec<u8> = (0..FRAMES as i16 * 2)
+            .flat_map(|sample| sample.to_le_bytes())
+            .collect();
+        let mut wav = vec![];
+        wav.extend_from_slice(b"RIFF");
+        wav.extend_from_slice(&(36 + data.len() as u32).to_le_bytes());
+        wav.extend_from_slice(b"WAVEfmt ");
+        wav.extend_from_slice(&16u32.to_le_bytes());
+        wav.extend_from_slice(&1u16.to_le_bytes());
+        wav.extend_from_slice(&2u16.to_le_bytes());
+        wav.extend_from_slice(&8000u32.to_le_bytes());
+        wav.extend_from_slice(&(8000u32 * 4).to_le_bytes());
+        wav.extend_from_slice(&4u16.to_le_bytes());
+        wav.extend_from_slice(&16u16.to_le_bytes());
+        wav.extend_from_slice(b"data");
+        wav.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        wav.extend_from_slice(&data);
+        fs::write(path, wav).unwrap();
+    }
+
+    fn sample(index: u32) -> f32 {
+        index as f32 / 32768.0
+    }
+
+    fn open_wav() -> (tempfile::TempDir, SymphoniaDecoder) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("count.wav");
+        write_wav(&path);
+        let decoder = SymphoniaDecoder::open(&path).unwrap();
+        (dir, decoder)
+    }
+
+    #[test]
+    fn wav_files_are_decoded() {
+        let (_dir, decoder) = open_wav();
+        assert_eq!(decoder.channels(), 2);
+        assert_eq!(decoder.sample_rate(), 8000);
+        assert_eq!(decoder.total_duration(), Some(Duration::from_millis(625)));
+        let samples: Vec<f32> = decoder.collect();
+        assert_eq!(samples.len(), FRAMES as usize * 2);
+        assert!(samples
+            .iter()
+            .enumerate()
+            .all(|(index, value)| *value == sample(index as u32)));
+    }
+
+    #[test]
+    fn seek_lands_on_the_frame() {
+        let (_dir, mut decoder) = open_wav();
+        for frame in [1000, 0, 1, 4095, 4999, 2500] {
+            decoder.seek(frame).unwrap();
+            assert_eq!(decoder.next(), Some(sample(frame as u32 * 2)), "{}", frame);
+            assert_eq!(decoder.next(), Some(sample(frame as u32 * 2 + 1)));
+        }
+    }
+
+    #[test]
+    fn seek_past_the_end_ends_the_source() {
+        let (_dir, mut decoder) = open_wav();
+        decoder.seek(FRAMES as u64 + 100).unwrap();
+        assert_eq!(decoder.next(), None);
+        decoder.seek(10).unwrap();
+        assert_eq!(decoder.next(), Some(sample(20)));
+    }
+
+    #[test]
+    fn missing_files_are_reported() {
+        assert!(SymphoniaDecoder::open(Path::new("/nonexistent/file.opus")).is_err());
+    }
+
+    #[test]
+    fn pre_skip_is_trimmed_across_packets() {
+        let mut pre_skip = 3;
+        let mut buffer = vec![1.0, -1.0, 2.0, -2.0];
+        trim_pre_skip(&mut buffer, 2, &mut pre_skip);
+        assert!(buffer.is_empty());
+        assert_eq!(pre_skip, 1);
+
+        let mut buffer = vec![3.0, -3.0, 4.0, -4.0, 5.0, -5.0];
+        trim_pre_skip(&mut buffer, 2, &mut pre_skip);
+        assert_eq!(buffer, vec![4.0, -4.0, 5.0, -5.0]);
+        assert_eq!(pre_skip, 0);
+
+        let mut buffer = vec![6.0, -6.0];
+        trim_pre_skip(&mut buffer, 2, &mut pre_skip);
+        assert_eq!(buffer, vec![6.0, -6.0]);
+    }
+
+    #[test]
+    fn pre_skip_is_read_from_the_opus_header() {
+        let mut header = b"OpusHead".to_vec();
+        // version, channels, pre-skip, sample rate, gain and mapping family
+        header.extend_from_slice(&[1, 2, 0x38, 0x01, 0x80, 0xbb, 0, 0, 0, 0, 0]);
+        let mut params = CodecParameters::new();
+        params.with_extra_data(header.clone().into_boxed_slice());
+        assert_eq!(opus_pre_skip(&params), 312);
+
+        header.truncate(18);
+        params.with_extra_data(header.into_boxed_slice());
+        assert_eq!(opus_pre_skip(&params), 0);
+        assert_eq!(opus_pre_skip(&CodecParameters::new()), 0);
+    }
+
+    #[test]
+    fn codecs_have_readable_names() {
+        assert_eq!(codec_name(codecs::CODEC_TYPE_AAC), "AAC");
+        assert_eq!(codec_name(codecs::CODEC_TYPE_OPUS), "Opus");
+        assert_eq!(codec_name(codecs::CODEC_TYPE_AC4), "AC-4");
+        assert!(codec_name(codecs::CODEC_TYPE_PCM_S16LE).starts_with("unknown"));
+    }
+}
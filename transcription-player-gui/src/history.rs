@@ -0,0 +1,285 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::project::{self, LoopPoints};
+
+/// How many files are remembered, the ones which were opened least recently are forgotten first
+const MAX_ENTRIES: usize = 500;
+
+/// The state of the playback of a file when it was last open
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct FileState {
+    pub path: PathBuf,
+    #[serde(with = "project::millis")]
+    pub position: Duration,
+    #[serde(default, with = "project::optional_millis")]
+    pub duration: Option<Duration>,
+    pub speed: f64,
+    /// in semitones relative to the original pitch
+    pub pitch: f64,
+    #[serde(default, rename = "loop")]
+    pub loop_points: Option<LoopPoints>,
+    /// seconds since the unix epoch
+    pub last_opened: u64,
+}
+
+impl FileState {
+    /// Whether the playback stopped so close to the end that there is nothing left to continue
+    pub fn is_finished(&self) -> bool {
+        self.duration.map_or(false, |duration| {
+            self.position + Duration::from_secs(1) >= duration
+        })
+    }
+}
+
+/// Remembers where the playback of each file stopped, so that it continues there when the file is
+/// opened again. The files are stored by the hash of their path.
+pub struct History {
+    file: PathBuf,
+    entries: HashMap<String, FileState>,
+    /// whether the entries changed since they were saved
+    changed: bool,
+}
+
+impl History {
+    /// Loads the history from the data directory, it is empty if there is none yet
+    pub fn load() -> Self {
+        Self::load_from(
+            glib::user_data_dir()
+                .join("transcrible")
+                .join("history.json"),
+        )
+    }
+
+    fn load_from(file: PathBuf) -> Self {
+        let entries = match fs::read(&file) {
+            Ok(content) => serde_json::from_slice(&content).unwrap_or_else(|err| {
+                log::warn!("Couldn't parse the history {:?}: {}", file, err);
+                HashMap::new()
+            }),
+            Err(_) => HashMap::new(),
+        };
+        History {
+            file,
+            entries,
+            changed: false,
+        }
+    }
+
+    pub fn get(&self, path: &Path) -> Option<&FileState> {
+        self.entries.get(&key(path))
+    }
+
+    /// Remembers `state` for its file
+    pub fn remember(&mut self, mut state: FileState) {
+        let key = key(&state.path);
+        if let Some(previous) = self.entries.get(&key) {
+            state.last_opened = previous.last_opened;
+            if *previous == state {
+                return;
+            }
+        }
+        state.last_opened = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        self.entries.insert(key, state);
+        self.changed = true;
+        if self.entries.len() > MAX_ENTRIES {
+            if let Some(oldest) = self
+                .entries
+                .iter()
+                .min_by_key(|(_, state)| state.last_opened)
+                .map(|(key, _)| key.clone())
+            {
+                self.entries.remove(&oldest);
+            }
+        }
+    }
+
+    /// The `max` files which were opened most recently, the latest first. Files which don't exist
+    /// anymore are forgotten.
+    pub fn recent(&mut self, max: usize) -> Vec<FileState> {
+        let entry_count = self.entries.len();
+        self.entries.retain(|_, state| state.path.exists());
+        self.changed |= self.entries.len() != entry_count;
+
+        let mut recent: Vec<FileState> = self.entries.values().cloned().collect();
+        recent.sort_by_key(|state| std::cmp::Reverse(state.last_opened));
+        recent.truncate(max);
+        recent
+    }
+
+    /// Writes the history to the data directory, if it changed since it was saved last
+    pub fn save(&mut self) {
+        if !self.changed {
+            return;
+        }
+        let result = fs::create_dir_all(self.file.parent().unwrap_or_else(|| Path::new("")))
+            .and_then(|_| {
+                let content =
+                    serde_json::to_vec(&self.entries).expect("The history is always valid json");
+                project::write_atomically(&self.file, &content)
+            });
+        match result {
+            Ok(()) => self.changed = false,
+            Err(err) => log::error!("Couldn't save the history {:?}: {}", self.file, err),
+        }
+    }
+}
+
+/// The key of the file at `path` in the history
+fn key(path: &Path) -> String {
+    let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    glib::compute_checksum_for_string(glib::ChecksumType::Sha256, &path.to_string_lossy())
+        .map(|hash| hash.to_string())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+
+    use super::*;
+
+    fn state(path: &Path, position: u64) -> FileState {
+        FileState {
+            path: path.to_path_buf(),
+            position: Duration::from_secs(position),
+            duration: Some(Duration::from_secs(60)),
+            speed: 1.0,
+            pitch: 0.0,
+            loop_points: None,
+            last_opened: 0,
+        }
+    }
+
+    #[test]
+    fn same_file_has_the_same_key() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("sub")).unwrap();
+        let path = dir.path().join("a.mp3");
+        File::create(&path).unwrap();
+
+        assert_eq!(
+            key(&path),
+            key(&dir.path().join("sub").join("..").join("a.mp3"))
+        );
+        assert_eq!(key(&path), key(&dir.path().join(".").join("a.mp3")));
+        assert_ne!(key(&path), key(&dir.path().join("b.mp3")));
+    }
+
+    #[test]
+    fn remembers_by_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut history = History::load_from(dir.path().join("history.json"));
+        let a = dir.path().join("a.mp3");
+        let b = dir.path().join("b.mp3");
+        File::create(&a).unwrap();
+
+        history.remember(state(&a, 1));
+        history.remember(state(&b, 2));
+        history.remember(state(&dir.path().join(".").join("a.mp3"), 3));
+
+        assert_eq!(history.entries.len(), 2);
+        assert_eq!(history.get(&a).unwrap().position, Duration::from_secs(3));
+        assert_eq!(history.get(&b).unwrap().position, Duration::from_secs(2));
+    }
+
+    #[test]
+    fn unchanged_state_is_not_saved_again() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut history = History::load_from(dir.path().join("history.json"));
+        history.remember(state(&dir.path().join("a.mp3"), 1));
+        history.save();
+        assert!(!history.changed);
+
+        history.remember(state(&dir.path().join("a.mp3"), 1));
+        assert!(!history.changed);
+        history.remember(state(&dir.path().join("a.mp3"), 2));
+        assert!(history.changed);
+    }
+
+    #[test]
+    fn saved_history_is_loaded() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("data").join("history.json");
+        let path = dir.path().join("a.mp3");
+        let mut history = History::load_from(file.clone());
+        history.remember(state(&path, 5));
+        history.save();
+
+        let history = History::load_from(file);
+
+        assert_eq!(history.get(&path).unwrap().position, Duration::from_secs(5));
+    }
+
+    #[test]
+    fn forgets_the_least_recently_opened_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut history = History::load_from(dir.path().join("history.json"));
+        for i in 0..MAX_ENTRIES {
+            let path = dir.path().join(format!("{i}.mp3"));
+            let state = FileState {
+                last_opened: i as u64 + 1,
+                ..state(&path, 0)
+            };
+            history.entries.insert(key(&path), state);
+        }
+
+        history.remember(state(&dir.path().join("new.mp3"), 0));
+
+        assert_eq!(history.entries.len(), MAX_ENTRIES);
+        assert!(history.get(&dir.path().join("0.mp3")).is_none());
+        assert!(history.get(&dir.path().join("1.mp3")).is_some());
+        assert!(history.get(&dir.path().join("new.mp3")).is_some());
+    }
+
+    #[test]
+    fn recent_files_are_sorted_and_exist() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut history = History::load_from(dir.path().join("history.json"));
+        for (name, last_opened) in [("a.mp3", 3), ("b.mp3", 1), ("c.mp3", 2), ("gone.mp3", 4)] {
+            let path = dir.path().join(name);
+            if name != "gone.mp3" {
+                File::create(&path).unwrap();
+            }
+            let state = FileState {
+                last_opened,
+                ..state(&path, 0)
+            };
+            history.entries.insert(key(&path), state);
+        }
+
+        let recent: Vec<PathBuf> = history
+            .recent(2)
+            .into_iter()
+            .map(|state| state.path)
+            .collect();
+
+        assert_eq!(
+            recent,
+            vec![dir.path().join("a.mp3"), dir.path().join("c.mp3")]
+        );
+        assert!(history.get(&dir.path().join("gone.mp3")).is_none());
+        assert!(history.changed);
+    }
+
+    #[test]
+    fn finished_near_the_end() {
+        let path = Path::new("a.mp3");
+        assert!(!state(path, 58).is_finished());
+        assert!(state(path, 59).is_finished());
+        let unknown_duration = FileState {
+            duration: None,
+            ..state(path, 100)
+        };
+        assert!(!unknown_duration.is_finished());
+    }
+}
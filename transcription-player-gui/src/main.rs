@@ -1,21 +1,46 @@
 mod audio;
+mod history;
+mod project;
+mod recovery;
+mod settings;
 pub mod ui {
+    pub mod actions;
+    pub mod errors;
+    pub mod file_filters;
     pub mod main_window;
+    pub mod preferences;
+    pub mod recent_files;
+    pub mod recovery;
+    pub mod transcript;
+    pub mod waveform;
 }
 
-use audio::AudioPlayer;
-use gtk::{gdk::Display, gio, prelude::*, CssProvider, StyleContext};
+use gtk::{gdk::Display, gio, glib::clone, prelude::*, CssProvider, StyleContext};
 use relm4::RelmApp;
 use ui::main_window;
 
 const APP_ID: &str = "ninja.seppli.Transcrible";
 
 fn main() {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+    if !settings::is_schema_installed() {
+        eprintln!(
+            "The GSettings schema {} isn't installed. Install it with `make install`, or run \
+             the app from the source tree with `make run`.",
+            APP_ID
+        );
+        std::process::exit(1);
+    }
     gio::resources_register_include!("transcrible.gresource")
         .expect("Failed to register resources");
 
-    let player = AudioPlayer::new().expect("Couldn't create AudioPlayer");
-    main_window::start_app(player);
+    // the model creates widgets before the application runs
+    gtk::init().expect("Failed to initialize gtk");
+
+    let app = adw::Application::new(Some(APP_ID), gio::ApplicationFlags::HANDLES_OPEN);
+    setup_shortcuts(&app);
+    let open_requests = setup_open(&app);
+    main_window::start_app(app, open_requests);
 }
 
 fn load_css() {
@@ -29,4 +54,35 @@ fn load_css() {
     );
 }
 
-fn setup_shortcuts(app: &adw::Application) {}
+/// Handles the files passed on the command line, as paths or uris. They are sent to the returned
+/// receiver once the window exists, uris which aren't local files as error.
+fn setup_open(app: &adw::Application) -> glib::Receiver<main_window::OpenRequest> {
+    let (sender, receiver) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
+    app.connect_open(move |app, files, _hint| {
+        // the window is created when the app is activated, which doesn't happen on its own
+        // when files are opened
+        app.activate();
+        for file in files {
+            let request = file
+                .path()
+                .ok_or_else(|| format!("Only local files can be opened, not {}", file.uri()));
+            let _ = sender.send(request);
+        }
+    });
+    receiver
+}
+
+fn setup_shortcuts(app: &adw::Application) {
+    let settings = settings::new_settings();
+    ui::actions::apply_accels(app, &settings::shortcuts(&settings));
+    settings.connect_changed(
+        Some(settings::SHORTCUTS),
+        clone!(@weak app => move |settings, _| {
+            ui::actions::apply_accels(&app, &settings::shortcuts(settings));
+        }),
+    );
+    // the signal handler lives as long as the settings, so they are kept until the app shuts down
+    app.connect_shutdown(move |_| {
+        let _ = &settings;
+    });
+}
@@ -0,0 +1,485 @@
+use std::{
+    fs,
+    io::{self, Write},
+    path::{Component, Path, PathBuf},
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use thiserror::Error;
+
+use crate::audio::{channels::ChannelRouting, equalizer::EqSettings};
+
+/// The extension of project files
+pub const PROJECT_EXTENSION: &str = "transcrible";
+
+/// The steps which upgrade the json of an older project, `MIGRATIONS[0]` upgrades version 1 to
+/// version 2 and so on. A new version of the format adds its step here.
+const MIGRATIONS: &[fn(Value) -> Value] = &[];
+
+/// The version of the format which is written
+pub const CURRENT_VERSION: u32 = MIGRATIONS.len() as u32 + 1;
+
+#[derive(Error, Debug)]
+pub enum ProjectError {
+    #[error("Couldn't read project \"{path}\"")]
+    ReadError { path: String, source: io::Error },
+    #[error("Couldn't write project \"{path}\"")]
+    WriteError { path: String, source: io::Error },
+    #[error("Project \"{path}\" is invalid")]
+    ParseError {
+        path: String,
+        source: serde_json::Error,
+    },
+    #[error("Project \"{path}\" was saved by a newer version (format {version})")]
+    UnsupportedVersion { path: String, version: u32 },
+}
+
+/// Where the audio file of a project is. The relative path is tried first, so that a project can
+/// be moved together with its audio file.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct AudioReference {
+    pub absolute: PathBuf,
+    /// relative to the directory of the project file
+    #[serde(default)]
+    pub relative: Option<PathBuf>,
+}
+
+impl AudioReference {
+    fn new(audio_path: &Path, project_path: &Path) -> Self {
+        let absolute = audio_path
+            .canonicalize()
+            .unwrap_or_else(|_| audio_path.to_path_buf());
+        let relative = project_dir(project_path)
+            .canonicalize()
+            .ok()
+            .and_then(|dir| relative_path(&dir, &absolute));
+        AudioReference { absolute, relative }
+    }
+
+    /// The path of the audio file, if either the relative or the absolute path exists
+    pub fn resolve(&self, project_path: &Path) -> Option<PathBuf> {
+        self.relative
+            .as_ref()
+            .map(|relative| project_dir(project_path).join(relative))
+            .filter(|path| path.exists())
+            .or_else(|| Some(self.absolute.clone()).filter(|path| path.exists()))
+    }
+}
+
+/// The points of the loop region, the gap and the repetitions are global settings
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct LoopPoints {
+    #[serde(with = "millis")]
+    pub start: Duration,
+    #[serde(with = "millis")]
+    pub end: Duration,
+}
+
+/// A transcription session: the audio file, the transcript and the state of the playback.
+/// Times are stored in milliseconds.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Project {
+    #[serde(default)]
+    pub audio: Option<AudioReference>,
+    #[serde(default)]
+    pub transcript: String,
+    #[serde(default, with = "millis")]
+    pub position: Duration,
+    #[serde(default = "default_speed")]
+    pub speed: f64,
+    /// in semitones relative to the original pitch
+    #[serde(default)]
+    pub pitch: f64,
+    #[serde(default, rename = "loop")]
+    pub loop_points: Option<LoopPoints>,
+    #[serde(default, with = "millis_list")]
+    pub markers: Vec<Duration>,
+    /// the equalizer which suits the recording
+    #[serde(default)]
+    pub eq: EqSettings,
+    /// which channels are heard, for recordings with one speaker per channel
+    #[serde(default)]
+    pub channels: ChannelRouting,
+}
+
+fn default_speed() -> f64 {
+    1.0
+}
+
+impl Project {
+    pub fn new() -> Self {
+        Project {
+            audio: None,
+            transcript: String::new(),
+            position: Duration::ZERO,
+            speed: default_speed(),
+            pitch: 0.0,
+            loop_points: None,
+            markers: vec![],
+            eq: EqSettings::default(),
+            channels: ChannelRouting::default(),
+        }
+    }
+
+    /// Sets the audio file, `project_path` is where the project will be saved
+    pub fn set_audio(&mut self, audio_path: &Path, project_path: &Path) {
+        self.audio = Some(AudioReference::new(audio_path, project_path));
+    }
+
+    /// Loads the project at `path`. Projects of older versions are migrated.
+    pub fn load<P: AsRef<Path>>(path: &P) -> Result<Self, ProjectError> {
+        let path = path.as_ref();
+        let display_path = path.to_string_lossy().into_owned();
+        let content = fs::read_to_string(path).map_err(|source| ProjectError::ReadError {
+            path: display_path.clone(),
+            source,
+        })?;
+        Self::from_json(&content).map_err(|err| match err {
+            ProjectFormatError::Parse(source) => ProjectError::ParseError {
+                path: display_path,
+                source,
+            },
+            ProjectFormatError::UnsupportedVersion(version) => ProjectError::UnsupportedVersion {
+                path: display_path,
+                version,
+            },
+        })
+    }
+
+    /// Saves the project to `path`. The previous file at `path` is only replaced once the project
+    /// is written completely.
+    pub fn save<P: AsRef<Path>>(&self, path: &P) -> Result<(), ProjectError> {
+        let path = path.as_ref();
+        write_atomically(path, self.to_json().as_bytes()).map_err(|source| {
+            ProjectError::WriteError {
+                path: path.to_string_lossy().into_owned(),
+                source,
+            }
+        })
+    }
+
+    fn to_json(&self) -> String {
+        let mut json = serde_json::to_value(self).expect("A project is always valid json");
+        if let Value::Object(object) = &mut json {
+            object.insert("version".to_string(), CURRENT_VERSION.into());
+        }
+        serde_json::to_string_pretty(&json).expect("A project is always valid json")
+    }
+
+    fn from_json(content: &str) -> Result<Self, ProjectFormatError> {
+        Self::migrate_from_json(content, MIGRATIONS)
+    }
+
+    /// Parses `content` and upgrades it with `migrations`, the version after the last migration
+    /// is the current one
+    fn migrate_from_json(
+        content: &str,
+        migrations: &[fn(Value) -> Value],
+    ) -> Result<Self, ProjectFormatError> {
+        let json: Value = serde_json::from_str(content).map_err(ProjectFormatError::Parse)?;
+        let version = json
+            .get("version")
+            .and_then(Value::as_u64)
+            .map_or(1, |version| version as u32);
+        if version > migrations.len() as u32 + 1 {
+            return Err(ProjectFormatError::UnsupportedVersion(version));
+        }
+        let json = migrations
+            .iter()
+            .skip(version.saturating_sub(1) as usize)
+            .fold(json, |json, migration| migration(json));
+        serde_json::from_value(json).map_err(ProjectFormatError::Parse)
+    }
+}
+
+impl Default for Project {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The errors of [`Project::from_json`], they get the path of the project in [`ProjectError`]
+#[derive(Debug)]
+enum ProjectFormatError {
+    Parse(serde_json::Error),
+    UnsupportedVersion(u32),
+}
+
+/// Writes `content` to a temporary file next to `path` and renames it to `path` afterwards, so
+/// that `path` never contains a partially written file, even if the app crashes while writing.
+/// The temporary file has a unique name, so that concurrent writes of the same path don't
+/// write into the same file.
+pub fn write_atomically(path: &Path, content: &[u8]) -> io::Result<()> {
+    let dir = path
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or_else(|| Path::new("."));
+    let mut file = tempfile::NamedTempFile::new_in(dir)?;
+    file.write_all(content)?;
+    file.as_file().sync_all()?;
+    file.persist(path)?;
+    Ok(())
+}
+
+/// Whether `path` has the extension of project files
+pub fn is_project_file(path: &Path) -> bool {
+    path.extension()
+        .map_or(false, |extension| extension == PROJECT_EXTENSION)
+}
+
+fn project_dir(project_path: &Path) -> &Path {
+    project_path.parent().unwrap_or_else(|| Path::new(""))
+}
+
+/// The path to `target` relative to the directory `base`. Both have to be absolute.
+fn relative_path(base: &Path, target: &Path) -> Option<PathBuf> {
+    let base: Vec<Component> = base.components().collect();
+    let target: Vec<Component> = target.components().collect();
+    // paths on different windows drives have no relative path
+    if base.first() != target.first() {
+        return None;
+    }
+    let common = base
+        .iter()
+        .zip(&target)
+        .take_while(|(base, target)| base == target)
+        .count();
+    let mut relative = PathBuf::new();
+    for _ in common..base.len() {
+        relative.push("..");
+    }
+    for component in &target[common..] {
+        relative.push(component);
+    }
+    Some(relative)
+}
+
+/// (De)serializes a [`Duration`] as milliseconds
+pub mod millis {
+    use std::time::Duration;
+
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(duration.as_millis() as u64)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        Ok(Duration::from_millis(u64::deserialize(deserializer)?))
+    }
+}
+
+/// (De)serializes an optional [`Duration`] as milliseconds
+pub mod optional_millis {
+    use std::time::Duration;
+
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        duration: &Option<Duration>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match duration {
+            Some(duration) => serializer.serialize_some(&(duration.as_millis() as u64)),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<Duration>, D::Error> {
+        Ok(Option::<u64>::deserialize(deserializer)?.map(Duration::from_millis))
+    }
+}
+
+/// (De)serializes a list of [`Duration`]s as milliseconds
+pub mod millis_list {
+    use std::time::Duration;
+
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        durations: &[Duration],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(durations.iter().map(|duration| duration.as_millis() as u64))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<Duration>, D::Error> {
+        Ok(Vec::<u64>::deserialize(deserializer)?
+            .into_iter()
+            .map(Duration::from_millis)
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn save_and_load_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let audio_path = dir.path().join("interview.mp3");
+        fs::write(&audio_path, b"").unwrap();
+        let project_path = dir.path().join("interview.transcrible");
+        let mut project = Project {
+            transcript: "[00:00:01] Hello".to_string(),
+            position: Duration::from_millis(61_500),
+            speed: 0.75,
+            pitch: -2.0,
+            loop_points: Some(LoopPoints {
+                start: Duration::from_secs(10),
+                end: Duration::from_secs(12),
+            }),
+            markers: vec![Duration::from_secs(3), Duration::from_secs(30)],
+            ..Project::new()
+        };
+        project.set_audio(&audio_path, &project_path);
+
+        project.save(&project_path).unwrap();
+
+        assert_eq!(Project::load(&project_path).unwrap(), project);
+    }
+
+    #[test]
+    fn write_atomically_replaces_the_file_without_leftovers() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("notes.transcrible");
+        write_atomically(&path, b"first").unwrap();
+        write_atomically(&path, b"second").unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"second");
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn saves_the_current_version() {
+        let json: Value = serde_json::from_str(&Project::new().to_json()).unwrap();
+        assert_eq!(json["version"], CURRENT_VERSION);
+    }
+
+    #[test]
+    fn project_without_version_is_version_one() {
+        fn add_speed(mut json: Value) -> Value {
+            json["speed"] = 2.0.into();
+            json
+        }
+        let content = json!({ "transcript": "text" }).to_string();
+
+        let project = Project::migrate_from_json(&content, &[add_speed]).unwrap();
+
+        assert_eq!(project.speed, 2.0);
+        assert_eq!(project.transcript, "text");
+    }
+
+    #[test]
+    fn migrations_run_from_the_saved_version() {
+        fn rename_rate(mut json: Value) -> Value {
+            if let Some(rate) = json.as_object_mut().and_then(|json| json.remove("rate")) {
+                json["speed"] = rate;
+            }
+            json
+        }
+        fn double_pitch(mut json: Value) -> Value {
+            json["pitch"] = (json["pitch"].as_f64().unwrap_or_default() * 2.0).into();
+            json
+        }
+        let migrations: [fn(Value) -> Value; 2] = [rename_rate, double_pitch];
+
+        let version_1 = json!({ "version": 1, "rate": 1.5, "pitch": 1.0 }).to_string();
+        let project = Project::migrate_from_json(&version_1, &migrations).unwrap();
+        assert_eq!(project.speed, 1.5);
+        assert_eq!(project.pitch, 2.0);
+
+        let version_2 = json!({ "version": 2, "speed": 1.5, "pitch": 1.0 }).to_string();
+        let project = Project::migrate_from_json(&version_2, &migrations).unwrap();
+        assert_eq!(project.speed, 1.5);
+        assert_eq!(project.pitch, 2.0);
+    }
+
+    #[test]
+    fn newer_version_is_rejected() {
+        let content = json!({ "version": CURRENT_VERSION + 1 }).to_string();
+        assert!(matches!(
+            Project::from_json(&content),
+            Err(ProjectFormatError::UnsupportedVersion(version)) if version == CURRENT_VERSION + 1
+        ));
+    }
+
+    #[test]
+    fn missing_fields_get_their_defaults() {
+        let project = Project::from_json("{}").unwrap();
+        assert_eq!(project, Project::new());
+    }
+
+    #[test]
+    fn relative_path_to_sibling_and_parent_directories() {
+        assert_eq!(
+            relative_path(
+                Path::new("/home/user/projects"),
+                Path::new("/home/user/audio/a.mp3")
+            ),
+            Some(PathBuf::from("../audio/a.mp3"))
+        );
+        assert_eq!(
+            relative_path(Path::new("/home/user"), Path::new("/home/user/a.mp3")),
+            Some(PathBuf::from("a.mp3"))
+        );
+        assert_eq!(
+            relative_path(Path::new("/home/user/a/b"), Path::new("/c.mp3")),
+            Some(PathBuf::from("../../../../c.mp3"))
+        );
+    }
+
+    #[test]
+    fn moved_project_finds_its_audio_by_the_relative_path() {
+        let dir = tempfile::tempdir().unwrap();
+        let old_dir = dir.path().join("old");
+        fs::create_dir_all(old_dir.join("audio")).unwrap();
+        fs::write(old_dir.join("audio").join("a.mp3"), b"").unwrap();
+        let audio = AudioReference::new(
+            &old_dir.join("audio").join("a.mp3"),
+            &old_dir.join("a.transcrible"),
+        );
+        assert_eq!(audio.relative, Some(PathBuf::from("audio/a.mp3")));
+
+        let new_dir = dir.path().join("new");
+        fs::rename(&old_dir, &new_dir).unwrap();
+
+        assert_eq!(
+            audio.resolve(&new_dir.join("a.transcrible")),
+            Some(new_dir.join("audio").join("a.mp3"))
+        );
+    }
+
+    #[test]
+    fn falls_back_to_the_absolute_path() {
+        let dir = tempfile::tempdir().unwrap();
+        let audio_path = dir.path().join("a.mp3");
+        fs::write(&audio_path, b"").unwrap();
+        let audio = AudioReference {
+            absolute: audio_path.clone(),
+            relative: Some(PathBuf::from("missing/a.mp3")),
+        };
+
+        assert_eq!(
+            audio.resolve(&dir.path().join("elsewhere").join("a.transcrible")),
+            Some(audio_path)
+        );
+    }
+
+    #[test]
+    fn missing_audio_is_not_resolved() {
+        let audio = AudioReference {
+            absolute: PathBuf::from("/does/not/exist.mp3"),
+            relative: None,
+        };
+        assert_eq!(audio.resolve(Path::new("/tmp/a.transcrible")), None);
+    }
+}
@@ -0,0 +1,185 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    process,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::project::{self, Project, ProjectError, PROJECT_EXTENSION};
+
+/// How many previous autosaves are kept per session, in case the latest one is broken
+const MAX_BACKUPS: usize = 3;
+/// The extension of the file which describes a session
+const INFO_EXTENSION: &str = "json";
+
+/// What is stored about a session next to its autosaves
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct SessionInfo {
+    /// the project file the session was opened from or saved to
+    project_path: Option<PathBuf>,
+}
+
+/// A session of a previous run whose autosaves weren't removed, because the app didn't shut down
+/// cleanly
+#[derive(Clone, Debug)]
+pub struct CrashedSession {
+    id: String,
+    pub project_path: Option<PathBuf>,
+    /// when the session was autosaved the last time
+    pub saved_at: SystemTime,
+}
+
+/// Autosaves the session of this run to the recovery directory. The autosaves are removed when
+/// the app shuts down cleanly, so the autosaves which are left at startup belong to crashed runs.
+#[derive(Clone, Debug)]
+pub struct Recovery {
+    dir: PathBuf,
+    /// the id of the session of this run
+    id: String,
+}
+
+impl Default for Recovery {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Recovery {
+    pub fn new() -> Self {
+        let started = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        Recovery {
+            dir: glib::user_data_dir().join("transcrible").join("recovery"),
+            id: format!("{}-{}", started, process::id()),
+        }
+    }
+
+    /// The file the latest autosave of this session is written to
+    pub fn file(&self) -> PathBuf {
+        self.autosave_path(&self.id, 0)
+    }
+
+    /// The path of an autosave of the session `id`, `0` is the latest one and higher numbers are
+    /// older backups
+    fn autosave_path(&self, id: &str, backup: usize) -> PathBuf {
+        match backup {
+            0 => self.dir.join(format!("{}.{}", id, PROJECT_EXTENSION)),
+            backup => self
+                .dir
+                .join(format!("{}.{}.{}", id, PROJECT_EXTENSION, backup)),
+        }
+    }
+
+    fn info_path(&self, id: &str) -> PathBuf {
+        self.dir.join(format!("{}.{}", id, INFO_EXTENSION))
+    }
+
+    /// Saves `project` as the latest autosave of this session. The previous autosaves are kept as
+    /// backups.
+    pub fn autosave(
+        &self,
+        project: &Project,
+        project_path: Option<&Path>,
+    ) -> Result<(), ProjectError> {
+        let write_error = |path: &Path, source| ProjectError::WriteError {
+            path: path.to_string_lossy().into_owned(),
+            source,
+        };
+        fs::create_dir_all(&self.dir).map_err(|err| write_error(&self.dir, err))?;
+        for backup in (0..MAX_BACKUPS).rev() {
+            let from = self.autosave_path(&self.id, backup);
+            if from.exists() {
+                fs::rename(&from, self.autosave_path(&self.id, backup + 1))
+                    .map_err(|err| write_error(&from, err))?;
+            }
+        }
+        project.save(&self.file())?;
+
+        let info = SessionInfo {
+            project_path: project_path.map(Path::to_path_buf),
+        };
+        let info = serde_json::to_vec(&info).expect("The session info is always valid json");
+        let info_path = self.info_path(&self.id);
+        project::write_atomically(&info_path, &info).map_err(|err| write_error(&info_path, err))
+    }
+
+    /// Removes the autosaves of this session, when the app shuts down cleanly
+    pub fn discard(&self) {
+        self.remove_session(&self.id);
+    }
+
+    /// The sessions of previous runs which crashed, the latest first
+    pub fn crashed_sessions(&self) -> Vec<CrashedSession> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(_) => return vec![],
+        };
+        let mut sessions: Vec<CrashedSession> = entries
+            .filter_map(|entry| {
+                let path = entry.ok()?.path();
+                if path.extension()? != INFO_EXTENSION {
+                    return None;
+                }
+                let id = path.file_stem()?.to_string_lossy().into_owned();
+                if id == self.id {
+                    return None;
+                }
+                let info: SessionInfo = fs::read(&path)
+                    .ok()
+                    .and_then(|info| serde_json::from_slice(&info).ok())
+                    .unwrap_or_default();
+                let saved_at = fs::metadata(&path).and_then(|m| m.modified()).ok()?;
+                Some(CrashedSession {
+                    id,
+                    project_path: info.project_path,
+                    saved_at,
+                })
+            })
+            .collect();
+        sessions.sort_by_key(|session| std::cmp::Reverse(session.saved_at));
+        sessions
+    }
+
+    /// Loads the latest autosave of `session` which isn't broken and removes the session. The
+    /// restored project becomes part of this session with the next autosave.
+    pub fn restore(&self, session: &CrashedSession) -> Result<Project, ProjectError> {
+        let mut result = Project::load(&self.autosave_path(&session.id, 0));
+        for backup in 1..=MAX_BACKUPS {
+            if result.is_ok() {
+                break;
+            }
+            let path = self.autosave_path(&session.id, backup);
+            if path.exists() {
+                log::warn!("Restoring the backup {:?}", path);
+                result = Project::load(&path);
+            }
+        }
+        if result.is_ok() {
+            self.remove_session(&session.id);
+        }
+        result
+    }
+
+    /// Removes the autosaves of `session` without restoring them
+    pub fn discard_session(&self, session: &CrashedSession) {
+        self.remove_session(&session.id);
+    }
+
+    fn remove_session(&self, id: &str) {
+        let paths = (0..=MAX_BACKUPS)
+            .map(|backup| self.autosave_path(id, backup))
+            .chain([self.info_path(id)]);
+        for path in paths {
+            match fs::remove_file(&path) {
+                Err(err) if err.kind() != io::ErrorKind::NotFound => {
+                    log::warn!("Couldn't remove the autosave {:?}: {}", path, err)
+                }
+                _ => {}
+            }
+        }
+    }
+}
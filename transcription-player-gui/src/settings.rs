@@ -0,0 +1,181 @@
+use std::time::Duration;
+
+use gtk::{gio, prelude::*};
+
+use crate::{
+    audio::{dynamics::DynamicsSettings, worker::SkipSteps, ResumeRewind},
+    ui::actions::CustomAccels,
+    APP_ID,
+};
+
+pub const RESUME_REWIND: &str = "resume-rewind";
+pub const RESUME_REWIND_SCALE_WITH_PAUSE: &str = "resume-rewind-scale-with-pause";
+pub const RESUME_REWIND_PAUSE_FACTOR: &str = "resume-rewind-pause-factor";
+pub const RESUME_REWIND_MAX: &str = "resume-rewind-max";
+
+/// The keys which make up the [`ResumeRewind`] settings
+pub const RESUME_REWIND_KEYS: [&str; 4] = [
+    RESUME_REWIND,
+    RESUME_REWIND_SCALE_WITH_PAUSE,
+    RESUME_REWIND_PAUSE_FACTOR,
+    RESUME_REWIND_MAX,
+];
+
+/// How many milliseconds pass between two updates of the position in the ui
+pub const POSITION_UPDATE_INTERVAL: &str = "position-update-interval";
+
+pub const SKIP_SMALL_STEP: &str = "skip-small-step";
+pub const SKIP_LARGE_STEP: &str = "skip-large-step";
+
+/// The keys which make up the [`SkipSteps`] settings
+pub const SKIP_STEP_KEYS: [&str; 2] = [SKIP_SMALL_STEP, SKIP_LARGE_STEP];
+
+pub const LOOP_GAP: &str = "loop-gap";
+pub const LOOP_MAX_REPEATS: &str = "loop-max-repeats";
+
+/// The keys which make up the loop options
+pub const LOOP_KEYS: [&str; 2] = [LOOP_GAP, LOOP_MAX_REPEATS];
+
+pub const DYNAMICS_THRESHOLD: &str = "dynamics-threshold";
+pub const DYNAMICS_RATIO: &str = "dynamics-ratio";
+pub const DYNAMICS_ATTACK: &str = "dynamics-attack";
+pub const DYNAMICS_RELEASE: &str = "dynamics-release";
+pub const DYNAMICS_MAKEUP_GAIN: &str = "dynamics-makeup-gain";
+pub const DYNAMICS_LIMITER: &str = "dynamics-limiter";
+pub const DYNAMICS_AGC: &str = "dynamics-agc";
+
+/// The keys which make up the [`DynamicsSettings`]
+pub const DYNAMICS_KEYS: [&str; 7] = [
+    DYNAMICS_THRESHOLD,
+    DYNAMICS_RATIO,
+    DYNAMICS_ATTACK,
+    DYNAMICS_RELEASE,
+    DYNAMICS_MAKEUP_GAIN,
+    DYNAMICS_LIMITER,
+    DYNAMICS_AGC,
+];
+
+/// The maximal size of the waveform peak cache in MiB
+pub const PEAK_CACHE_SIZE: &str = "peak-cache-size";
+
+/// How many MiB a file may take up when it is decoded into memory, 0 always streams them
+pub const MEMORY_DECODE_LIMIT: &str = "memory-decode-limit";
+
+/// How many seconds pass between two autosaves, 0 disables autosaving
+pub const AUTOSAVE_INTERVAL: &str = "autosave-interval";
+
+/// Whether a file continues where it stopped the last time it was open
+pub const RESTORE_POSITION: &str = "restore-position";
+
+/// How many files the list of recent files shows
+pub const MAX_RECENT_FILES: &str = "max-recent-files";
+
+/// The custom accelerators of the transport actions, see [`CustomAccels`]
+pub const SHORTCUTS: &str = "shortcuts";
+
+/// Whether the schema of the settings can be found. Without it, creating the settings aborts.
+pub fn is_schema_installed() -> bool {
+    gio::SettingsSchemaSource::default()
+        .and_then(|source| source.lookup(APP_ID, true))
+        .is_some()
+}
+
+pub fn new_settings() -> gio::Settings {
+    gio::Settings::new(APP_ID)
+}
+
+fn millis(settings: &gio::Settings, key: &str) -> Duration {
+    Duration::from_millis(settings.uint(key) as u64)
+}
+
+pub fn resume_rewind(settings: &gio::Settings) -> ResumeRewind {
+    ResumeRewind {
+        rewind: millis(settings, RESUME_REWIND),
+        scale_with_pause: settings.boolean(RESUME_REWIND_SCALE_WITH_PAUSE),
+        pause_factor: settings.double(RESUME_REWIND_PAUSE_FACTOR),
+        max_rewind: millis(settings, RESUME_REWIND_MAX),
+    }
+}
+
+pub fn position_update_interval(settings: &gio::Settings) -> Duration {
+    millis(settings, POSITION_UPDATE_INTERVAL)
+}
+
+pub fn skip_steps(settings: &gio::Settings) -> SkipSteps {
+    SkipSteps {
+        small: millis(settings, SKIP_SMALL_STEP),
+        large: millis(settings, SKIP_LARGE_STEP),
+    }
+}
+
+/// The gap between two repetitions and the maximal number of repetitions of the loop region
+pub fn loop_options(settings: &gio::Settings) -> (Duration, Option<u32>) {
+    let max_repeats = match settings.uint(LOOP_MAX_REPEATS) {
+        0 => None,
+        max_repeats => Some(max_repeats),
+    };
+    (millis(settings, LOOP_GAP), max_repeats)
+}
+
+pub fn dynamics(settings: &gio::Settings) -> DynamicsSettings {
+    DynamicsSettings {
+        threshold: settings.double(DYNAMICS_THRESHOLD) as f32,
+        ratio: settings.double(DYNAMICS_RATIO) as f32,
+        attack: millis(settings, DYNAMICS_ATTACK),
+        release: millis(settings, DYNAMICS_RELEASE),
+        makeup_gain: settings.double(DYNAMICS_MAKEUP_GAIN) as f32,
+        limiter: settings.boolean(DYNAMICS_LIMITER),
+        agc: settings.boolean(DYNAMICS_AGC),
+    }
+}
+
+/// Stores `dynamics`. The keys change at once, so that the listeners never read half of the new
+/// settings.
+pub fn set_dynamics(dynamics: &DynamicsSettings) {
+    // an object of its own, since the changes of an object stay delayed once it was delayed
+    let settings = new_settings();
+    settings.delay();
+    let result = settings
+        .set_double(DYNAMICS_THRESHOLD, dynamics.threshold as f64)
+        .and_then(|_| settings.set_double(DYNAMICS_RATIO, dynamics.ratio as f64))
+        .and_then(|_| settings.set_uint(DYNAMICS_ATTACK, dynamics.attack.as_millis() as u32))
+        .and_then(|_| settings.set_uint(DYNAMICS_RELEASE, dynamics.release.as_millis() as u32))
+        .and_then(|_| settings.set_double(DYNAMICS_MAKEUP_GAIN, dynamics.makeup_gain as f64))
+        .and_then(|_| settings.set_boolean(DYNAMICS_LIMITER, dynamics.limiter))
+        .and_then(|_| settings.set_boolean(DYNAMICS_AGC, dynamics.agc));
+    match result {
+        Ok(()) => settings.apply(),
+        Err(err) => {
+            settings.revert();
+            log::error!("Couldn't save the dynamics settings: {}", err);
+        }
+    }
+}
+
+/// The maximal size of the peak cache in bytes
+pub fn peak_cache_size(settings: &gio::Settings) -> u64 {
+    settings.uint(PEAK_CACHE_SIZE) as u64 * 1024 * 1024
+}
+
+/// How many bytes a file may take up when it is decoded into memory
+pub fn memory_decode_limit(settings: &gio::Settings) -> u64 {
+    settings.uint(MEMORY_DECODE_LIMIT) as u64 * 1024 * 1024
+}
+
+pub fn autosave_interval(settings: &gio::Settings) -> u32 {
+    settings.uint(AUTOSAVE_INTERVAL)
+}
+
+pub fn max_recent_files(settings: &gio::Settings) -> usize {
+    settings.uint(MAX_RECENT_FILES) as usize
+}
+
+pub fn shortcuts(settings: &gio::Settings) -> CustomAccels {
+    settings.value(SHORTCUTS).get().unwrap_or_default()
+}
+
+pub fn set_shortcuts(settings: &gio::Settings, custom_accels: &CustomAccels) {
+    if let Err(err) = settings.set_value(SHORTCUTS, &custom_accels.to_variant()) {
+        log::error!("Couldn't save the shortcuts: {}", err);
+    }
+}
@@ -0,0 +1,268 @@
+use std::collections::HashMap;
+
+use gtk::{gio, prelude::*};
+
+/// The prefix of the actions of the application window
+pub const ACTION_GROUP: &str = "win";
+
+/// An action which controls the playback or the transcript. The actions are registered on the
+/// window, so their accelerators work regardless of the focused widget.
+pub struct TransportAction {
+    pub name: &'static str,
+    pub title: &'static str,
+    /// The accelerators are chosen to not collide with typing in the transcript
+    pub default_accels: &'static [&'static str],
+}
+
+impl TransportAction {
+    /// The name of the action including the group prefix, like `win.play-pause`
+    pub fn detailed_name(&self) -> String {
+        format!("{}.{}", ACTION_GROUP, self.name)
+    }
+}
+
+pub const PLAY_PAUSE: &str = "play-pause";
+pub const SKIP_SMALL_BACKWARD: &str = "skip-small-backward";
+pub const SKIP_SMALL_FORWARD: &str = "skip-small-forward";
+pub const SKIP_LARGE_BACKWARD: &str = "skip-large-backward";
+pub const SKIP_LARGE_FORWARD: &str = "skip-large-forward";
+pub const SPEED_DOWN: &str = "speed-down";
+pub const SPEED_UP: &str = "speed-up";
+pub const INSERT_TIMESTAMP: &str = "insert-timestamp";
+pub const SET_LOOP_START: &str = "set-loop-start";
+pub const SET_LOOP_END: &str = "set-loop-end";
+pub const CLEAR_LOOP: &str = "clear-loop";
+pub const ADD_MARKER: &str = "add-marker";
+pub const START_FROM_BEGINNING: &str = "start-from-beginning";
+
+pub static TRANSPORT_ACTIONS: [TransportAction; 13] = [
+    TransportAction {
+        name: PLAY_PAUSE,
+        title: "Play/Pause",
+        default_accels: &["Escape", "<Control>space"],
+    },
+    TransportAction {
+        name: SKIP_SMALL_BACKWARD,
+        title: "Skip backward",
+        default_accels: &["F1"],
+    },
+    TransportAction {
+        name: SKIP_SMALL_FORWARD,
+        title: "Skip forward",
+        default_accels: &["F2"],
+    },
+    TransportAction {
+        name: SKIP_LARGE_BACKWARD,
+        title: "Skip far backward",
+        default_accels: &["<Shift>F1"],
+    },
+    TransportAction {
+        name: SKIP_LARGE_FORWARD,
+        title: "Skip far forward",
+        default_accels: &["<Shift>F2"],
+    },
+    TransportAction {
+        name: SPEED_DOWN,
+        title: "Slow down",
+        default_accels: &["F3"],
+    },
+    TransportAction {
+        name: SPEED_UP,
+        title: "Speed up",
+        default_accels: &["F4"],
+    },
+    TransportAction {
+        name: INSERT_TIMESTAMP,
+        title: "Insert timestamp",
+        default_accels: &["<Control>t"],
+    },
+    TransportAction {
+        name: SET_LOOP_START,
+        title: "Set loop start (A)",
+        default_accels: &["F6"],
+    },
+    TransportAction {
+        name: SET_LOOP_END,
+        title: "Set loop end (B)",
+        default_accels: &["F7"],
+    },
+    TransportAction {
+        name: CLEAR_LOOP,
+        title: "Clear loop",
+        default_accels: &["F8"],
+    },
+    TransportAction {
+        name: ADD_MARKER,
+        title: "Add marker",
+        default_accels: &["F5"],
+    },
+    TransportAction {
+        name: START_FROM_BEGINNING,
+        title: "Start from the beginning",
+        default_accels: &["<Alt>Home"],
+    },
+];
+
+pub const PREFERENCES: &str = "preferences";
+pub const OPEN_PROJECT: &str = "open-project";
+pub const SAVE_PROJECT: &str = "save-project";
+pub const SAVE_PROJECT_AS: &str = "save-project-as";
+
+/// The actions of the window which aren't transport actions and their fixed accelerators
+const WINDOW_ACCELS: [(&str, &str); 4] = [
+    (PREFERENCES, "<Control>comma"),
+    (OPEN_PROJECT, "<Control>o"),
+    (SAVE_PROJECT, "<Control>s"),
+    (SAVE_PROJECT_AS, "<Control><Shift>s"),
+];
+
+/// The accelerators the user bound to the actions, by action name. Actions which aren't in the
+/// map use their default accelerators.
+pub type CustomAccels = HashMap<String, Vec<String>>;
+
+/// The accelerators of `action`, either the custom ones or the defaults
+pub fn effective_accels(action: &TransportAction, custom_accels: &CustomAccels) -> Vec<String> {
+    match custom_accels.get(action.name) {
+        Some(accels) => accels.clone(),
+        None => action
+            .default_accels
+            .iter()
+            .map(|accel| accel.to_string())
+            .collect(),
+    }
+}
+
+/// Brings `accel` into the canonical form of gtk, so that `<ctrl>t` and `<Control>t` compare
+/// equal. Returns `None` if it isn't a valid accelerator.
+pub fn normalize_accel(accel: &str) -> Option<String> {
+    let (key, modifiers) = gtk::accelerator_parse(accel)?;
+    Some(gtk::accelerator_name(key, modifiers).to_string())
+}
+
+/// Returns the action other than `action_name` which already uses `accel`
+pub fn conflicting_action(
+    action_name: &str,
+    accel: &str,
+    custom_accels: &CustomAccels,
+) -> Option<&'static TransportAction> {
+    let accel = normalize_accel(accel)?;
+    TRANSPORT_ACTIONS.iter().find(|action| {
+        action.name != action_name
+            && effective_accels(action, custom_accels)
+                .iter()
+                .any(|other| normalize_accel(other).as_ref() == Some(&accel))
+    })
+}
+
+/// Whether `accel` is used by one of the fixed window actions
+pub fn is_window_accel(accel: &str) -> bool {
+    let accel = normalize_accel(accel);
+    accel.is_some()
+        && WINDOW_ACCELS
+            .iter()
+            .any(|(_, window_accel)| normalize_accel(window_accel) == accel)
+}
+
+/// Sets the accelerators of all transport actions, the custom ones replace the defaults
+pub fn apply_accels(app: &impl IsA<gtk::Application>, custom_accels: &CustomAccels) {
+    for action in &TRANSPORT_ACTIONS {
+        let accels = effective_accels(action, custom_accels);
+        let accels: Vec<&str> = accels.iter().map(|accel| accel.as_str()).collect();
+        app.set_accels_for_action(&action.detailed_name(), &accels);
+    }
+    for (name, accel) in WINDOW_ACCELS {
+        app.set_accels_for_action(&format!("{}.{}", ACTION_GROUP, name), &[accel]);
+    }
+}
+
+/// Adds all transport actions to `window`. When an action is activated, `activate` is called
+/// with its name.
+///
+/// The actions are added to the window itself instead of an action group of their own, which
+/// would replace the built-in actions of the window, like `win.show-help-overlay`.
+pub fn add_transport_actions<F: Fn(&str) + Clone + 'static>(
+    window: &impl IsA<gio::ActionMap>,
+    activate: F,
+) {
+    for transport_action in &TRANSPORT_ACTIONS {
+        let action = gio::SimpleAction::new(transport_action.name, None);
+        let activate = activate.clone();
+        action.connect_activate(move |action, _| activate(&action.name()));
+        window.add_action(&action);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn custom(accels: &[(&str, &[&str])]) -> CustomAccels {
+        accels
+            .iter()
+            .map(|(name, accels)| {
+                let accels = accels.iter().map(|accel| accel.to_string()).collect();
+                (name.to_string(), accels)
+            })
+            .collect()
+    }
+
+    fn action(name: &str) -> &'static TransportAction {
+        TRANSPORT_ACTIONS
+            .iter()
+            .find(|action| action.name == name)
+            .unwrap()
+    }
+
+    #[test]
+    fn custom_accels_replace_the_defaults() {
+        let custom_accels = custom(&[(PLAY_PAUSE, &["F9"]), (ADD_MARKER, &[])]);
+
+        assert_eq!(effective_accels(action(PLAY_PAUSE), &custom_accels), ["F9"]);
+        assert!(effective_accels(action(ADD_MARKER), &custom_accels).is_empty());
+        assert_eq!(
+            effective_accels(action(SPEED_UP), &custom_accels),
+            action(SPEED_UP).default_accels
+        );
+    }
+
+    /// The accelerators are parsed by gtk, which can only be used from the thread it was
+    /// initialized on. So all checks which parse accelerators run in this one test. Without a
+    /// display gtk can't be initialized and the test is skipped.
+    #[test]
+    fn accels_are_compared_in_the_form_of_gtk() {
+        if gtk::init().is_err() {
+            eprintln!("skipped, gtk can't be initialized without a display");
+            return;
+        }
+        let no_custom_accels = CustomAccels::new();
+
+        assert_eq!(normalize_accel("<ctrl>T").as_deref(), Some("<Control>t"));
+        assert_eq!(normalize_accel("not an accelerator"), None);
+
+        // the default accelerators are unique
+        for action in &TRANSPORT_ACTIONS {
+            for accel in action.default_accels {
+                assert!(normalize_accel(accel).is_some(), "{}", accel);
+                let conflict = conflicting_action(action.name, accel, &no_custom_accels);
+                assert!(conflict.is_none(), "{} of {}", accel, action.name);
+                assert!(!is_window_accel(accel), "{}", accel);
+            }
+        }
+
+        let conflict = conflicting_action(SPEED_UP, "<ctrl>T", &no_custom_accels);
+        assert_eq!(conflict.map(|action| action.name), Some(INSERT_TIMESTAMP));
+        // an action doesn't conflict with itself
+        assert!(conflicting_action(SPEED_UP, "F4", &no_custom_accels).is_none());
+
+        let custom_accels = custom(&[(SPEED_UP, &["F9"])]);
+        let conflict = conflicting_action(PLAY_PAUSE, "F9", &custom_accels);
+        assert_eq!(conflict.map(|action| action.name), Some(SPEED_UP));
+        assert!(conflicting_action(PLAY_PAUSE, "F4", &custom_accels).is_none());
+
+        assert!(conflicting_action(PLAY_PAUSE, "", &no_custom_accels).is_none());
+        assert!(!is_window_accel("<Control>"));
+        assert!(is_window_accel("<ctrl>s"));
+        assert!(is_window_accel("<Control><Shift>s"));
+        assert!(!is_window_accel("<Control>t"));
+    }
+}
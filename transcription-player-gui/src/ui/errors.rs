@@ -0,0 +1,28 @@
+use std::error::Error;
+
+use gtk::prelude::*;
+
+/// The message of `err` followed by the messages of its causes, one per line
+pub fn error_chain(err: &dyn Error) -> String {
+    let mut messages = vec![err.to_string()];
+    let mut source = err.source();
+    while let Some(cause) = source {
+        messages.push(cause.to_string());
+        source = cause.source();
+    }
+    messages.join("\n")
+}
+
+/// Shows a dialog with `title` and the cause chain of `err`
+pub fn show_error(parent: Option<&gtk::Window>, title: &str, err: &dyn Error) {
+    let dialog = gtk::MessageDialog::builder()
+        .modal(true)
+        .message_type(gtk::MessageType::Error)
+        .buttons(gtk::ButtonsType::Close)
+        .text(title)
+        .secondary_text(&error_chain(err))
+        .build();
+    dialog.set_transient_for(parent);
+    dialog.connect_response(|dialog, _| dialog.close());
+    dialog.show();
+}
@@ -0,0 +1,58 @@
+use crate::{
+    audio::{AudioFormat, SUPPORTED_FORMATS},
+    project::PROJECT_EXTENSION,
+};
+
+fn add_format(filter: &gtk::FileFilter, format: &AudioFormat) {
+    for extension in format.extensions {
+        // the patterns are case sensitive
+        filter.add_pattern(&format!("*.{}", extension));
+        filter.add_pattern(&format!("*.{}", extension.to_uppercase()));
+    }
+    for mime_type in format.mime_types {
+        filter.add_mime_type(mime_type);
+    }
+}
+
+fn add_project(filter: &gtk::FileFilter) {
+    filter.add_pattern(&format!("*.{}", PROJECT_EXTENSION));
+}
+
+pub fn project_filter() -> gtk::FileFilter {
+    let filter = gtk::FileFilter::new();
+    filter.set_name(Some("Transcrible projects"));
+    add_project(&filter);
+    filter
+}
+
+pub fn all_files_filter() -> gtk::FileFilter {
+    let filter = gtk::FileFilter::new();
+    filter.set_name(Some("All files"));
+    filter.add_pattern("*");
+    filter
+}
+
+/// The filters of the dialog which opens audio files and projects, the first one is selected
+/// by default. The audio formats are the ones in [`SUPPORTED_FORMATS`].
+pub fn open_filters() -> Vec<gtk::FileFilter> {
+    let supported = gtk::FileFilter::new();
+    supported.set_name(Some("Audio files and projects"));
+    let audio = gtk::FileFilter::new();
+    audio.set_name(Some("Audio files"));
+    for format in &SUPPORTED_FORMATS {
+        add_format(&supported, format);
+        add_format(&audio, format);
+    }
+    add_project(&supported);
+
+    let mut filters = vec![supported, audio];
+    filters.extend(SUPPORTED_FORMATS.iter().map(|format| {
+        let filter = gtk::FileFilter::new();
+        filter.set_name(Some(format.name));
+        add_format(&filter, format);
+        filter
+    }));
+    filters.push(project_filter());
+    filters.push(all_files_filter());
+    filters
+}
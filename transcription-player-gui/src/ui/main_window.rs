@@ -1,34 +1,494 @@
-use std::path::PathBuf;
+use std::{
+    cell::{Cell, RefCell},
+    path::{Path, PathBuf},
+    rc::Rc,
+    sync::Arc,
+    time::Duration,
+};
 
 use adw::prelude::*;
-use gtk::prelude::{BoxExt, ButtonExt, GtkWindowExt, OrientableExt};
+use gtk::{
+    gdk, gio,
+    glib::clone,
+    prelude::{BoxExt, ButtonExt, GtkWindowExt, OrientableExt},
+};
 use relm4::{
-    send, AppUpdate, Components, Model, RelmApp, RelmComponent, Sender, WidgetPlus, Widgets,
+    send, AppUpdate, Components, Model, RelmApp, RelmComponent, RelmWorker, Sender, WidgetPlus,
+    Widgets,
 };
 use relm4_components::{
     open_button::{OpenButtonConfig, OpenButtonModel, OpenButtonParent, OpenButtonSettings},
-    open_dialog::{OpenDialogConfig, OpenDialogSettings},
+    open_dialog::{
+        OpenDialogConfig, OpenDialogModel, OpenDialogMsg, OpenDialogParent, OpenDialogSettings,
+    },
+    save_dialog::{
+        SaveDialogConfig, SaveDialogModel, SaveDialogMsg, SaveDialogParent, SaveDialogSettings,
+    },
     ParentWindow,
 };
 
-use crate::audio::AudioPlayer;
+use crate::{
+    audio::{
+        channels::{ChannelMode, ChannelRouting},
+        equalizer::{EqBand, EqParameter, EqPreset, EqSettings},
+        peaks::Peaks,
+        worker::{AudioPlayerMsg, AudioPlayerWorkerModel, AudioPlayerWorkerParent},
+        AudioError, LoopRegion, PlaybackControl, MAX_SPEED, MIN_SPEED,
+    },
+    history::{FileState, History},
+    project::{self, LoopPoints, Project, ProjectError, PROJECT_EXTENSION},
+    recovery::{CrashedSession, Recovery},
+    settings,
+};
+
+use super::{
+    actions, errors, file_filters, preferences, recent_files::recent_files_button, recovery,
+    transcript, waveform::Waveform,
+};
+
+/// A file passed on the command line, or why it can't be opened
+pub type OpenRequest = Result<PathBuf, String>;
+
+/// how much the speed changes when it is sped up or slowed down
+const SPEED_STEP: f64 = 0.1;
+
+/// how often the player tries to connect to an output device while there is none
+const OUTPUT_RETRY_INTERVAL: u32 = 5;
 
 struct AppModel {
-    player: AudioPlayer,
+    settings: gio::Settings,
+    transcript: gtk::TextBuffer,
+    waveform: Waveform,
+    /// positions the user marked to come back to later
+    markers: Vec<Duration>,
+    playback: Option<PlaybackControl>,
+    /// the loaded audio file
+    audio_path: Option<PathBuf>,
+    /// where the project was last opened from or saved to
+    project_path: Option<PathBuf>,
+    loop_region: Option<LoopRegion>,
+    recovery: Recovery,
+    /// the project which was autosaved last
+    autosaved: Option<Project>,
+    /// the project as it was last opened or saved, `None` if the session was never saved
+    saved: Option<Project>,
+    /// set once the window may close, after the unsaved changes were saved or discarded
+    close_allowed: Rc<Cell<bool>>,
+    /// what is done once the project is saved to the path chosen in the dialog
+    after_save: Option<PendingAction>,
+    /// shared with the window, which saves it when it closes
+    history: Rc<RefCell<History>>,
+    eq: EqSettings,
+    channel_routing: ChannelRouting,
+    /// whether a noise profile was captured for the loaded file
+    has_noise_profile: bool,
+    denoise: bool,
+    /// shows how much of the loaded file is decoded into memory
+    load_progress: gtk::ProgressBar,
+    /// shows the toasts, it is the content of the window
+    toast_overlay: adw::ToastOverlay,
+    /// why the player couldn't connect to an output device, `None` if it is connected
+    output_error: Option<String>,
+    /// whether the last autosave failed, so that a failure is only shown once
+    autosave_failed: bool,
+    /// the files passed on the command line, it is taken when the components are initialized
+    open_requests: RefCell<Option<glib::Receiver<OpenRequest>>>,
+    speed: f64,
+    pitch_semitones: f64,
+    /// 0.0 lets rubberband choose the formant scale which matches the pitch
+    formant_scale: f64,
+    position: Duration,
+    duration: Option<Duration>,
+}
+
+impl AppModel {
+    fn set_speed(&mut self, speed: f64) {
+        // rounded, so that repeated steps don't accumulate floating point errors
+        self.speed = ((speed * 100.0).round() / 100.0).clamp(MIN_SPEED, MAX_SPEED);
+        if let Some(playback) = &self.playback {
+            playback.set_speed(self.speed);
+        }
+    }
+
+    /// Stops the current file and loads the audio file at `path`. The markers and the waveform
+    /// of the previous file are removed.
+    fn load_audio(&mut self, path: PathBuf, components: &AppComponents) {
+        self.remember_file();
+        self.history.borrow_mut().save();
+        // the playback of the previous file doesn't belong to `path`
+        self.playback = None;
+        self.markers.clear();
+        self.waveform.set_markers(vec![]);
+        self.waveform.set_peaks(None);
+        // the noise profile of the previous file doesn't fit `path`
+        self.has_noise_profile = false;
+        self.denoise = false;
+        send!(
+            components.player.sender(),
+            AudioPlayerMsg::SetDenoise(false)
+        );
+        self.audio_path = Some(path.clone());
+        send!(components.player.sender(), AudioPlayerMsg::Load(path));
+    }
+
+    /// Stores the state of the playback of the loaded file in the history
+    fn remember_file(&self) {
+        if let (Some(path), Some(_)) = (&self.audio_path, &self.playback) {
+            self.history.borrow_mut().remember(FileState {
+                path: path.clone(),
+                position: self.position,
+                duration: self.duration,
+                speed: self.speed,
+                pitch: self.pitch_semitones,
+                loop_points: self.loop_region.map(|loop_region| LoopPoints {
+                    start: loop_region.start,
+                    end: loop_region.end,
+                }),
+                last_opened: 0,
+            });
+        }
+    }
+
+    /// Continues the playback of `path` where it stopped the last time the file was open
+    fn restore_file_state(&mut self, path: &Path, components: &AppComponents) {
+        let state = match self.history.borrow().get(path) {
+            Some(state) => state.clone(),
+            None => return,
+        };
+        self.set_speed(state.speed);
+        self.set_pitch(state.pitch);
+        let sender = components.player.sender();
+        if !state.is_finished() {
+            send!(sender, AudioPlayerMsg::Seek(state.position));
+        }
+        if let Some(LoopPoints { start, end }) = state.loop_points {
+            send!(sender, AudioPlayerMsg::SetLoopPoints { start, end });
+        }
+    }
+
+    fn set_pitch(&mut self, semitones: f64) {
+        self.pitch_semitones = semitones;
+        if let Some(playback) = &self.playback {
+            playback.set_pitch_semitones(semitones);
+        }
+    }
+
+    /// The current session as project which is saved to `project_path`
+    fn project(&self, project_path: &Path) -> Project {
+        let (start, end) = self.transcript.bounds();
+        let mut project = Project {
+            transcript: self.transcript.text(&start, &end, false).to_string(),
+            position: self.position,
+            speed: self.speed,
+            pitch: self.pitch_semitones,
+            loop_points: self.loop_region.map(|loop_region| LoopPoints {
+                start: loop_region.start,
+                end: loop_region.end,
+            }),
+            markers: self.markers.clone(),
+            eq: self.eq,
+            channels: self.channel_routing,
+            ..Project::new()
+        };
+        if let Some(audio_path) = &self.audio_path {
+            project.set_audio(audio_path, project_path);
+        }
+        project
+    }
+
+    /// Restores the session of `project`. The relative path of the audio file is resolved
+    /// against `project_path`, the path the project was loaded from.
+    fn open_project(&mut self, project: Project, project_path: &Path, components: &AppComponents) {
+        self.transcript.set_text(&project.transcript);
+        self.set_speed(project.speed);
+        self.set_pitch(project.pitch);
+        self.eq = project.eq;
+        send!(components.player.sender(), AudioPlayerMsg::SetEq(self.eq));
+        self.channel_routing = project.channels;
+        send!(
+            components.player.sender(),
+            AudioPlayerMsg::SetChannelRouting(self.channel_routing)
+        );
+        match project
+            .audio
+            .as_ref()
+            .and_then(|audio| audio.resolve(project_path))
+        {
+            Some(audio_path) => {
+                self.load_audio(audio_path, components);
+                let sender = components.player.sender();
+                send!(sender, AudioPlayerMsg::Seek(project.position));
+                if let Some(LoopPoints { start, end }) = project.loop_points {
+                    send!(sender, AudioPlayerMsg::SetLoopPoints { start, end });
+                }
+                self.markers = project.markers;
+                self.waveform.set_markers(self.markers.clone());
+            }
+            None => match project.audio {
+                Some(audio) => self.show_toast(&format!(
+                    "Couldn't find the audio file \"{}\"",
+                    audio.absolute.display()
+                )),
+                None => log::warn!("The project {:?} has no audio file", project_path),
+            },
+        }
+    }
+
+    /// Saves the session to the recovery directory, if it changed since the last autosave
+    fn autosave(&mut self) {
+        let project = self.project(&self.recovery.file());
+        if project.audio.is_none() && project.transcript.is_empty() {
+            // there is nothing to lose yet
+            return;
+        }
+        if self.autosaved.as_ref() == Some(&project) {
+            return;
+        }
+        match self
+            .recovery
+            .autosave(&project, self.project_path.as_deref())
+        {
+            Ok(()) => {
+                self.autosaved = Some(project);
+                self.autosave_failed = false;
+            }
+            Err(err) => {
+                log::error!("Couldn't autosave the session: {}", err);
+                if !self.autosave_failed {
+                    self.show_toast("Couldn't autosave the session, see the log for details");
+                }
+                self.autosave_failed = true;
+            }
+        }
+    }
+
+    fn send_channel_routing(&self, components: &AppComponents) {
+        send!(
+            components.player.sender(),
+            AudioPlayerMsg::SetChannelRouting(self.channel_routing)
+        );
+    }
+
+    /// Whether the transcript or the markers changed since the project was opened or saved
+    fn has_unsaved_changes(&self) -> bool {
+        let (start, end) = self.transcript.bounds();
+        let transcript = self.transcript.text(&start, &end, false);
+        match &self.saved {
+            Some(saved) => saved.transcript != transcript.as_str() || saved.markers != self.markers,
+            None => !transcript.is_empty() || !self.markers.is_empty(),
+        }
+    }
+
+    /// Does `action` right away if there are no unsaved changes. Otherwise it asks whether the
+    /// changes should be saved first.
+    fn ask_save_changes(&self, action: PendingAction, sender: &Sender<AppMsg>) {
+        match self.window() {
+            Some(window) if self.has_unsaved_changes() => {
+                let name = self.project_path.as_ref().and_then(|path| path.file_name());
+                let (doing, discard_label) = match action {
+                    PendingAction::Close => ("closing", "Close without Saving"),
+                    PendingAction::OpenProject(_) => {
+                        ("opening another project", "Open without Saving")
+                    }
+                };
+                recovery::ask_save_changes(
+                    &window,
+                    name.map(|name| name.to_string_lossy()).as_deref(),
+                    doing,
+                    discard_label,
+                    clone!(@strong sender => move |save| {
+                        let action = action.clone();
+                        send!(sender, if save {
+                            AppMsg::SaveAndContinue(action)
+                        } else {
+                            AppMsg::Continue(action)
+                        });
+                    }),
+                );
+            }
+            _ => send!(sender, AppMsg::Continue(action)),
+        }
+    }
+
+    /// Does `action`, the unsaved changes were saved or discarded before
+    fn continue_with(&mut self, action: PendingAction, components: &AppComponents) {
+        match action {
+            PendingAction::Close => self.close(),
+            PendingAction::OpenProject(path) => match Project::load(&path) {
+                Ok(project) => {
+                    self.saved = Some(project.clone());
+                    self.open_project(project, &path, components);
+                    self.project_path = Some(path);
+                }
+                Err(err) => self.show_error("Couldn't open the project", &err),
+            },
+        }
+    }
+
+    /// Closes the window, without asking about unsaved changes again
+    fn close(&self) {
+        // the close request saves the history
+        self.remember_file();
+        self.close_allowed.set(true);
+        if let Some(window) = self.window() {
+            window.close();
+        }
+    }
+
+    fn window(&self) -> Option<gtk::Window> {
+        self.toast_overlay
+            .root()
+            .and_then(|root| root.downcast::<gtk::Window>().ok())
+    }
+
+    /// Shows `message` in a toast, it is plain text and not markup
+    fn show_toast(&self, message: &str) {
+        let message = glib::markup_escape_text(message);
+        self.toast_overlay.add_toast(&adw::Toast::new(&message));
+    }
+
+    /// Shows `err` with its causes in a dialog above the window
+    fn show_error(&self, title: &str, err: &dyn std::error::Error) {
+        errors::show_error(self.window().as_ref(), title, err);
+    }
+
+    fn save_project(&mut self, project_path: PathBuf) -> Result<(), ProjectError> {
+        let project = self.project(&project_path);
+        project.save(&project_path)?;
+        self.saved = Some(project);
+        self.project_path = Some(project_path);
+        Ok(())
+    }
+
+    /// The file name which is proposed when the project is saved for the first time
+    fn project_file_name(&self) -> String {
+        let stem = self
+            .audio_path
+            .as_ref()
+            .and_then(|path| path.file_stem())
+            .map_or("Untitled".into(), |stem| stem.to_string_lossy());
+        format!("{}.{}", stem, PROJECT_EXTENSION)
+    }
+
+    fn time_label(&self) -> String {
+        match self.duration {
+            Some(duration) => format!("{} / {}", format_time(self.position), format_time(duration)),
+            None => format_time(self.position),
+        }
+    }
+}
+
+/// Formats `time` as `hh:mm:ss`
+pub fn format_time(time: Duration) -> String {
+    let seconds = time.as_secs();
+    format!(
+        "{:02}:{:02}:{:02}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+/// An action which replaces the session. It waits until the unsaved changes are saved or
+/// discarded.
+#[derive(Clone)]
+enum PendingAction {
+    Close,
+    OpenProject(PathBuf),
 }
 
 enum AppMsg {
+    /// loads an audio file, or opens the project if the path is a project file
     LoadFile(PathBuf),
+    /// how much of the file is decoded into memory, `None` if it isn't known
+    LoadProgress(Option<f64>),
+    FileLoaded(PlaybackControl),
+    LoadingFailed(AudioError),
+    /// the player connected to an output device, or couldn't connect because of the error
+    OutputChanged(Option<AudioError>),
+    /// tries to connect to an output device again
+    RetryOutput,
+    /// uses the loop region as the noise profile of the denoiser
+    CaptureNoiseProfile,
+    NoiseProfileCaptured(Result<(), AudioError>),
+    SetDenoise(bool),
+    SetEq(EqSettings),
+    /// switches a filter of the equalizer on or off
+    SetEqBand(EqBand, bool),
+    SetEqParameter(EqParameter, f32),
+    /// the mains frequency whose hum is removed, `None` keeps the hum
+    SetHum(Option<f32>),
+    SetChannelMode(ChannelMode),
+    /// the gain of the left channel in dB
+    SetLeftGain(f32),
+    /// the gain of the right channel in dB
+    SetRightGain(f32),
+    /// shows a short message in a toast
+    ShowToast(String),
     TogglePlayStatus,
+    PositionChanged {
+        position: Duration,
+        duration: Option<Duration>,
+        loop_region: Option<LoopRegion>,
+    },
+    PeaksComputed(Arc<Peaks>),
+    /// shows the dialog to open a project
+    ShowOpenProjectDialog,
+    /// opens the project, after asking whether unsaved changes should be saved first
+    OpenProject(PathBuf),
+    /// saves the project to the path it was opened from, or asks for a path
+    SaveProject,
+    /// asks for the path the project is saved to
+    SaveProjectAs,
+    SaveProjectTo(PathBuf),
+    /// saves the session to the recovery directory
+    Autosave,
+    /// the user wants to close the window, it asks whether unsaved changes should be saved first
+    CloseRequested,
+    /// saves the project, then does the action
+    SaveAndContinue(PendingAction),
+    /// does the action without saving the unsaved changes
+    Continue(PendingAction),
+    /// restores the autosave of a session which crashed
+    RestoreSession(CrashedSession),
+    DiscardSession(CrashedSession),
+    SeekTo(Duration),
+    /// marks the current position in the waveform
+    AddMarker,
+    /// inserts the current position as timestamp at the cursor of the transcript
+    InsertTimestamp,
+    /// skips by the given number of milliseconds, negative values skip backwards
+    SkipBy(i64),
+    SkipSmallBackward,
+    SkipSmallForward,
+    SkipLargeBackward,
+    SkipLargeForward,
+    SetLoopStart,
+    SetLoopEnd,
+    ClearLoop,
+    /// plays the file from the start, instead of where it stopped the last time
+    StartFromBeginning,
+    SetSpeed(f64),
+    SpeedUp,
+    SpeedDown,
+    /// sets the pitch in semitones relative to the original pitch
+    SetPitch(f64),
+    /// sets the formant scale, 0.0 keeps the formants of the original voice
+    SetFormant(f64),
 }
 
 struct AppComponents {
     open_button: RelmComponent<OpenButtonModel<AppOpenButtonConfig>, AppModel>,
+    player: RelmWorker<AudioPlayerWorkerModel, AppModel>,
+    open_project_dialog: RelmComponent<OpenDialogModel<ProjectDialogConfig>, AppModel>,
+    save_project_dialog: RelmComponent<SaveDialogModel<ProjectDialogConfig>, AppModel>,
 }
 
 struct AppOpenButtonConfig {}
 impl OpenButtonConfig for AppOpenButtonConfig {
     fn open_button_config(_model: &AppModel) -> OpenButtonSettings {
+        // the recent files are listed by the button next to it, which also shows the position
+        // they stopped at
         OpenButtonSettings {
             text: "Open",
             recently_opened_files: None,
@@ -45,28 +505,244 @@ impl OpenDialogConfig for AppOpenButtonConfig {
             accept_label: "Open",
             create_folders: false,
             is_modal: true,
-            filters: vec![],
+            filters: file_filters::open_filters(),
+        }
+    }
+}
+
+struct ProjectDialogConfig {}
+
+impl OpenDialogConfig for ProjectDialogConfig {
+    type Model = AppModel;
+    fn open_dialog_config(_model: &AppModel) -> OpenDialogSettings {
+        OpenDialogSettings {
+            cancel_label: "Cancel",
+            accept_label: "Open",
+            create_folders: false,
+            is_modal: true,
+            filters: vec![
+                file_filters::project_filter(),
+                file_filters::all_files_filter(),
+            ],
+        }
+    }
+}
+
+impl SaveDialogConfig for ProjectDialogConfig {
+    type Model = AppModel;
+    fn dialog_config(_model: &AppModel) -> SaveDialogSettings {
+        SaveDialogSettings {
+            cancel_label: "Cancel",
+            accept_label: "Save",
+            create_folders: true,
+            is_modal: true,
+            filters: vec![file_filters::project_filter()],
         }
     }
 }
 
+impl OpenDialogParent for AppModel {
+    fn open_msg(path: PathBuf) -> AppMsg {
+        AppMsg::OpenProject(path)
+    }
+}
+
+impl SaveDialogParent for AppModel {
+    fn save_msg(path: PathBuf) -> AppMsg {
+        AppMsg::SaveProjectTo(path)
+    }
+}
+
 impl OpenButtonParent for AppModel {
     fn open_msg(path: std::path::PathBuf) -> AppMsg {
         AppMsg::LoadFile(path)
     }
 }
 
+impl AudioPlayerWorkerParent for AppModel {
+    fn loading_done_msg(control: PlaybackControl) -> AppMsg {
+        AppMsg::FileLoaded(control)
+    }
+
+    fn loading_error_msg(err: AudioError) -> AppMsg {
+        AppMsg::LoadingFailed(err)
+    }
+
+    fn load_progress_msg(progress: Option<f64>) -> AppMsg {
+        AppMsg::LoadProgress(progress)
+    }
+
+    fn noise_profile_msg(result: Result<(), AudioError>) -> AppMsg {
+        AppMsg::NoiseProfileCaptured(result)
+    }
+
+    fn output_msg(error: Option<AudioError>) -> AppMsg {
+        AppMsg::OutputChanged(error)
+    }
+
+    fn position_msg(
+        position: Duration,
+        duration: Option<Duration>,
+        loop_region: Option<LoopRegion>,
+    ) -> AppMsg {
+        AppMsg::PositionChanged {
+            position,
+            duration,
+            loop_region,
+        }
+    }
+
+    fn peaks_msg(peaks: Arc<Peaks>) -> AppMsg {
+        AppMsg::PeaksComputed(peaks)
+    }
+}
+
+fn loop_options_msg(settings: &gio::Settings) -> AudioPlayerMsg {
+    let (gap, max_repeats) = settings::loop_options(settings);
+    AudioPlayerMsg::SetLoopOptions { gap, max_repeats }
+}
+
+/// Sends [`AppMsg::Autosave`] every `interval` seconds. Returns the timer, or `None` if autosaving
+/// is disabled.
+fn start_autosave(interval: u32, sender: Sender<AppMsg>) -> Option<glib::SourceId> {
+    if interval == 0 {
+        return None;
+    }
+    Some(glib::timeout_add_seconds_local(interval, move || {
+        glib::Continue(sender.send(AppMsg::Autosave).is_ok())
+    }))
+}
+
 impl Components<AppModel> for AppComponents {
     fn init_components(
         parent_model: &AppModel,
         parent_sender: Sender<<AppModel as Model>::Msg>,
     ) -> Self {
+        let player = RelmWorker::with_new_thread(parent_model, parent_sender.clone());
+        send!(
+            player.sender(),
+            AudioPlayerMsg::ConnectOutput {
+                report_failure: true
+            }
+        );
+        send!(
+            player.sender(),
+            AudioPlayerMsg::SetPositionUpdateInterval(settings::position_update_interval(
+                &parent_model.settings
+            ))
+        );
+        send!(
+            player.sender(),
+            AudioPlayerMsg::SetResumeRewind(settings::resume_rewind(&parent_model.settings))
+        );
+        send!(
+            player.sender(),
+            AudioPlayerMsg::SetSkipSteps(settings::skip_steps(&parent_model.settings))
+        );
+        send!(player.sender(), loop_options_msg(&parent_model.settings));
+        send!(
+            player.sender(),
+            AudioPlayerMsg::SetPeakCacheSize(settings::peak_cache_size(&parent_model.settings))
+        );
+        send!(
+            player.sender(),
+            AudioPlayerMsg::SetDynamics(settings::dynamics(&parent_model.settings))
+        );
+        send!(
+            player.sender(),
+            AudioPlayerMsg::SetMemoryDecodeLimit(settings::memory_decode_limit(
+                &parent_model.settings
+            ))
+        );
+        let player_sender = player.sender();
+        parent_model
+            .settings
+            .connect_changed(None, move |settings, key| {
+                if settings::RESUME_REWIND_KEYS.contains(&key) {
+                    send!(
+                        player_sender,
+                        AudioPlayerMsg::SetResumeRewind(settings::resume_rewind(settings))
+                    );
+                } else if settings::SKIP_STEP_KEYS.contains(&key) {
+                    send!(
+                        player_sender,
+                        AudioPlayerMsg::SetSkipSteps(settings::skip_steps(settings))
+                    );
+                } else if settings::LOOP_KEYS.contains(&key) {
+                    send!(player_sender, loop_options_msg(settings));
+                } else if key == settings::PEAK_CACHE_SIZE {
+                    send!(
+                        player_sender,
+                        AudioPlayerMsg::SetPeakCacheSize(settings::peak_cache_size(settings))
+                    );
+                } else if settings::DYNAMICS_KEYS.contains(&key) {
+                    send!(
+                        player_sender,
+                        AudioPlayerMsg::SetDynamics(settings::dynamics(settings))
+                    );
+                } else if key == settings::POSITION_UPDATE_INTERVAL {
+                    send!(
+                        player_sender,
+                        AudioPlayerMsg::SetPositionUpdateInterval(
+                            settings::position_update_interval(settings)
+                        )
+                    );
+                } else if key == settings::MEMORY_DECODE_LIMIT {
+                    send!(
+                        player_sender,
+                        AudioPlayerMsg::SetMemoryDecodeLimit(settings::memory_decode_limit(
+                            settings
+                        ))
+                    );
+                }
+            });
+        let player_sender = player.sender();
+        glib::timeout_add_seconds_local(OUTPUT_RETRY_INTERVAL, move || {
+            let msg = AudioPlayerMsg::ConnectOutput {
+                report_failure: false,
+            };
+            glib::Continue(player_sender.send(msg).is_ok())
+        });
+        if let Some(open_requests) = parent_model.open_requests.borrow_mut().take() {
+            let open_sender = parent_sender.clone();
+            open_requests.attach(None, move |request| {
+                let msg = match request {
+                    Ok(path) => AppMsg::LoadFile(path),
+                    Err(message) => AppMsg::ShowToast(message),
+                };
+                glib::Continue(open_sender.send(msg).is_ok())
+            });
+        }
+        let autosave_sender = parent_sender.clone();
+        let autosave = RefCell::new(start_autosave(
+            settings::autosave_interval(&parent_model.settings),
+            autosave_sender.clone(),
+        ));
+        // the timer is restarted, so that the new interval applies right away
+        parent_model.settings.connect_changed(
+            Some(settings::AUTOSAVE_INTERVAL),
+            move |settings, _| {
+                if let Some(timer) = autosave.take() {
+                    timer.remove();
+                }
+                autosave.replace(start_autosave(
+                    settings::autosave_interval(settings),
+                    autosave_sender.clone(),
+                ));
+            },
+        );
         AppComponents {
-            open_button: RelmComponent::new(parent_model, parent_sender),
+            open_button: RelmComponent::new(parent_model, parent_sender.clone()),
+            player,
+            open_project_dialog: RelmComponent::new(parent_model, parent_sender.clone()),
+            save_project_dialog: RelmComponent::new(parent_model, parent_sender),
         }
     }
 
-    fn connect_parent(&mut self, _parent_widgets: &<AppModel as Model>::Widgets) {}
+    fn connect_parent(&mut self, parent_widgets: &<AppModel as Model>::Widgets) {
+        self.open_project_dialog.connect_parent(parent_widgets);
+        self.save_project_dialog.connect_parent(parent_widgets);
+    }
 }
 
 impl Model for AppModel {
@@ -76,15 +752,251 @@ impl Model for AppModel {
 }
 
 impl AppUpdate for AppModel {
-    fn update(
-        &mut self,
-        msg: AppMsg,
-        _components: &AppComponents,
-        _sender: Sender<AppMsg>,
-    ) -> bool {
+    fn update(&mut self, msg: AppMsg, components: &AppComponents, sender: Sender<AppMsg>) -> bool {
         match msg {
-            AppMsg::LoadFile(path) => self.player.load(&path).unwrap(),
-            AppMsg::TogglePlayStatus => self.player.toggle_play_status(),
+            AppMsg::LoadFile(path) if project::is_project_file(&path) => {
+                send!(sender, AppMsg::OpenProject(path));
+            }
+            AppMsg::LoadFile(path) => {
+                // a new session starts, which is saved as new project
+                self.project_path = None;
+                self.load_audio(path.clone(), components);
+                if self.settings.boolean(settings::RESTORE_POSITION) {
+                    self.restore_file_state(&path, components);
+                }
+            }
+            AppMsg::LoadProgress(progress) => {
+                self.load_progress.set_visible(true);
+                match progress {
+                    Some(fraction) => self.load_progress.set_fraction(fraction),
+                    None => self.load_progress.pulse(),
+                }
+            }
+            AppMsg::FileLoaded(playback) => {
+                self.load_progress.set_visible(false);
+                playback.set_speed(self.speed);
+                playback.set_pitch_semitones(self.pitch_semitones);
+                playback.set_formant_scale(self.formant_scale);
+                self.playback = Some(playback);
+            }
+            AppMsg::LoadingFailed(err) => {
+                self.load_progress.set_visible(false);
+                self.audio_path = None;
+                self.show_error("Couldn't open the audio file", &err);
+            }
+            AppMsg::OutputChanged(error) => {
+                if error.is_none() && self.output_error.is_some() {
+                    self.show_toast("Connected to the output device");
+                }
+                self.output_error = error.map(|err| errors::error_chain(&err).replace('\n', ": "));
+            }
+            AppMsg::RetryOutput => {
+                send!(
+                    components.player.sender(),
+                    AudioPlayerMsg::ConnectOutput {
+                        report_failure: true
+                    }
+                );
+            }
+            AppMsg::ShowToast(message) => self.show_toast(&message),
+            AppMsg::CaptureNoiseProfile => match self.loop_region {
+                Some(LoopRegion { start, end, .. }) => send!(
+                    components.player.sender(),
+                    AudioPlayerMsg::CaptureNoiseProfile { start, end }
+                ),
+                None => self.show_toast("Select a region with only noise using A and B first"),
+            },
+            AppMsg::NoiseProfileCaptured(result) => match result {
+                Ok(()) => {
+                    self.has_noise_profile = true;
+                    self.denoise = true;
+                    send!(components.player.sender(), AudioPlayerMsg::SetDenoise(true));
+                    self.show_toast("Captured the noise profile");
+                }
+                Err(err) => self.show_error("Couldn't capture the noise profile", &err),
+            },
+            AppMsg::SetEq(eq) => {
+                self.eq = eq;
+                send!(components.player.sender(), AudioPlayerMsg::SetEq(eq));
+            }
+            AppMsg::SetEqBand(band, enabled) => {
+                self.eq.set_enabled(band, enabled);
+                send!(components.player.sender(), AudioPlayerMsg::SetEq(self.eq));
+            }
+            AppMsg::SetEqParameter(parameter, value) => {
+                self.eq.set_parameter(parameter, value);
+                send!(components.player.sender(), AudioPlayerMsg::SetEq(self.eq));
+            }
+            AppMsg::SetHum(hum) => {
+                self.eq.hum = hum;
+                send!(components.player.sender(), AudioPlayerMsg::SetEq(self.eq));
+            }
+            AppMsg::SetChannelMode(mode) => {
+                self.channel_routing.mode = mode;
+                self.send_channel_routing(components);
+            }
+            AppMsg::SetLeftGain(gain) => {
+                self.channel_routing.left_gain = gain;
+                self.send_channel_routing(components);
+            }
+            AppMsg::SetRightGain(gain) => {
+                self.channel_routing.right_gain = gain;
+                self.send_channel_routing(components);
+            }
+            AppMsg::SetDenoise(enabled) => {
+                self.denoise = enabled;
+                send!(
+                    components.player.sender(),
+                    AudioPlayerMsg::SetDenoise(enabled)
+                );
+            }
+            AppMsg::TogglePlayStatus => {
+                send!(components.player.sender(), AudioPlayerMsg::TogglePlayPause);
+                self.remember_file();
+                self.history.borrow_mut().save();
+            }
+            AppMsg::PositionChanged {
+                position,
+                duration,
+                loop_region,
+            } => {
+                self.position = position;
+                self.duration = duration;
+                self.loop_region = loop_region;
+                self.waveform.set_position(position, duration);
+                self.waveform.set_loop_region(loop_region);
+            }
+            AppMsg::PeaksComputed(peaks) => {
+                self.waveform.set_peaks(Some(peaks));
+            }
+            AppMsg::ShowOpenProjectDialog => {
+                send!(components.open_project_dialog.sender(), OpenDialogMsg::Open);
+            }
+            AppMsg::OpenProject(path) => {
+                self.ask_save_changes(PendingAction::OpenProject(path), &sender);
+            }
+            AppMsg::SaveProject => match self.project_path.clone() {
+                Some(path) => {
+                    if let Err(err) = self.save_project(path) {
+                        self.show_error("Couldn't save the project", &err);
+                    }
+                }
+                None => {
+                    self.after_save = None;
+                    send!(
+                        components.save_project_dialog.sender(),
+                        SaveDialogMsg::SaveAs(self.project_file_name())
+                    );
+                }
+            },
+            AppMsg::SaveProjectAs => {
+                self.after_save = None;
+                send!(
+                    components.save_project_dialog.sender(),
+                    SaveDialogMsg::SaveAs(self.project_file_name())
+                );
+            }
+            AppMsg::SaveProjectTo(path) => match self.save_project(path) {
+                Ok(()) => {
+                    if let Some(action) = self.after_save.take() {
+                        self.continue_with(action, components);
+                    }
+                }
+                Err(err) => self.show_error("Couldn't save the project", &err),
+            },
+            AppMsg::Autosave => {
+                self.autosave();
+                self.remember_file();
+                self.history.borrow_mut().save();
+            }
+            AppMsg::CloseRequested => self.ask_save_changes(PendingAction::Close, &sender),
+            AppMsg::SaveAndContinue(action) => match self.project_path.clone() {
+                Some(path) => match self.save_project(path) {
+                    Ok(()) => self.continue_with(action, components),
+                    Err(err) => self.show_error("Couldn't save the project", &err),
+                },
+                None => {
+                    self.after_save = Some(action);
+                    send!(
+                        components.save_project_dialog.sender(),
+                        SaveDialogMsg::SaveAs(self.project_file_name())
+                    );
+                }
+            },
+            AppMsg::Continue(action) => self.continue_with(action, components),
+            AppMsg::RestoreSession(session) => match self.recovery.restore(&session) {
+                Ok(project) => {
+                    // the autosave was written with its audio path relative to the recovery
+                    // directory
+                    let recovery_file = self.recovery.file();
+                    self.open_project(project, &recovery_file, components);
+                    self.project_path = session.project_path;
+                    // the changes of the crashed session were never saved
+                    self.saved = None;
+                }
+                Err(err) => self.show_error("Couldn't restore the session", &err),
+            },
+            AppMsg::DiscardSession(session) => self.recovery.discard_session(&session),
+            AppMsg::SeekTo(time) => {
+                send!(components.player.sender(), AudioPlayerMsg::Seek(time));
+            }
+            AppMsg::InsertTimestamp => {
+                transcript::insert_timestamp(&self.transcript, self.position);
+            }
+            AppMsg::AddMarker => {
+                if self.playback.is_some() {
+                    self.markers.push(self.position);
+                    self.markers.sort();
+                    self.waveform.set_markers(self.markers.clone());
+                }
+            }
+            AppMsg::SkipBy(offset) => {
+                send!(components.player.sender(), AudioPlayerMsg::SkipBy(offset));
+            }
+            AppMsg::SkipSmallBackward => {
+                send!(
+                    components.player.sender(),
+                    AudioPlayerMsg::SkipSmallBackward
+                );
+            }
+            AppMsg::SkipSmallForward => {
+                send!(components.player.sender(), AudioPlayerMsg::SkipSmallForward);
+            }
+            AppMsg::SkipLargeBackward => {
+                send!(
+                    components.player.sender(),
+                    AudioPlayerMsg::SkipLargeBackward
+                );
+            }
+            AppMsg::SkipLargeForward => {
+                send!(components.player.sender(), AudioPlayerMsg::SkipLargeForward);
+            }
+            AppMsg::SetLoopStart => {
+                send!(components.player.sender(), AudioPlayerMsg::SetLoopStart);
+            }
+            AppMsg::SetLoopEnd => {
+                send!(components.player.sender(), AudioPlayerMsg::SetLoopEnd);
+            }
+            AppMsg::ClearLoop => {
+                send!(components.player.sender(), AudioPlayerMsg::ClearLoop);
+            }
+            AppMsg::StartFromBeginning => {
+                send!(components.player.sender(), AudioPlayerMsg::ClearLoop);
+                send!(
+                    components.player.sender(),
+                    AudioPlayerMsg::Seek(Duration::ZERO)
+                );
+            }
+            AppMsg::SetSpeed(speed) => self.set_speed(speed),
+            AppMsg::SpeedUp => self.set_speed(self.speed + SPEED_STEP),
+            AppMsg::SpeedDown => self.set_speed(self.speed - SPEED_STEP),
+            AppMsg::SetPitch(semitones) => self.set_pitch(semitones),
+            AppMsg::SetFormant(scale) => {
+                self.formant_scale = scale;
+                if let Some(playback) = &self.playback {
+                    playback.set_formant_scale(scale);
+                }
+            }
         };
         true
     }
@@ -93,26 +1005,506 @@ impl AppUpdate for AppModel {
 #[relm4_macros::widget]
 impl Widgets<AppModel, ()> for AppWidgets {
     view! {
-        adw::ApplicationWindow {
-            set_title: Some("Simple app"),
-            set_default_width: 300,
-            set_default_height: 100,
+        main_window = adw::ApplicationWindow {
+            set_title: Some("Transcrible"),
+            set_default_width: 800,
+            set_default_height: 600,
             set_content = Some(&gtk::Box) {
                 set_orientation: gtk::Orientation::Vertical,
                 append = &adw::HeaderBar {
-                    pack_start: components.open_button.root_widget()
+                    pack_start = &gtk::Box {
+                        add_css_class: "linked",
+                        append: components.open_button.root_widget(),
+                        append: &recent_files_button(
+                            model.history.clone(),
+                            model.settings.clone(),
+                            clone!(@strong sender => move |path| send!(sender, AppMsg::LoadFile(path)))
+                        ),
+                    },
+                    pack_start = &gtk::MenuButton {
+                        set_label: "File",
+                        set_menu_model: Some(&file_menu()),
+                    },
+                    pack_end = &gtk::Button {
+                        set_icon_name: "emblem-system-symbolic",
+                        set_tooltip_text: Some("Preferences"),
+                        set_action_name: Some("win.preferences"),
+                    }
                 },
+                append = &gtk::InfoBar {
+                    set_message_type: gtk::MessageType::Warning,
+                    set_revealed: watch!(model.output_error.is_some()),
+                    add_child = &gtk::Label {
+                        set_wrap: true,
+                        set_hexpand: true,
+                        set_xalign: 0.0,
+                        set_label: watch!(&format!(
+                            "No output device, the audio can't be played. {}",
+                            model.output_error.as_deref().unwrap_or_default()
+                        )),
+                    },
+                    add_child = &gtk::Button::with_label("Retry") {
+                        connect_clicked(sender) => move |_| {
+                            send!(sender, AppMsg::RetryOutput);
+                        }
+                    },
+                },
+                append = &gtk::Box {
+                    set_orientation: gtk::Orientation::Horizontal,
+                    set_spacing: 6,
+                    append: model.waveform.widget(),
+                    append = &gtk::Label {
+                        set_label: watch!(&model.time_label()),
+                    }
+                },
+                append: &model.load_progress,
                 append = &gtk::Box {
                     set_orientation: gtk::Orientation::Horizontal,
+                    append = &gtk::Button::with_label("From start") {
+                        set_tooltip_text: Some("Play the file from the beginning"),
+                        connect_clicked(sender) => move |_| {
+                            send!(sender, AppMsg::StartFromBeginning);
+                        }
+                    },
+                    append = &gtk::Button::with_label("Previous") {
+                        connect_clicked(sender) => move |_| {
+                            send!(sender, AppMsg::SkipSmallBackward);
+                        }
+                    },
                     append = &gtk::Button::with_label("Play") {
                         connect_clicked(sender) => move |_| {
                             send!(sender, AppMsg::TogglePlayStatus);
                         }
+                    },
+                    append = &gtk::Button::with_label("Next") {
+                        connect_clicked(sender) => move |_| {
+                            send!(sender, AppMsg::SkipSmallForward);
+                        }
+                    },
+                    append = &gtk::Button::with_label("A") {
+                        set_tooltip_text: Some("Set the start of the loop"),
+                        connect_clicked(sender) => move |_| {
+                            send!(sender, AppMsg::SetLoopStart);
+                        }
+                    },
+                    append = &gtk::Button::with_label("B") {
+                        set_tooltip_text: Some("Set the end of the loop"),
+                        connect_clicked(sender) => move |_| {
+                            send!(sender, AppMsg::SetLoopEnd);
+                        }
+                    },
+                    append = &gtk::Button::with_label("Clear loop") {
+                        connect_clicked(sender) => move |_| {
+                            send!(sender, AppMsg::ClearLoop);
+                        }
+                    },
+                    append = &gtk::Button::with_label("Timestamp") {
+                        set_tooltip_text: Some("Insert the current position into the transcript"),
+                        connect_clicked(sender) => move |_| {
+                            send!(sender, AppMsg::InsertTimestamp);
+                        }
+                    },
+                    append = &gtk::Button::with_label("Capture noise") {
+                        set_tooltip_text: Some("Use the loop region as the noise the denoiser removes"),
+                        set_sensitive: watch!(model.loop_region.is_some()),
+                        connect_clicked(sender) => move |_| {
+                            send!(sender, AppMsg::CaptureNoiseProfile);
+                        }
+                    },
+                    append = &gtk::ToggleButton::with_label("Denoise") {
+                        set_sensitive: watch!(model.has_noise_profile),
+                        set_active: watch!(model.denoise),
+                        connect_toggled(sender) => move |button| {
+                            send!(sender, AppMsg::SetDenoise(button.is_active()));
+                        }
+                    },
+                    append = &gtk::MenuButton {
+                        set_label: "Equalizer",
+                        set_tooltip_text: Some("Choose a preset or adjust the filters of the equalizer"),
+                        set_popover = Some(&gtk::Popover) {
+                            set_child = Some(&gtk::Box) {
+                                set_orientation: gtk::Orientation::Vertical,
+                                set_spacing: 6,
+                                append = &gtk::DropDown::from_strings(&eq_preset_names()) {
+                                    set_selected: watch!(eq_preset_index(&model.eq)),
+                                    connect_selected_notify(sender) => move |drop_down| {
+                                        // selecting "Custom" keeps the current settings
+                                        if let Some(preset) = EqPreset::ALL.get(drop_down.selected() as usize) {
+                                            send!(sender, AppMsg::SetEq(preset.settings()));
+                                        }
+                                    }
+                                },
+                                append = &gtk::Box {
+                                    set_spacing: 6,
+                                    append = &gtk::CheckButton::with_label("High-pass (Hz)") {
+                                        set_hexpand: true,
+                                        set_active: watch!(model.eq.is_enabled(EqBand::HighPass)),
+                                        connect_toggled(sender) => move |button| {
+                                            send!(sender, AppMsg::SetEqBand(EqBand::HighPass, button.is_active()));
+                                        }
+                                    },
+                                    append = &gtk::SpinButton::with_range(20.0, 1000.0, 10.0) {
+                                        set_sensitive: watch!(model.eq.is_enabled(EqBand::HighPass)),
+                                        set_value: watch!(model.eq.parameter(EqParameter::HighPass) as f64),
+                                        connect_value_changed(sender) => move |button| {
+                                            send!(sender, AppMsg::SetEqParameter(EqParameter::HighPass, button.value() as f32));
+                                        }
+                                    },
+                                },
+                                append = &gtk::Box {
+                                    set_spacing: 6,
+                                    append = &gtk::CheckButton::with_label("Presence (Hz)") {
+                                        set_hexpand: true,
+                                        set_active: watch!(model.eq.is_enabled(EqBand::Presence)),
+                                        connect_toggled(sender) => move |button| {
+                                            send!(sender, AppMsg::SetEqBand(EqBand::Presence, button.is_active()));
+                                        }
+                                    },
+                                    append = &gtk::SpinButton::with_range(200.0, 8000.0, 100.0) {
+                                        set_sensitive: watch!(model.eq.is_enabled(EqBand::Presence)),
+                                        set_value: watch!(model.eq.parameter(EqParameter::PresenceFrequency) as f64),
+                                        connect_value_changed(sender) => move |button| {
+                                            send!(sender, AppMsg::SetEqParameter(EqParameter::PresenceFrequency, button.value() as f32));
+                                        }
+                                    },
+                                },
+                                append = &gtk::Box {
+                                    set_spacing: 6,
+                                    append = &gtk::Label::new(Some("Presence gain (dB)")) {
+                                        set_hexpand: true,
+                                        set_xalign: 0.0,
+                                    },
+                                    append = &gtk::SpinButton::with_range(-12.0, 12.0, 0.5) {
+                                        set_digits: 1,
+                                        set_sensitive: watch!(model.eq.is_enabled(EqBand::Presence)),
+                                        set_value: watch!(model.eq.parameter(EqParameter::PresenceGain) as f64),
+                                        connect_value_changed(sender) => move |button| {
+                                            send!(sender, AppMsg::SetEqParameter(EqParameter::PresenceGain, button.value() as f32));
+                                        }
+                                    },
+                                },
+                                append = &gtk::Box {
+                                    set_spacing: 6,
+                                    append = &gtk::Label::new(Some("Presence Q")) {
+                                        set_hexpand: true,
+                                        set_xalign: 0.0,
+                                        set_tooltip_text: Some("Higher values narrow the boost"),
+                                    },
+                                    append = &gtk::SpinButton::with_range(0.1, 10.0, 0.1) {
+                                        set_digits: 1,
+                                        set_sensitive: watch!(model.eq.is_enabled(EqBand::Presence)),
+                                        set_value: watch!(model.eq.parameter(EqParameter::PresenceQ) as f64),
+                                        connect_value_changed(sender) => move |button| {
+                                            send!(sender, AppMsg::SetEqParameter(EqParameter::PresenceQ, button.value() as f32));
+                                        }
+                                    },
+                                },
+                                append = &gtk::Box {
+                                    set_spacing: 6,
+                                    append = &gtk::CheckButton::with_label("Low-pass (Hz)") {
+                                        set_hexpand: true,
+                                        set_active: watch!(model.eq.is_enabled(EqBand::LowPass)),
+                                        connect_toggled(sender) => move |button| {
+                                            send!(sender, AppMsg::SetEqBand(EqBand::LowPass, button.is_active()));
+                                        }
+                                    },
+                                    append = &gtk::SpinButton::with_range(1000.0, 20000.0, 100.0) {
+                                        set_sensitive: watch!(model.eq.is_enabled(EqBand::LowPass)),
+                                        set_value: watch!(model.eq.parameter(EqParameter::LowPass) as f64),
+                                        connect_value_changed(sender) => move |button| {
+                                            send!(sender, AppMsg::SetEqParameter(EqParameter::LowPass, button.value() as f32));
+                                        }
+                                    },
+                                },
+                                append = &gtk::Box {
+                                    set_spacing: 6,
+                                    append = &gtk::Label::new(Some("Hum")) {
+                                        set_hexpand: true,
+                                        set_xalign: 0.0,
+                                    },
+                                    append = &gtk::DropDown::from_strings(&["Off", "50 Hz", "60 Hz"]) {
+                                        set_selected: watch!(hum_index(model.eq.hum)),
+                                        connect_selected_notify(sender) => move |drop_down| {
+                                            if let Some(hum) = HUM_FREQUENCIES.get(drop_down.selected() as usize) {
+                                                send!(sender, AppMsg::SetHum(*hum));
+                                            }
+                                        }
+                                    },
+                                },
+                            },
+                        },
+                    },
+                    append = &gtk::MenuButton {
+                        set_label: "Channels",
+                        set_tooltip_text: Some("Choose the channels of recordings with one speaker per channel"),
+                        set_popover = Some(&gtk::Popover) {
+                            set_child = Some(&gtk::Box) {
+                                set_orientation: gtk::Orientation::Vertical,
+                                set_spacing: 6,
+                                append = &gtk::DropDown::from_strings(&channel_mode_names()) {
+                                    set_selected: watch!(channel_mode_index(model.channel_routing.mode)),
+                                    connect_selected_notify(sender) => move |drop_down| {
+                                        if let Some(mode) = ChannelMode::ALL.get(drop_down.selected() as usize) {
+                                            send!(sender, AppMsg::SetChannelMode(*mode));
+                                        }
+                                    }
+                                },
+                                append = &gtk::Box {
+                                    set_spacing: 6,
+                                    append = &gtk::Label::new(Some("Left gain (dB)")) {
+                                        set_hexpand: true,
+                                        set_xalign: 0.0,
+                                    },
+                                    append = &gtk::SpinButton::with_range(-30.0, 30.0, 1.0) {
+                                        set_value: watch!(model.channel_routing.left_gain as f64),
+                                        connect_value_changed(sender) => move |button| {
+                                            send!(sender, AppMsg::SetLeftGain(button.value() as f32));
+                                        }
+                                    },
+                                },
+                                append = &gtk::Box {
+                                    set_spacing: 6,
+                                    append = &gtk::Label::new(Some("Right gain (dB)")) {
+                                        set_hexpand: true,
+                                        set_xalign: 0.0,
+                                    },
+                                    append = &gtk::SpinButton::with_range(-30.0, 30.0, 1.0) {
+                                        set_value: watch!(model.channel_routing.right_gain as f64),
+                                        connect_value_changed(sender) => move |button| {
+                                            send!(sender, AppMsg::SetRightGain(button.value() as f32));
+                                        }
+                                    },
+                                },
+                            },
+                        },
+                    },
+                    append = &gtk::Label::new(Some("Speed")) {},
+                    append = &gtk::SpinButton::with_range(MIN_SPEED, MAX_SPEED, 0.05) {
+                        set_digits: 2,
+                        set_value: watch!(model.speed),
+                        connect_value_changed(sender) => move |button| {
+                            send!(sender, AppMsg::SetSpeed(button.value()));
+                        }
+                    },
+                    append = &gtk::Label::new(Some("Pitch")) {},
+                    append = &gtk::SpinButton::with_range(-12.0, 12.0, 0.5) {
+                        set_digits: 1,
+                        set_value: watch!(model.pitch_semitones),
+                        connect_value_changed(sender) => move |button| {
+                            send!(sender, AppMsg::SetPitch(button.value()));
+                        }
+                    },
+                    append = &gtk::Label::new(Some("Formant")) {},
+                    append = &gtk::SpinButton::with_range(0.0, 2.0, 0.05) {
+                        set_digits: 2,
+                        set_tooltip_text: Some("0 keeps the voice natural when the pitch changes"),
+                        set_value: watch!(model.formant_scale),
+                        connect_value_changed(sender) => move |button| {
+                            send!(sender, AppMsg::SetFormant(button.value()));
+                        }
+                    }
+                },
+                append = &gtk::ScrolledWindow {
+                    set_vexpand: true,
+                    set_child = Some(&gtk::TextView) {
+                        set_buffer: Some(&model.transcript),
+                        set_wrap_mode: gtk::WrapMode::WordChar,
+                        set_left_margin: 12,
+                        set_right_margin: 12,
+                        set_top_margin: 12,
+                        set_bottom_margin: 12,
+                        add_controller: &transcript::seek_on_click(
+                            clone!(@strong sender => move |time| send!(sender, AppMsg::SeekTo(time)))
+                        ),
                     }
                 }
             }
         }
     }
+
+    fn post_init() {
+        // the toasts are shown above the whole content of the window
+        if let Some(content) = main_window.content() {
+            main_window.set_content(None::<&gtk::Widget>);
+            model.toast_overlay.set_child(Some(&content));
+        }
+        main_window.set_content(Some(&model.toast_overlay));
+
+        model.waveform.connect_seek(
+            clone!(@strong sender => move |time| send!(sender, AppMsg::SeekTo(time))),
+        );
+        actions::add_transport_actions(
+            &main_window,
+            clone!(@strong sender => move |name| {
+                if let Some(msg) = action_msg(name) {
+                    send!(sender, msg);
+                }
+            }),
+        );
+        let preferences_action = gio::SimpleAction::new(actions::PREFERENCES, None);
+        preferences_action.connect_activate(
+            clone!(@weak main_window, @strong model.settings as settings => move |_, _| {
+                preferences::show_preferences(&main_window, &settings);
+            }),
+        );
+        main_window.add_action(&preferences_action);
+        let file_actions: [(&str, fn() -> AppMsg); 3] = [
+            (actions::OPEN_PROJECT, || AppMsg::ShowOpenProjectDialog),
+            (actions::SAVE_PROJECT, || AppMsg::SaveProject),
+            (actions::SAVE_PROJECT_AS, || AppMsg::SaveProjectAs),
+        ];
+        for (name, msg) in file_actions {
+            let action = gio::SimpleAction::new(name, None);
+            action.connect_activate(clone!(@strong sender => move |_, _| send!(sender, msg())));
+            main_window.add_action(&action);
+        }
+
+        main_window.add_controller(&file_drop_target(clone!(@strong sender => move |result| {
+            send!(sender, match result {
+                Ok(path) => AppMsg::LoadFile(path),
+                Err(message) => AppMsg::ShowToast(message),
+            })
+        })));
+
+        main_window.connect_close_request(clone!(
+            @strong model.close_allowed as close_allowed,
+            @strong sender => move |_| {
+                if !close_allowed.get() {
+                    send!(sender, AppMsg::CloseRequested);
+                    return gtk::Inhibit(true);
+                }
+                gtk::Inhibit(false)
+            }
+        ));
+        recovery::offer_restore(
+            &main_window,
+            model.recovery.crashed_sessions(),
+            clone!(@strong sender => move |session, restore| {
+                send!(sender, if restore {
+                    AppMsg::RestoreSession(session)
+                } else {
+                    AppMsg::DiscardSession(session)
+                });
+            }),
+        );
+    }
+}
+
+/// The names of the equalizer presets, followed by "Custom" for settings which match none of them
+fn eq_preset_names() -> Vec<&'static str> {
+    EqPreset::ALL
+        .iter()
+        .map(|preset| preset.name())
+        .chain(["Custom"])
+        .collect()
+}
+
+/// The index of `eq` in [`eq_preset_names`]
+fn eq_preset_index(eq: &EqSettings) -> u32 {
+    EqPreset::matching(eq)
+        .and_then(|preset| EqPreset::ALL.iter().position(|other| *other == preset))
+        .unwrap_or(EqPreset::ALL.len()) as u32
+}
+
+/// The choices of the hum filter, in the order of its drop down
+const HUM_FREQUENCIES: [Option<f32>; 3] = [None, Some(50.0), Some(60.0)];
+
+/// The index of `hum` in [`HUM_FREQUENCIES`], nothing is selected for other frequencies so that
+/// they are kept
+fn hum_index(hum: Option<f32>) -> u32 {
+    HUM_FREQUENCIES
+        .iter()
+        .position(|other| *other == hum)
+        .map_or(gtk::INVALID_LIST_POSITION, |index| index as u32)
+}
+
+fn channel_mode_names() -> Vec<&'static str> {
+    ChannelMode::ALL.iter().map(|mode| mode.name()).collect()
+}
+
+fn channel_mode_index(mode: ChannelMode) -> u32 {
+    ChannelMode::ALL
+        .iter()
+        .position(|other| *other == mode)
+        .unwrap_or(0) as u32
+}
+
+/// Creates the controller which opens audio and project files which are dropped on the window.
+/// `open` is called with the path of the file, or with a message why it can't be opened.
+fn file_drop_target<F: Fn(Result<PathBuf, String>) + 'static>(open: F) -> gtk::DropTarget {
+    let drop_target = gtk::DropTarget::new(gio::File::static_type(), gdk::DragAction::COPY);
+    // files are handled before the transcript would insert their uri as text
+    drop_target.set_propagation_phase(gtk::PropagationPhase::Capture);
+    drop_target.connect_drop(move |_, value, _, _| {
+        let file = match value.get::<gio::File>() {
+            Ok(file) => file,
+            Err(_) => return false,
+        };
+        match file.path() {
+            Some(path) => {
+                open(Ok(path));
+                true
+            }
+            None => {
+                open(Err(format!(
+                    "Only local files can be opened, not {}",
+                    file.uri()
+                )));
+                false
+            }
+        }
+    });
+    drop_target
+}
+
+/// The menu of the file button in the header bar
+fn file_menu() -> gio::Menu {
+    let menu = gio::Menu::new();
+    menu.append(
+        Some("Open project…"),
+        Some(&format!(
+            "{}.{}",
+            actions::ACTION_GROUP,
+            actions::OPEN_PROJECT
+        )),
+    );
+    menu.append(
+        Some("Save project"),
+        Some(&format!(
+            "{}.{}",
+            actions::ACTION_GROUP,
+            actions::SAVE_PROJECT
+        )),
+    );
+    menu.append(
+        Some("Save project as…"),
+        Some(&format!(
+            "{}.{}",
+            actions::ACTION_GROUP,
+            actions::SAVE_PROJECT_AS
+        )),
+    );
+    menu
+}
+
+/// The message which is sent when the transport action `name` is activated
+fn action_msg(name: &str) -> Option<AppMsg> {
+    Some(match name {
+        actions::PLAY_PAUSE => AppMsg::TogglePlayStatus,
+        actions::SKIP_SMALL_BACKWARD => AppMsg::SkipSmallBackward,
+        actions::SKIP_SMALL_FORWARD => AppMsg::SkipSmallForward,
+        actions::SKIP_LARGE_BACKWARD => AppMsg::SkipLargeBackward,
+        actions::SKIP_LARGE_FORWARD => AppMsg::SkipLargeForward,
+        actions::SPEED_DOWN => AppMsg::SpeedDown,
+        actions::SPEED_UP => AppMsg::SpeedUp,
+        actions::INSERT_TIMESTAMP => AppMsg::InsertTimestamp,
+        actions::SET_LOOP_START => AppMsg::SetLoopStart,
+        actions::SET_LOOP_END => AppMsg::SetLoopEnd,
+        actions::CLEAR_LOOP => AppMsg::ClearLoop,
+        actions::ADD_MARKER => AppMsg::AddMarker,
+        actions::START_FROM_BEGINNING => AppMsg::StartFromBeginning,
+        _ => return None,
+    })
 }
 
 impl ParentWindow for AppWidgets {
@@ -121,8 +1513,53 @@ impl ParentWindow for AppWidgets {
     }
 }
 
-pub fn start_app(player: AudioPlayer) {
-    let model = AppModel { player };
-    let app = RelmApp::new(model);
+/// Creates the main window when `app` is activated and runs it. The paths received by
+/// `open_requests` are loaded like files opened with the open button, the errors are shown as
+/// toasts.
+pub fn start_app(app: adw::Application, open_requests: glib::Receiver<OpenRequest>) {
+    let model = AppModel {
+        settings: settings::new_settings(),
+        transcript: gtk::TextBuffer::new(None),
+        waveform: Waveform::new(),
+        markers: vec![],
+        playback: None,
+        audio_path: None,
+        project_path: None,
+        loop_region: None,
+        recovery: Recovery::new(),
+        autosaved: None,
+        saved: None,
+        close_allowed: Rc::new(Cell::new(false)),
+        after_save: None,
+        history: Rc::new(RefCell::new(History::load())),
+        eq: EqSettings::default(),
+        channel_routing: ChannelRouting::default(),
+        has_noise_profile: false,
+        denoise: false,
+        load_progress: gtk::ProgressBar::builder()
+            .text("Loading the audio file into memory")
+            .show_text(true)
+            .visible(false)
+            .build(),
+        toast_overlay: adw::ToastOverlay::new(),
+        output_error: None,
+        autosave_failed: false,
+        open_requests: RefCell::new(Some(open_requests)),
+        speed: 1.0,
+        pitch_semitones: 0.0,
+        formant_scale: 0.0,
+        position: Duration::ZERO,
+        duration: None,
+    };
+    // the autosaves are only kept if the app doesn't shut down cleanly. The app also shuts down
+    // without closing the window, for example when the session ends.
+    app.connect_shutdown(clone!(
+        @strong model.recovery as recovery,
+        @strong model.history as history => move |_| {
+            recovery.discard();
+            history.borrow_mut().save();
+        }
+    ));
+    let app = RelmApp::with_app(model, app.upcast());
     app.run()
 }
@@ -0,0 +1,513 @@
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    rc::Rc,
+};
+
+use adw::prelude::*;
+use gtk::{
+    gdk, gio,
+    glib::{self, clone},
+};
+
+use super::actions::{self, TRANSPORT_ACTIONS};
+use crate::{audio::dynamics::DynamicsPreset, settings};
+
+/// Keys which only modify other keys and can't be a shortcut on their own
+const MODIFIER_KEYS: [gdk::Key; 12] = [
+    gdk::Key::Shift_L,
+    gdk::Key::Shift_R,
+    gdk::Key::Control_L,
+    gdk::Key::Control_R,
+    gdk::Key::Alt_L,
+    gdk::Key::Alt_R,
+    gdk::Key::Super_L,
+    gdk::Key::Super_R,
+    gdk::Key::Meta_L,
+    gdk::Key::Meta_R,
+    gdk::Key::ISO_Level3_Shift,
+    gdk::Key::Caps_Lock,
+];
+
+/// Shows the preferences window
+pub fn show_preferences(parent: &impl IsA<gtk::Window>, settings: &gio::Settings) {
+    let window = adw::PreferencesWindow::builder()
+        .transient_for(parent)
+        .modal(true)
+        .build();
+    window.add(&playback_page(settings));
+    window.add(&shortcuts_page(&window, settings));
+    window.add(&dynamics_page(&window, settings));
+    window.present();
+}
+
+/// The page with the settings of the playback
+fn playback_page(settings: &gio::Settings) -> adw::PreferencesPage {
+    let page = adw::PreferencesPage::builder()
+        .title("Playback")
+        .icon_name("media-playback-start-symbolic")
+        .build();
+
+    let display_group = adw::PreferencesGroup::builder().title("Display").build();
+    display_group.add(&spin_row(
+        "Position updates",
+        "ms between two updates of the position and the waveform",
+        settings,
+        settings::POSITION_UPDATE_INTERVAL,
+        gtk::Adjustment::new(100.0, 20.0, 1000.0, 10.0, 100.0, 0.0),
+        0,
+    ));
+    page.add(&display_group);
+
+    let skip_group = adw::PreferencesGroup::builder().title("Skipping").build();
+    skip_group.add(&spin_row(
+        "Small step",
+        "ms",
+        settings,
+        settings::SKIP_SMALL_STEP,
+        gtk::Adjustment::new(2000.0, 100.0, 60000.0, 100.0, 1000.0, 0.0),
+        0,
+    ));
+    skip_group.add(&spin_row(
+        "Large step",
+        "ms",
+        settings,
+        settings::SKIP_LARGE_STEP,
+        gtk::Adjustment::new(10000.0, 100.0, 600000.0, 1000.0, 10000.0, 0.0),
+        0,
+    ));
+    page.add(&skip_group);
+
+    let rewind_group = adw::PreferencesGroup::builder()
+        .title("Rewind on resume")
+        .description(
+            "Jumps back when the playback is resumed, so that the last words are heard again.",
+        )
+        .build();
+    rewind_group.add(&spin_row(
+        "Rewind",
+        "ms",
+        settings,
+        settings::RESUME_REWIND,
+        gtk::Adjustment::new(1500.0, 0.0, 30000.0, 100.0, 1000.0, 0.0),
+        0,
+    ));
+    rewind_group.add(&switch_row(
+        "Scale with the pause",
+        "Rewinds further after longer pauses",
+        settings,
+        settings::RESUME_REWIND_SCALE_WITH_PAUSE,
+    ));
+    rewind_group.add(&spin_row(
+        "Rewind per paused second",
+        "Fraction of the pause which is added to the rewind",
+        settings,
+        settings::RESUME_REWIND_PAUSE_FACTOR,
+        gtk::Adjustment::new(0.1, 0.0, 1.0, 0.01, 0.1, 0.0),
+        2,
+    ));
+    rewind_group.add(&spin_row(
+        "Maximal rewind",
+        "ms",
+        settings,
+        settings::RESUME_REWIND_MAX,
+        gtk::Adjustment::new(5000.0, 0.0, 60000.0, 100.0, 1000.0, 0.0),
+        0,
+    ));
+    page.add(&rewind_group);
+
+    let loop_group = adw::PreferencesGroup::builder().title("Loop").build();
+    loop_group.add(&spin_row(
+        "Gap between repetitions",
+        "ms of silence before the loop starts again",
+        settings,
+        settings::LOOP_GAP,
+        gtk::Adjustment::new(0.0, 0.0, 10000.0, 100.0, 1000.0, 0.0),
+        0,
+    ));
+    loop_group.add(&spin_row(
+        "Maximal repetitions",
+        "0 repeats the loop until it is cleared",
+        settings,
+        settings::LOOP_MAX_REPEATS,
+        gtk::Adjustment::new(0.0, 0.0, 100.0, 1.0, 5.0, 0.0),
+        0,
+    ));
+    page.add(&loop_group);
+
+    let memory_group = adw::PreferencesGroup::builder().title("Memory").build();
+    memory_group.add(&spin_row(
+        "Waveform cache",
+        "MiB the cached waveforms of previous files may use",
+        settings,
+        settings::PEAK_CACHE_SIZE,
+        gtk::Adjustment::new(200.0, 0.0, 10000.0, 10.0, 100.0, 0.0),
+        0,
+    ));
+    memory_group.add(&spin_row(
+        "Decode into memory",
+        "MiB a decoded file may take up, about 20 per minute of stereo audio. Longer files are \
+         streamed, 0 always streams them.",
+        settings,
+        settings::MEMORY_DECODE_LIMIT,
+        gtk::Adjustment::new(512.0, 0.0, 16384.0, 64.0, 512.0, 0.0),
+        0,
+    ));
+    page.add(&memory_group);
+
+    let files_group = adw::PreferencesGroup::builder().title("Files").build();
+    files_group.add(&spin_row(
+        "Autosave",
+        "Seconds between two autosaves, which are restored after a crash. 0 disables them.",
+        settings,
+        settings::AUTOSAVE_INTERVAL,
+        gtk::Adjustment::new(30.0, 0.0, 3600.0, 5.0, 60.0, 0.0),
+        0,
+    ));
+    files_group.add(&switch_row(
+        "Continue where the playback stopped",
+        "Restores the position, speed, pitch and loop of a file when it is opened again",
+        settings,
+        settings::RESTORE_POSITION,
+    ));
+    files_group.add(&spin_row(
+        "Recent files",
+        "How many files the list next to the open button shows",
+        settings,
+        settings::MAX_RECENT_FILES,
+        gtk::Adjustment::new(10.0, 1.0, 50.0, 1.0, 5.0, 0.0),
+        0,
+    ));
+    page.add(&files_group);
+
+    page
+}
+
+/// The page where the accelerators of the transport actions are rebound. A shortcut is changed
+/// by clicking on it and pressing the new key combination.
+fn shortcuts_page(
+    window: &adw::PreferencesWindow,
+    settings: &gio::Settings,
+) -> adw::PreferencesPage {
+    let page = adw::PreferencesPage::builder()
+        .title("Shortcuts")
+        .icon_name("preferences-desktop-keyboard-shortcuts-symbolic")
+        .build();
+    let group = adw::PreferencesGroup::builder()
+        .title("Playback and transcript")
+        .description(
+            "Click on a shortcut and press the new key combination. \
+             Backspace removes the shortcut.",
+        )
+        .build();
+    page.add(&group);
+
+    // the action whose shortcut is currently being recorded
+    let recording: Rc<RefCell<Option<&'static str>>> = Rc::new(RefCell::new(None));
+    let buttons: Rc<HashMap<&'static str, gtk::Button>> = Rc::new(
+        TRANSPORT_ACTIONS
+            .iter()
+            .map(|action| {
+                let button = gtk::Button::builder()
+                    .valign(gtk::Align::Center)
+                    .css_classes(vec!["flat".to_string()])
+                    .build();
+                let row = adw::ActionRow::builder()
+                    .title(action.title)
+                    .activatable_widget(&button)
+                    .build();
+                row.add_suffix(&button);
+                group.add(&row);
+                (action.name, button)
+            })
+            .collect(),
+    );
+    update_shortcut_labels(&buttons, settings);
+
+    for (name, button) in buttons.iter() {
+        let name: &'static str = name;
+        button.connect_clicked(
+            clone!(@strong recording, @strong buttons, @strong settings => move |button| {
+                let previous = recording.replace(Some(name));
+                update_shortcut_labels(&buttons, &settings);
+                if previous == Some(name) {
+                    // clicking the same shortcut again cancels the recording
+                    recording.replace(None);
+                } else {
+                    button.set_label("Press a shortcut…");
+                }
+            }),
+        );
+    }
+
+    let key_controller = gtk::EventControllerKey::new();
+    key_controller.set_propagation_phase(gtk::PropagationPhase::Capture);
+    key_controller.connect_key_pressed(
+        clone!(@strong recording, @strong buttons, @strong settings, @weak window => @default-return gtk::Inhibit(false), move |_, key, _, modifiers| {
+            let name = match *recording.borrow() {
+                Some(name) => name,
+                None => return gtk::Inhibit(false),
+            };
+            if MODIFIER_KEYS.contains(&key) {
+                return gtk::Inhibit(true);
+            }
+            let modifiers = modifiers & gtk::accelerator_get_default_mod_mask();
+            match record_shortcut(name, key, modifiers, &settings) {
+                Ok(()) => {
+                    recording.replace(None);
+                }
+                // toasts show markup, but key labels like & or < are plain text
+                Err(message) => {
+                    window.add_toast(&adw::Toast::new(&glib::markup_escape_text(&message)))
+                }
+            }
+            update_shortcut_labels(&buttons, &settings);
+            if let (Some(name), Some(button)) = (*recording.borrow(), buttons.get(name)) {
+                button.set_label("Press a shortcut…");
+            }
+            gtk::Inhibit(true)
+        }),
+    );
+    window.add_controller(&key_controller);
+
+    let restore_group = adw::PreferencesGroup::new();
+    let restore_button = gtk::Button::builder()
+        .label("Restore defaults")
+        .halign(gtk::Align::Center)
+        .css_classes(vec!["destructive-action".to_string()])
+        .build();
+    restore_button.connect_clicked(clone!(@strong recording, @strong settings => move |_| {
+        recording.replace(None);
+        settings.reset(settings::SHORTCUTS);
+    }));
+    restore_group.add(&restore_button);
+    page.add(&restore_group);
+
+    // the labels are updated when the shortcuts change, until the window closes
+    let handler_id = settings.connect_changed(
+        Some(settings::SHORTCUTS),
+        clone!(@strong buttons => move |settings, _| {
+            update_shortcut_labels(&buttons, settings);
+        }),
+    );
+    let handler_id = Cell::new(Some(handler_id));
+    window.connect_close_request(clone!(@strong settings => move |_| {
+        if let Some(handler_id) = handler_id.take() {
+            settings.disconnect(handler_id);
+        }
+        gtk::Inhibit(false)
+    }));
+
+    page
+}
+
+/// The page where the compressor, the limiter and the automatic gain control are adjusted. The
+/// changes are heard right away.
+fn dynamics_page(
+    window: &adw::PreferencesWindow,
+    settings: &gio::Settings,
+) -> adw::PreferencesPage {
+    let page = adw::PreferencesPage::builder()
+        .title("Dynamics")
+        .icon_name("audio-volume-high-symbolic")
+        .build();
+
+    let preset_group = adw::PreferencesGroup::builder()
+        .description(
+            "Evens out loud and quiet speakers, so that the volume doesn't have to be \
+             adjusted all the time.",
+        )
+        .build();
+    let names: Vec<&str> = DynamicsPreset::ALL
+        .iter()
+        .map(|preset| preset.name())
+        .chain(["Custom"])
+        .collect();
+    let preset_row = adw::ComboRow::builder()
+        .title("Preset")
+        .model(&gtk::StringList::new(&names))
+        .build();
+    preset_group.add(&preset_row);
+    page.add(&preset_group);
+
+    let compressor_group = adw::PreferencesGroup::builder().title("Compressor").build();
+    compressor_group.add(&spin_row(
+        "Threshold",
+        "dBFS",
+        settings,
+        settings::DYNAMICS_THRESHOLD,
+        gtk::Adjustment::new(0.0, -60.0, 0.0, 1.0, 6.0, 0.0),
+        0,
+    ));
+    compressor_group.add(&spin_row(
+        "Ratio",
+        "1 turns the compressor off",
+        settings,
+        settings::DYNAMICS_RATIO,
+        gtk::Adjustment::new(1.0, 1.0, 20.0, 0.5, 2.0, 0.0),
+        1,
+    ));
+    compressor_group.add(&spin_row(
+        "Attack",
+        "ms",
+        settings,
+        settings::DYNAMICS_ATTACK,
+        gtk::Adjustment::new(0.0, 0.0, 500.0, 1.0, 10.0, 0.0),
+        0,
+    ));
+    compressor_group.add(&spin_row(
+        "Release",
+        "ms",
+        settings,
+        settings::DYNAMICS_RELEASE,
+        gtk::Adjustment::new(1.0, 1.0, 5000.0, 10.0, 100.0, 0.0),
+        0,
+    ));
+    compressor_group.add(&spin_row(
+        "Make-up gain",
+        "dB",
+        settings,
+        settings::DYNAMICS_MAKEUP_GAIN,
+        gtk::Adjustment::new(0.0, 0.0, 30.0, 0.5, 3.0, 0.0),
+        1,
+    ));
+    compressor_group.add(&switch_row(
+        "Limiter",
+        "Keeps the peaks from clipping",
+        settings,
+        settings::DYNAMICS_LIMITER,
+    ));
+    page.add(&compressor_group);
+
+    let agc_group = adw::PreferencesGroup::new();
+    agc_group.add(&switch_row(
+        "Automatic gain control",
+        "Slowly levels the loudness of the speakers",
+        settings,
+        settings::DYNAMICS_AGC,
+    ));
+    page.add(&agc_group);
+
+    // whether the selected preset is changed because the settings changed
+    let updating = Rc::new(Cell::new(false));
+    let update_preset = clone!(@strong updating, @weak preset_row => move |settings: &gio::Settings| {
+        let preset = DynamicsPreset::matching(&settings::dynamics(settings));
+        let index = preset
+            .and_then(|preset| DynamicsPreset::ALL.iter().position(|other| *other == preset))
+            .unwrap_or(DynamicsPreset::ALL.len());
+        updating.set(true);
+        preset_row.set_selected(index as u32);
+        updating.set(false);
+    });
+    update_preset(settings);
+    preset_row.connect_selected_notify(move |row| {
+        if updating.get() {
+            return;
+        }
+        if let Some(preset) = DynamicsPreset::ALL.get(row.selected() as usize) {
+            settings::set_dynamics(&preset.settings());
+        }
+    });
+
+    let handler_id = settings.connect_changed(None, move |settings, key| {
+        if settings::DYNAMICS_KEYS.contains(&key) {
+            update_preset(settings);
+        }
+    });
+    let handler_id = Cell::new(Some(handler_id));
+    window.connect_close_request(clone!(@strong settings => move |_| {
+        if let Some(handler_id) = handler_id.take() {
+            settings.disconnect(handler_id);
+        }
+        gtk::Inhibit(false)
+    }));
+
+    page
+}
+
+/// A row with a spin button which is bound to the numeric `key`
+fn spin_row(
+    title: &str,
+    subtitle: &str,
+    settings: &gio::Settings,
+    key: &str,
+    adjustment: gtk::Adjustment,
+    digits: u32,
+) -> adw::ActionRow {
+    let spin_button = gtk::SpinButton::builder()
+        .adjustment(&adjustment)
+        .digits(digits)
+        .valign(gtk::Align::Center)
+        .build();
+    settings.bind(key, &spin_button, "value").build();
+    let row = adw::ActionRow::builder()
+        .title(title)
+        .subtitle(subtitle)
+        .activatable_widget(&spin_button)
+        .build();
+    row.add_suffix(&spin_button);
+    row
+}
+
+/// A row with a switch which is bound to the boolean `key`
+fn switch_row(title: &str, subtitle: &str, settings: &gio::Settings, key: &str) -> adw::ActionRow {
+    let switch = gtk::Switch::builder().valign(gtk::Align::Center).build();
+    settings.bind(key, &switch, "active").build();
+    let row = adw::ActionRow::builder()
+        .title(title)
+        .subtitle(subtitle)
+        .activatable_widget(&switch)
+        .build();
+    row.add_suffix(&switch);
+    row
+}
+
+/// Binds the pressed key combination to the action `name`. Returns a message for the user if it
+/// can't be used.
+fn record_shortcut(
+    name: &str,
+    key: gdk::Key,
+    modifiers: gdk::ModifierType,
+    settings: &gio::Settings,
+) -> Result<(), String> {
+    let mut custom_accels = settings::shortcuts(settings);
+    if key == gdk::Key::BackSpace && modifiers.is_empty() {
+        custom_accels.insert(name.to_string(), vec![]);
+        settings::set_shortcuts(settings, &custom_accels);
+        return Ok(());
+    }
+
+    let label = gtk::accelerator_get_label(key, modifiers);
+    let typing_modifiers = modifiers - gdk::ModifierType::SHIFT_MASK;
+    if key.to_unicode().is_some() && typing_modifiers.is_empty() {
+        return Err(format!(
+            "{} can't be used, since it is needed for typing the transcript",
+            label
+        ));
+    }
+    let accel = gtk::accelerator_name(key, modifiers).to_string();
+    if actions::is_window_accel(&accel) {
+        return Err(format!("{} is reserved for the window", label));
+    }
+    if let Some(action) = actions::conflicting_action(name, &accel, &custom_accels) {
+        return Err(format!("{} is already used by \"{}\"", label, action.title));
+    }
+    custom_accels.insert(name.to_string(), vec![accel]);
+    settings::set_shortcuts(settings, &custom_accels);
+    Ok(())
+}
+
+fn update_shortcut_labels(buttons: &HashMap<&'static str, gtk::Button>, settings: &gio::Settings) {
+    let custom_accels = settings::shortcuts(settings);
+    for action in &TRANSPORT_ACTIONS {
+        let label = actions::effective_accels(action, &custom_accels)
+            .iter()
+            .filter_map(|accel| gtk::accelerator_parse(accel))
+            .map(|(key, modifiers)| gtk::accelerator_get_label(key, modifiers).to_string())
+            .collect::<Vec<_>>()
+            .join(", ");
+        if let Some(button) = buttons.get(action.name) {
+            button.set_label(if label.is_empty() { "Disabled" } else { &label });
+        }
+    }
+}
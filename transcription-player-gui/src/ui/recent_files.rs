@@ -0,0 +1,77 @@
+use std::{cell::RefCell, path::PathBuf, rc::Rc};
+
+use adw::prelude::*;
+use gtk::{gio, glib, glib::clone};
+
+use super::main_window::format_time;
+use crate::{
+    history::{FileState, History},
+    settings,
+};
+
+/// Creates the button next to the open button which lists the recently opened files with their
+/// duration and the position they stopped at. `open` is called with the path of the file the
+/// user chose.
+///
+/// The list is created from `history` every time it is shown, so it is always up to date.
+pub fn recent_files_button<F: Fn(PathBuf) + 'static>(
+    history: Rc<RefCell<History>>,
+    settings: gio::Settings,
+    open: F,
+) -> gtk::MenuButton {
+    let open = Rc::new(open);
+    let popover = gtk::Popover::new();
+    popover.connect_show(move |popover| {
+        let files = history
+            .borrow_mut()
+            .recent(settings::max_recent_files(&settings));
+        if files.is_empty() {
+            let label = gtk::Label::builder()
+                .label("No recent files")
+                .margin_top(6)
+                .margin_bottom(6)
+                .margin_start(6)
+                .margin_end(6)
+                .build();
+            popover.set_child(Some(&label));
+            return;
+        }
+
+        let list = gtk::ListBox::builder()
+            .selection_mode(gtk::SelectionMode::None)
+            .build();
+        for file in files {
+            let name = file.path.file_name().map_or_else(
+                || file.path.to_string_lossy(),
+                |name| name.to_string_lossy(),
+            );
+            // the title and the subtitle of the row are markup
+            let row = adw::ActionRow::builder()
+                .title(&glib::markup_escape_text(&name))
+                .subtitle(&glib::markup_escape_text(&progress_label(&file)))
+                .tooltip_text(&file.path.to_string_lossy())
+                .activatable(true)
+                .build();
+            row.connect_activated(clone!(@strong open, @weak popover => move |_| {
+                popover.popdown();
+                open(file.path.clone());
+            }));
+            list.append(&row);
+        }
+        popover.set_child(Some(&list));
+    });
+
+    gtk::MenuButton::builder()
+        .icon_name("document-open-recent-symbolic")
+        .tooltip_text("Recent files")
+        .popover(&popover)
+        .build()
+}
+
+/// Describes where the playback of `file` stopped, like `00:12:34 / 01:02:03`
+fn progress_label(file: &FileState) -> String {
+    match file.duration {
+        Some(duration) => format!("{} / {}", format_time(file.position), format_time(duration)),
+        None => format_time(file.position),
+    }
+}
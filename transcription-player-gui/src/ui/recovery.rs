@@ -0,0 +1,115 @@
+use std::rc::Rc;
+
+use gtk::{glib, prelude::*};
+
+use crate::recovery::CrashedSession;
+
+/// Asks the user whether the autosaves of `sessions` should be restored, the latest first.
+/// `respond` is called with the session and `true` if it should be restored, or `false` if it
+/// should be discarded.
+///
+/// When a session is discarded, the next one is offered. The sessions after a restored one are
+/// kept for the next start, as only one session can be open at a time. The dialog is shown once
+/// the main loop is idle, so that it appears on top of `parent`.
+pub fn offer_restore<F: Fn(CrashedSession, bool) + 'static>(
+    parent: &impl IsA<gtk::Window>,
+    sessions: Vec<CrashedSession>,
+    respond: F,
+) {
+    offer_next(parent.upcast_ref(), sessions, Rc::new(respond));
+}
+
+fn offer_next(
+    parent: &gtk::Window,
+    mut sessions: Vec<CrashedSession>,
+    respond: Rc<dyn Fn(CrashedSession, bool)>,
+) {
+    if sessions.is_empty() {
+        return;
+    }
+    let session = sessions.remove(0);
+    let name = session
+        .project_path
+        .as_ref()
+        .and_then(|path| path.file_name())
+        .map_or("an unsaved session".into(), |name| {
+            format!("\"{}\"", name.to_string_lossy())
+        });
+    let saved_at = session
+        .saved_at
+        .duration_since(std::time::UNIX_EPOCH)
+        .ok()
+        .and_then(|since_epoch| glib::DateTime::from_unix_local(since_epoch.as_secs() as i64).ok())
+        .and_then(|saved_at| saved_at.format("%x %X").ok())
+        .map_or(String::new(), |saved_at| {
+            format!(" It was saved on {}.", saved_at)
+        });
+    let others = match sessions.len() {
+        0 => String::new(),
+        1 => " One more session can be restored.".to_string(),
+        count => format!(" {} more sessions can be restored.", count),
+    };
+
+    let dialog = gtk::MessageDialog::builder()
+        .transient_for(parent)
+        .modal(true)
+        .message_type(gtk::MessageType::Question)
+        .text("Restore the last session?")
+        .secondary_text(&format!(
+            "Transcrible wasn't closed properly while {} was open.{}{}",
+            name, saved_at, others
+        ))
+        .build();
+    dialog.add_button("Discard", gtk::ResponseType::Reject);
+    dialog.add_button("Restore", gtk::ResponseType::Accept);
+    dialog.set_default_response(gtk::ResponseType::Accept);
+    dialog.connect_response(glib::clone!(@weak parent => move |dialog, response| {
+        match response {
+            gtk::ResponseType::Accept => respond(session.clone(), true),
+            gtk::ResponseType::Reject => {
+                respond(session.clone(), false);
+                offer_next(&parent, sessions.clone(), respond.clone());
+            }
+            // closing the dialog keeps the autosaves for the next start
+            _ => {}
+        }
+        dialog.close();
+    }));
+    glib::idle_add_local_once(move || dialog.show());
+}
+
+/// Asks the user whether the changes to the project `name` should be saved before `doing`,
+/// like "closing". `discard_label` is the label of the button which discards the changes.
+/// `respond` is called with `true` if they should be saved, or `false` if they should be
+/// discarded. Nothing is called if the user cancels.
+pub fn ask_save_changes<F: Fn(bool) + 'static>(
+    parent: &impl IsA<gtk::Window>,
+    name: Option<&str>,
+    doing: &str,
+    discard_label: &str,
+    respond: F,
+) {
+    let dialog = gtk::MessageDialog::builder()
+        .transient_for(parent)
+        .modal(true)
+        .message_type(gtk::MessageType::Question)
+        .text(&format!("Save the changes before {}?", doing))
+        .secondary_text(&match name {
+            Some(name) => format!("The changes to \"{}\" are lost otherwise.", name),
+            None => "The transcript is lost otherwise.".to_string(),
+        })
+        .build();
+    dialog.add_button("Cancel", gtk::ResponseType::Cancel);
+    dialog.add_button(discard_label, gtk::ResponseType::Reject);
+    dialog.add_button("Save", gtk::ResponseType::Accept);
+    dialog.set_default_response(gtk::ResponseType::Accept);
+    dialog.connect_response(move |dialog, response| {
+        match response {
+            gtk::ResponseType::Accept => respond(true),
+            gtk::ResponseType::Reject => respond(false),
+            _ => {}
+        }
+        dialog.close();
+    });
+    dialog.show();
+}
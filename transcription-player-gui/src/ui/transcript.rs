@@ -0,0 +1,152 @@
+use std::time::Duration;
+
+use gtk::prelude::*;
+
+/// Formats `time` as a timestamp like `[00:12:34.5]`
+pub fn format_timestamp(time: Duration) -> String {
+    let seconds = time.as_secs();
+    format!(
+        "[{:02}:{:02}:{:02}.{}]",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60,
+        time.subsec_millis() / 100
+    )
+}
+
+/// Parses the content of a timestamp without the brackets, like `00:12:34.5` or `12:34`
+pub fn parse_timestamp(text: &str) -> Option<Duration> {
+    let parts: Vec<&str> = text.trim().split(':').collect();
+    let (hours, minutes, seconds) = match parts[..] {
+        [minutes, seconds] => ("0", minutes, seconds),
+        [hours, minutes, seconds] => (hours, minutes, seconds),
+        _ => return None,
+    };
+    let hours: u64 = hours.parse().ok()?;
+    let minutes: u64 = minutes.parse().ok()?;
+    let seconds: f64 = seconds.parse().ok()?;
+    if minutes >= 60 || !(0.0..60.0).contains(&seconds) {
+        return None;
+    }
+    Some(Duration::from_secs(hours * 3600 + minutes * 60) + Duration::from_secs_f64(seconds))
+}
+
+/// Returns the time of the timestamp `iter` points into, if there is one
+pub fn timestamp_at(iter: &gtk::TextIter) -> Option<Duration> {
+    let mut line_start = iter.clone();
+    line_start.set_line_offset(0);
+    let mut line_end = iter.clone();
+    if !line_end.ends_line() {
+        line_end.forward_to_line_end();
+    }
+    let line: Vec<char> = line_start.text(&line_end).chars().collect();
+    timestamp_in_line(&line, iter.line_offset() as usize)
+}
+
+/// Returns the time of the timestamp in `line` which contains the character at `offset`
+fn timestamp_in_line(line: &[char], offset: usize) -> Option<Duration> {
+    let open = line[..(offset + 1).min(line.len())]
+        .iter()
+        .rposition(|c| *c == '[')?;
+    let close = open + line[open..].iter().position(|c| *c == ']')?;
+    if close < offset {
+        // the closest timestamp ends before the iter
+        return None;
+    }
+    let content: String = line[open + 1..close].iter().collect();
+    parse_timestamp(&content)
+}
+
+/// Inserts a timestamp of `time` at the cursor
+pub fn insert_timestamp(buffer: &gtk::TextBuffer, time: Duration) {
+    buffer.insert_at_cursor(&format!("{} ", format_timestamp(time)));
+}
+
+/// Creates a gesture for the transcript view which calls `seek` with the time of the timestamp
+/// that was clicked on.
+pub fn seek_on_click<F: Fn(Duration) + 'static>(seek: F) -> gtk::GestureClick {
+    let gesture = gtk::GestureClick::new();
+    gesture.connect_released(move |gesture, _n_press, x, y| {
+        let text_view = match gesture.widget().downcast::<gtk::TextView>() {
+            Ok(text_view) => text_view,
+            Err(_) => return,
+        };
+        let (x, y) =
+            text_view.window_to_buffer_coords(gtk::TextWindowType::Widget, x as i32, y as i32);
+        if let Some(time) = text_view
+            .iter_at_location(x, y)
+            .and_then(|iter| timestamp_at(&iter))
+        {
+            seek(time);
+        }
+    });
+    gesture
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_hours_minutes_seconds_and_tenths() {
+        assert_eq!(
+            format_timestamp(Duration::from_millis(3_723_456)),
+            "[01:02:03.4]"
+        );
+        assert_eq!(format_timestamp(Duration::ZERO), "[00:00:00.0]");
+    }
+
+    #[test]
+    fn parses_with_and_without_hours() {
+        assert_eq!(
+            parse_timestamp("01:02:03.4"),
+            Some(Duration::from_millis(3_723_400))
+        );
+        assert_eq!(parse_timestamp(" 12:34 "), Some(Duration::from_secs(754)));
+        assert_eq!(
+            parse_timestamp("100:00:00"),
+            Some(Duration::from_secs(360_000))
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_timestamps() {
+        for text in ["", "12", "1:2:3:4", "a:00", "00:60", "00:-1", "00:60:00"] {
+            assert_eq!(parse_timestamp(text), None, "{text}");
+        }
+    }
+
+    #[test]
+    fn formatted_timestamps_parse_to_the_tenth_of_a_second() {
+        for millis in [0, 100, 59_900, 61_234, 3_599_999, 36_000_000] {
+            let time = Duration::from_millis(millis);
+            let formatted = format_timestamp(time);
+            let parsed = parse_timestamp(&formatted[1..formatted.len() - 1]).unwrap();
+            assert_eq!(
+                parsed.as_millis(),
+                u128::from(millis / 100 * 100),
+                "{formatted}"
+            );
+        }
+    }
+
+    #[test]
+    fn finds_the_timestamp_under_the_cursor() {
+        let line: Vec<char> = "a [00:01.5] b [00:02] c".chars().collect();
+        let first = Some(Duration::from_millis(1_500));
+        assert_eq!(timestamp_in_line(&line, 2), first);
+        assert_eq!(timestamp_in_line(&line, 7), first);
+        assert_eq!(timestamp_in_line(&line, 10), first);
+        assert_eq!(timestamp_in_line(&line, 16), Some(Duration::from_secs(2)));
+        assert_eq!(timestamp_in_line(&line, 0), None);
+        assert_eq!(timestamp_in_line(&line, 12), None);
+        assert_eq!(timestamp_in_line(&line, line.len()), None);
+    }
+
+    #[test]
+    fn ignores_brackets_which_are_no_timestamp() {
+        let line: Vec<char> = "[inaudible] [00:03".chars().collect();
+        assert_eq!(timestamp_in_line(&line, 3), None);
+        assert_eq!(timestamp_in_line(&line, 15), None);
+    }
+}
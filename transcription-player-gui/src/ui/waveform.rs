@@ -0,0 +1,188 @@
+use std::{cell::RefCell, rc::Rc, sync::Arc, time::Duration};
+
+use gtk::{glib::clone, prelude::*};
+
+use crate::audio::{peaks::Peaks, LoopRegion};
+
+/// the color of the playhead and the loop region
+const ACCENT_COLOR: (f64, f64, f64) = (0.21, 0.52, 0.89);
+/// the color of the markers
+const MARKER_COLOR: (f64, f64, f64) = (0.96, 0.47, 0.0);
+
+#[derive(Default)]
+struct WaveformState {
+    peaks: Option<Arc<Peaks>>,
+    position: Duration,
+    duration: Option<Duration>,
+    loop_region: Option<LoopRegion>,
+    markers: Vec<Duration>,
+    /// the fraction of the duration the user is currently dragging the playhead to
+    drag_fraction: Option<f64>,
+    seek: Option<Box<dyn Fn(Duration)>>,
+}
+
+impl WaveformState {
+    fn fraction_of(&self, time: Duration) -> Option<f64> {
+        let duration = self.duration.filter(|duration| !duration.is_zero())?;
+        Some((time.as_secs_f64() / duration.as_secs_f64()).clamp(0.0, 1.0))
+    }
+}
+
+/// An overview of the whole audio file, with the playhead, the loop region and the markers.
+/// Clicking or dragging moves the playhead.
+#[derive(Clone)]
+pub struct Waveform {
+    area: gtk::DrawingArea,
+    state: Rc<RefCell<WaveformState>>,
+}
+
+impl Waveform {
+    pub fn new() -> Self {
+        let area = gtk::DrawingArea::builder()
+            .hexpand(true)
+            .height_request(80)
+            .build();
+        let state = Rc::new(RefCell::new(WaveformState::default()));
+
+        area.set_draw_func(clone!(@strong state => move |area, cr, width, height| {
+            draw(&state.borrow(), area, cr, width as f64, height as f64);
+        }));
+
+        let drag = gtk::GestureDrag::new();
+        drag.connect_drag_begin(clone!(@strong state, @weak area => move |_, x, _| {
+            state.borrow_mut().drag_fraction = Some(x / area.width() as f64);
+            area.queue_draw();
+        }));
+        drag.connect_drag_update(
+            clone!(@strong state, @weak area => move |drag, offset_x, _| {
+                if let Some((start_x, _)) = drag.start_point() {
+                    let fraction = (start_x + offset_x) / area.width() as f64;
+                    state.borrow_mut().drag_fraction = Some(fraction);
+                    area.queue_draw();
+                }
+            }),
+        );
+        // the playhead is only moved once the drag ends, since every seek flushes the buffers
+        drag.connect_drag_end(clone!(@strong state, @weak area => move |_, _, _| {
+            let mut state = state.borrow_mut();
+            if let (Some(fraction), Some(duration)) = (state.drag_fraction.take(), state.duration) {
+                let time = duration.mul_f64(fraction.clamp(0.0, 1.0));
+                state.position = time;
+                if let Some(seek) = &state.seek {
+                    seek(time);
+                }
+            }
+            area.queue_draw();
+        }));
+        area.add_controller(&drag);
+
+        Waveform { area, state }
+    }
+
+    pub fn widget(&self) -> &gtk::DrawingArea {
+        &self.area
+    }
+
+    /// Sets the function which is called with the new position when the user moves the
+    /// playhead.
+    pub fn connect_seek<F: Fn(Duration) + 'static>(&self, seek: F) {
+        self.state.borrow_mut().seek = Some(Box::new(seek));
+    }
+
+    pub fn set_peaks(&self, peaks: Option<Arc<Peaks>>) {
+        self.state.borrow_mut().peaks = peaks;
+        self.area.queue_draw();
+    }
+
+    pub fn set_position(&self, position: Duration, duration: Option<Duration>) {
+        let mut state = self.state.borrow_mut();
+        if state.position != position || state.duration != duration {
+            state.position = position;
+            state.duration = duration;
+            self.area.queue_draw();
+        }
+    }
+
+    pub fn set_loop_region(&self, loop_region: Option<LoopRegion>) {
+        let mut state = self.state.borrow_mut();
+        if state.loop_region != loop_region {
+            state.loop_region = loop_region;
+            self.area.queue_draw();
+        }
+    }
+
+    pub fn set_markers(&self, markers: Vec<Duration>) {
+        self.state.borrow_mut().markers = markers;
+        self.area.queue_draw();
+    }
+}
+
+fn draw(
+    state: &WaveformState,
+    area: &gtk::DrawingArea,
+    cr: &gtk::cairo::Context,
+    width: f64,
+    height: f64,
+) {
+    let foreground = area.style_context().color();
+    let (accent_r, accent_g, accent_b) = ACCENT_COLOR;
+
+    if let Some(loop_region) = state.loop_region {
+        if let (Some(start), Some(end)) = (
+            state.fraction_of(loop_region.start),
+            state.fraction_of(loop_region.end),
+        ) {
+            cr.set_source_rgba(accent_r, accent_g, accent_b, 0.2);
+            cr.rectangle(start * width, 0.0, (end - start) * width, height);
+            let _ = cr.fill();
+        }
+    }
+
+    let middle = height / 2.0;
+    cr.set_source_rgba(
+        foreground.red() as f64,
+        foreground.green() as f64,
+        foreground.blue() as f64,
+        0.7,
+    );
+    cr.set_line_width(1.0);
+    match &state.peaks {
+        Some(peaks) => {
+            for x in 0..width as usize {
+                let start = x as f64 / width;
+                let end = (x + 1) as f64 / width;
+                if let Some((min, max)) = peaks.range(start, end) {
+                    cr.move_to(x as f64 + 0.5, middle - max as f64 * middle);
+                    cr.line_to(x as f64 + 0.5, middle - min as f64 * middle + 1.0);
+                }
+            }
+        }
+        None => {
+            cr.move_to(0.0, middle);
+            cr.line_to(width, middle);
+        }
+    }
+    let _ = cr.stroke();
+
+    cr.set_source_rgb(MARKER_COLOR.0, MARKER_COLOR.1, MARKER_COLOR.2);
+    for marker in &state.markers {
+        if let Some(fraction) = state.fraction_of(*marker) {
+            let x = (fraction * width).round() + 0.5;
+            cr.move_to(x, 0.0);
+            cr.line_to(x, height);
+        }
+    }
+    let _ = cr.stroke();
+
+    let playhead = state
+        .drag_fraction
+        .or_else(|| state.fraction_of(state.position));
+    if let Some(fraction) = playhead {
+        let x = (fraction.clamp(0.0, 1.0) * width).round() + 0.5;
+        cr.set_source_rgb(accent_r, accent_g, accent_b);
+        cr.set_line_width(2.0);
+        cr.move_to(x, 0.0);
+        cr.line_to(x, height);
+        let _ = cr.stroke();
+    }
+}